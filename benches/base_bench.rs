@@ -4,8 +4,9 @@ use rewatch::build::clean;
 use rewatch::build::packages;
 use rewatch::helpers;
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::Path;
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("build-package-tree", |b| {
@@ -23,7 +24,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         let folder = "testrepo";
         let filename = "testrepo/packages/dep02/src/Dep02.res";
         // Clean the build
-        clean::clean(folder);
+        clean::clean(folder, None);
         // Read the file we'll be mutating
         let mut file = File::options()
             .read(true)
@@ -36,19 +37,66 @@ fn criterion_benchmark(c: &mut Criterion) {
 
         b.iter(|| {
             // Create initial build
-            let _ = build::build(&None, folder, false);
+            let _ = build::build(&None, folder, false, false, None);
             // Update the file
             let _ = writeln!(file, r#"let log2 = () => ["a", "b"]->forEach(Js.log);log2()"#);
             // Create another build
-            let _ = build::build(&None, folder, false);
+            let _ = build::build(&None, folder, false, false, None);
 
             // Reset state
             File::create(filename).unwrap();
             file.write_all(contents.as_bytes()).unwrap();
-            let _ = build::build(&None, folder, false);
+            let _ = build::build(&None, folder, false, false, None);
         })
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// A root package that depends on lots of tiny packages (one source folder, one module each)
+fn write_many_tiny_packages(root: &Path, count: usize) {
+    let dependencies = (0..count)
+        .map(|i| format!("\"tiny-{}\"", i))
+        .collect::<Vec<String>>()
+        .join(", ");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("bsconfig.json"),
+        format!(
+            r#"{{ "name": "root", "sources": "src", "bs-dependencies": [{}] }}"#,
+            dependencies
+        ),
+    )
+    .unwrap();
+    (0..count).for_each(|i| {
+        let package = root.join("node_modules").join(format!("tiny-{}", i));
+        fs::create_dir_all(package.join("src")).unwrap();
+        fs::write(
+            package.join("bsconfig.json"),
+            format!(r#"{{ "name": "tiny-{}", "sources": "src" }}"#, i),
+        )
+        .unwrap();
+        fs::write(package.join("src").join(format!("Tiny{}.res", i)), "let a = 1").unwrap();
+    });
+}
+
+fn scan_benchmark(c: &mut Criterion) {
+    let root = std::env::temp_dir().join(format!("rewatch-bench-tiny-packages-{}", std::process::id()));
+    write_many_tiny_packages(&root, 500);
+    let project_root = root.to_string_lossy().to_string();
+    let mut group = c.benchmark_group("many-tiny-packages");
+    // with a threshold of 0, no package is tiny, so every package is scanned on its own with its
+    // source folders in parallel (the old behaviour), instead of batching the tiny packages
+    for (name, threshold) in [("always-parallel", Some(0)), ("batched", None)] {
+        let options = packages::MakeOptions {
+            parallel_scan_threshold: threshold,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| packages::make_with(&None, &project_root, &None, &options).unwrap())
+        });
+    }
+    group.finish();
+    let _ = fs::remove_dir_all(&root);
+}
+
+criterion_group!(benches, criterion_benchmark, scan_benchmark);
 criterion_main!(benches);
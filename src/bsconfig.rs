@@ -1,17 +1,25 @@
 use crate::build::packages;
+use crate::helpers;
 use convert_case::{Case, Casing};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum OneOrMore<T> {
     Multiple(Vec<T>),
     Single(T),
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
+impl<T> Default for OneOrMore<T> {
+    fn default() -> Self {
+        OneOrMore::Multiple(vec![])
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Hash)]
 #[serde(untagged)]
 pub enum Subdirs {
     Qualified(Vec<Source>),
@@ -19,7 +27,7 @@ pub enum Subdirs {
 }
 impl Eq for Subdirs {}
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Hash)]
 pub struct PackageSource {
     pub dir: String,
     pub subdirs: Option<Subdirs>,
@@ -68,7 +76,7 @@ pub fn to_qualified_without_children(s: &Source, sub_path: Option<PathBuf>) -> P
 
 impl Eq for PackageSource {}
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Hash)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Hash)]
 #[serde(untagged)]
 pub enum Source {
     Shorthand(String),
@@ -76,41 +84,56 @@ pub enum Source {
 }
 impl Eq for Source {}
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct PackageSpec {
     pub module: String,
-    #[serde(rename = "in-source")]
+    // the JavaScript is written next to the sources, instead of in `lib/js` or `lib/es6`
+    #[serde(rename = "in-source", default)]
     pub in_source: bool,
     pub suffix: Option<String>,
+    // this is a new feature of rewatch, and it's not part of the bsconfig.json spec. The folder
+    // (relative to the package) the JavaScript is written to, instead of `lib/js` or `lib/es6`
+    #[serde(rename = "output-dir")]
+    pub output_dir: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Error {
     Catchall(bool),
     Qualified(String),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Warnings {
     pub number: Option<String>,
     pub error: Option<Error>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Reason {
     #[serde(rename = "react-jsx")]
     pub react_jsx: i32,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum NamespaceConfig {
     Bool(bool),
     String(String),
+    // an experimental form, like `{ "name": "Api", "entry": "Api" }`. It is not part of the
+    // bsconfig.json spec, so it is only tolerated: it works like `"namespace": "<name>"` (or
+    // `true` without a name) with `"namespace-entry": "<entry>"`
+    Object(NamespaceObject),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct NamespaceObject {
+    pub name: Option<String>,
+    pub entry: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum JsxMode {
     #[serde(rename = "classic")]
     Classic,
@@ -118,13 +141,34 @@ pub enum JsxMode {
     Automatic,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// The library the JSX is compiled for: React, or the module of another one (like preact or a
+/// custom renderer)
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(from = "String", into = "String")]
 pub enum JsxModule {
-    #[serde(rename = "react")]
     React,
+    Custom(String),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl From<String> for JsxModule {
+    fn from(module: String) -> Self {
+        match module.as_str() {
+            "react" => Self::React,
+            _ => Self::Custom(module),
+        }
+    }
+}
+
+impl From<JsxModule> for String {
+    fn from(module: JsxModule) -> Self {
+        match module {
+            JsxModule::React => "react".to_string(),
+            JsxModule::Custom(module) => module,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct JsxSpecs {
     pub version: Option<i32>,
     pub module: Option<JsxModule>,
@@ -133,9 +177,15 @@ pub struct JsxSpecs {
     pub v3_dependencies: Option<Vec<String>>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GenTypeConfig {
+    #[serde(rename = "generatedFileExtension")]
+    pub generated_file_extension: Option<String>,
+}
+
 /// # bsconfig.json representation
 /// This is tricky, there is a lot of ambiguity. This is probably incomplete.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 pub struct Config {
     pub name: String,
     pub sources: OneOrMore<Source>,
@@ -145,6 +195,7 @@ pub struct Config {
     pub suffix: Option<String>,
     #[serde(rename = "pinned-dependencies")]
     pub pinned_dependencies: Option<Vec<String>>,
+    // `None` when the field is absent, which is not the same as an empty list
     #[serde(rename = "bs-dependencies")]
     pub bs_dependencies: Option<Vec<String>>,
     #[serde(rename = "bs-dev-dependencies")]
@@ -154,15 +205,77 @@ pub struct Config {
     #[serde(rename = "bsc-flags")]
     pub bsc_flags: Option<Vec<OneOrMore<String>>>,
     pub reason: Option<Reason>,
+    // the version of refmt that legacy (bs-platform) projects format their Reason code with. It
+    // is not used, but unknown fields are ignored anyway, so this is just for migration tooling
+    pub refmt: Option<i32>,
     pub namespace: Option<NamespaceConfig>,
     pub jsx: Option<JsxSpecs>,
+    // the legacy top level forms of `jsx.mode` and `jsx.module`, these are only used when the
+    // jsx object doesn't configure them
+    #[serde(rename = "jsx-mode")]
+    pub jsx_mode: Option<JsxMode>,
+    #[serde(rename = "jsx-module")]
+    pub jsx_module: Option<JsxModule>,
     pub uncurried: Option<bool>,
+    pub gentypeconfig: Option<GenTypeConfig>,
     // this is a new feature of rewatch, and it's not part of the bsconfig.json spec
     #[serde(rename = "namespace-entry")]
     pub namespace_entry: Option<String>,
     // this is a new feature of rewatch, and it's not part of the bsconfig.json spec
     #[serde(rename = "allowed-dependents")]
     pub allowed_dependents: Option<Vec<String>>,
+    // this is a new feature of rewatch, and it's not part of the bsconfig.json spec. A (gzipped)
+    // tar archive, relative to the package, that the sources are scanned from instead of the disk
+    #[serde(rename = "sources-archive")]
+    pub sources_archive: Option<String>,
+    // all the fields we don't know about (like wrapper objects that other tooling adds), these
+    // are ignored, but kept around to detect typos
+    #[serde(flatten)]
+    pub unknown_fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What needs to happen for a change of a config, from least to most work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigChangeKind {
+    // nothing that affects the build changed (like formatting or unknown fields)
+    None,
+    // the compiler flags changed, so the modules of the package need to be recompiled
+    Recompile,
+    // the sources (or the module names) changed, so the package needs to be rescanned
+    Rescan,
+    // the dependencies changed, so the package tree needs to be rebuilt
+    Restructure,
+}
+
+/// Classifies the change from `old` to `new`, for the watcher to do the least amount of work.
+/// When multiple things changed, this is the kind that needs the most work.
+pub fn classify_config_change(old: &Config, new: &Config) -> ConfigChangeKind {
+    let restructure = old.name != new.name
+        || old.bs_dependencies != new.bs_dependencies
+        || old.bs_dev_dependencies != new.bs_dev_dependencies
+        || old.pinned_dependencies != new.pinned_dependencies
+        || old.allowed_dependents != new.allowed_dependents;
+    let rescan = old.sources != new.sources
+        || old.sources_archive != new.sources_archive
+        || old.gentypeconfig != new.gentypeconfig
+        || old.namespace != new.namespace
+        || old.namespace_entry != new.namespace_entry;
+    let recompile = old.package_specs != new.package_specs
+        || old.suffix != new.suffix
+        || old.warnings != new.warnings
+        || old.bsc_flags != new.bsc_flags
+        || old.ppx_flags != new.ppx_flags
+        || old.reason != new.reason
+        || old.jsx != new.jsx
+        || old.jsx_mode != new.jsx_mode
+        || old.jsx_module != new.jsx_module
+        || old.uncurried != new.uncurried;
+    match (restructure, rescan, recompile) {
+        (true, _, _) => ConfigChangeKind::Restructure,
+        (_, true, _) => ConfigChangeKind::Rescan,
+        (_, _, true) => ConfigChangeKind::Recompile,
+        _ => ConfigChangeKind::None,
+    }
 }
 
 /// This flattens string flags
@@ -228,14 +341,176 @@ pub fn flatten_ppx_flags(
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum ReadError {
+    ConfigRead {
+        path: String,
+        message: String,
+    },
+    // a json syntax error, with a few lines of the file around it
+    ConfigParse {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+        context: String,
+    },
+    // valid json, but not a valid config
+    ConfigInvalid {
+        path: String,
+        message: String,
+    },
+    ExtendsCycle {
+        path: String,
+    },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ConfigRead { path, message } => write!(f, "Could not read bsconfig. {path} - {message}"),
+            Self::ConfigParse {
+                path,
+                line,
+                column,
+                message,
+                context,
+            } => write!(
+                f,
+                "Could not parse bsconfig. {path}:{line}:{column} - {message}\n{context}"
+            ),
+            Self::ConfigInvalid { path, message } => {
+                write!(f, "Could not parse bsconfig. {path} - {message}")
+            }
+            Self::ExtendsCycle { path } => write!(f, "Could not read bsconfig. {path} - it extends itself"),
+        }
+    }
+}
+
+/// The lines around `line` (1-based), numbered, with the line itself marked
+fn get_error_context(contents: &str, line: usize) -> String {
+    let first = line.saturating_sub(3);
+    contents
+        .lines()
+        .enumerate()
+        .skip(first)
+        .take(line + 2 - first)
+        .map(|(index, text)| {
+            let marker = if index + 1 == line { ">" } else { " " };
+            format!("{} {:>4} | {}", marker, index + 1, text)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 /// Try to convert a bsconfig from a certain path to a bsconfig struct
 pub fn read(path: String) -> Config {
-    fs::read_to_string(path.clone())
-        .map_err(|e| format!("Could not read bsconfig. {path} - {e}"))
-        .and_then(|x| {
-            serde_json::from_str::<Config>(&x).map_err(|e| format!("Could not parse bsconfig. {path} - {e}"))
-        })
-        .expect("Errors reading bsconfig")
+    try_read(&path).unwrap_or_else(|e| panic!("Errors reading bsconfig: {e}"))
+}
+
+pub fn try_read(path: &str) -> Result<Config, ReadError> {
+    read_with_extends(Path::new(path), &mut vec![]).and_then(|x| to_config(x, path))
+}
+
+/// Parses config `contents` that are already in memory (like an unsaved editor buffer), as if
+/// they were read from `origin_path`. The `"extends"` is resolved relative to that path, so the
+/// file itself doesn't need to exist.
+pub fn parse(contents: &str, origin_path: &str) -> Result<Config, ReadError> {
+    let mut visited = vec![];
+    if let Ok(canonical_path) = Path::new(origin_path).canonicalize() {
+        visited.push(canonical_path);
+    }
+    parse_with_extends(contents, Path::new(origin_path), &mut visited).and_then(|x| to_config(x, origin_path))
+}
+
+fn to_config(mut value: serde_json::Value, path: &str) -> Result<Config, ReadError> {
+    // the name and the sources are required, except in a placeholder config without either
+    // (like `{}`), which parses as a trivial config, see `is_trivial`
+    if let Some(object) = value.as_object_mut() {
+        if !object.contains_key("name") && !object.contains_key("sources") {
+            object.insert("name".to_string(), serde_json::Value::String(String::new()));
+            object.insert("sources".to_string(), serde_json::Value::Array(vec![]));
+        }
+    }
+    serde_json::from_value::<Config>(value).map_err(|e| ReadError::ConfigInvalid {
+        path: path.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Reads the config at `path` as json, with the config it `"extends"` (a path relative to the
+/// config) merged in. `visited` holds the configs that are being read, to detect cycles.
+fn read_with_extends(path: &Path, visited: &mut Vec<PathBuf>) -> Result<serde_json::Value, ReadError> {
+    let display_path = path.to_string_lossy().to_string();
+    let read_error = |e: std::io::Error| ReadError::ConfigRead {
+        path: display_path.to_owned(),
+        message: e.to_string(),
+    };
+    let canonical_path = path.canonicalize().map_err(read_error)?;
+    if visited.contains(&canonical_path) {
+        return Err(ReadError::ExtendsCycle { path: display_path });
+    }
+    visited.push(canonical_path);
+
+    let contents = fs::read_to_string(path).map_err(read_error)?;
+    parse_with_extends(&contents, path, visited)
+}
+
+/// Parses the `contents` of the config at `path` as json, with the config it `"extends"` merged in
+fn parse_with_extends(
+    contents: &str,
+    path: &Path,
+    visited: &mut Vec<PathBuf>,
+) -> Result<serde_json::Value, ReadError> {
+    let display_path = path.to_string_lossy().to_string();
+    let mut config =
+        serde_json::from_str::<serde_json::Value>(contents).map_err(|e| ReadError::ConfigParse {
+            path: display_path.to_owned(),
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+            context: get_error_context(contents, e.line()),
+        })?;
+
+    match config.as_object_mut().and_then(|config| config.remove("extends")) {
+        None => Ok(config),
+        Some(serde_json::Value::String(extends)) => {
+            let base_path = path.parent().unwrap_or(Path::new("")).join(extends);
+            let mut base = read_with_extends(&base_path, visited)?;
+            merge_json(&mut base, config);
+            Ok(base)
+        }
+        Some(_) => Err(ReadError::ConfigInvalid {
+            path: display_path,
+            message: "\"extends\" should be a path".to_string(),
+        }),
+    }
+}
+
+/// Deep merges `child` into `base`: objects are merged key by key, anything else (including
+/// arrays) in the child replaces the value of the base.
+fn merge_json(base: &mut serde_json::Value, child: serde_json::Value) {
+    match (base, child) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(child)) => {
+            for (key, value) in child {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_json(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, child) => *base = child,
+    }
+}
+
+/// The config fields that a compiler version doesn't support yet, with the first major version
+/// that does. These are silently ignored (or rejected) by older compilers.
+const VERSIONED_FIELDS: [(&str, usize); 1] = [("uncurried", 11)];
+
+fn major_version(version: &str) -> Option<usize> {
+    version.trim_start_matches('v').split('.').next()?.parse().ok()
 }
 
 fn check_if_rescript11_or_higher(version: &str) -> bool {
@@ -250,11 +525,27 @@ fn namespace_from_package_name(package_name: &str) -> String {
         .to_case(Case::Pascal)
 }
 
+/// The namespace is derived from the package name, so the name needs to turn into a valid module
+/// name (for instance it can't start with a digit or contain spaces).
+pub fn is_valid_package_name(package_name: &str) -> bool {
+    let namespace = namespace_from_package_name(package_name);
+    !package_name.chars().any(char::is_whitespace)
+        && !namespace.is_empty()
+        && helpers::is_non_exotic_module_name(&namespace)
+}
+
 impl Config {
     pub fn get_namespace(&self) -> packages::Namespace {
         let namespace_from_package = namespace_from_package_name(&self.name);
         match (self.namespace.as_ref(), self.namespace_entry.as_ref()) {
             (Some(NamespaceConfig::Bool(false)), _) => packages::Namespace::NoNamespace,
+            // a package without a name has nothing to derive a namespace from
+            (Some(NamespaceConfig::Bool(true)), _) if namespace_from_package.is_empty() => {
+                packages::Namespace::NoNamespace
+            }
+            (Some(NamespaceConfig::String(str)), _) if str == "true" && namespace_from_package.is_empty() => {
+                packages::Namespace::NoNamespace
+            }
             (None, _) => packages::Namespace::NoNamespace,
             (Some(NamespaceConfig::Bool(true)), None) => {
                 packages::Namespace::Namespace(namespace_from_package)
@@ -284,6 +575,15 @@ impl Config {
                     entry: entry.to_string(),
                 },
             },
+            (Some(NamespaceConfig::Object(object)), entry) => Config {
+                namespace: Some(match &object.name {
+                    Some(name) => NamespaceConfig::String(name.to_owned()),
+                    None => NamespaceConfig::Bool(true),
+                }),
+                namespace_entry: object.entry.to_owned().or(entry.cloned()),
+                ..self.to_owned()
+            }
+            .get_namespace(),
         }
     }
     pub fn get_jsx_args(&self) -> Vec<String> {
@@ -302,31 +602,44 @@ impl Config {
         }
     }
 
-    pub fn get_jsx_mode_args(&self) -> Vec<String> {
-        match self.jsx.to_owned() {
-            Some(jsx) => match jsx.mode {
-                Some(JsxMode::Classic) => {
-                    vec!["-bs-jsx-mode".to_string(), "classic".to_string()]
-                }
-                Some(JsxMode::Automatic) => {
-                    vec!["-bs-jsx-mode".to_string(), "automatic".to_string()]
-                }
+    /// The configured JSX mode, from the jsx object or the legacy top level field
+    pub fn get_jsx_mode(&self) -> Option<JsxMode> {
+        self.jsx
+            .as_ref()
+            .and_then(|jsx| jsx.mode.to_owned())
+            .or(self.jsx_mode.to_owned())
+    }
 
-                None => vec![],
-            },
-            _ => vec![],
+    pub fn get_jsx_mode_args(&self) -> Vec<String> {
+        match self.get_jsx_mode() {
+            Some(JsxMode::Classic) => {
+                vec!["-bs-jsx-mode".to_string(), "classic".to_string()]
+            }
+            Some(JsxMode::Automatic) => {
+                vec!["-bs-jsx-mode".to_string(), "automatic".to_string()]
+            }
+            None => vec![],
         }
     }
 
+    /// The configured JSX module, from the jsx object or the legacy top level field
+    pub fn get_configured_jsx_module(&self) -> Option<JsxModule> {
+        self.jsx
+            .as_ref()
+            .and_then(|jsx| jsx.module.to_owned())
+            .or(self.jsx_module.to_owned())
+    }
+
+    /// The library the JSX is compiled for, React when nothing else is configured
+    pub fn get_jsx_module(&self) -> JsxModule {
+        self.get_configured_jsx_module().unwrap_or(JsxModule::React)
+    }
+
+    /// The compiler only needs the module when it is configured, it defaults to React itself
     pub fn get_jsx_module_args(&self) -> Vec<String> {
-        match self.jsx.to_owned() {
-            Some(jsx) => match jsx.module {
-                Some(JsxModule::React) => {
-                    vec!["-bs-jsx-module".to_string(), "react".to_string()]
-                }
-                None => vec![],
-            },
-            _ => vec![],
+        match self.get_configured_jsx_module() {
+            Some(module) => vec!["-bs-jsx-module".to_string(), module.into()],
+            None => vec![],
         }
     }
 
@@ -342,6 +655,24 @@ impl Config {
         }
     }
 
+    /// The fields that are set in this config, but are too new for the compiler `version`, with
+    /// the first major version that supports them. Empty when the version can't be parsed.
+    pub fn unsupported_fields(&self, version: &str) -> Vec<(&'static str, usize)> {
+        let Some(major) = major_version(version) else {
+            return vec![];
+        };
+        VERSIONED_FIELDS
+            .into_iter()
+            .filter(|(field, since)| {
+                major < *since
+                    && match *field {
+                        "uncurried" => self.uncurried.is_some(),
+                        _ => false,
+                    }
+            })
+            .collect()
+    }
+
     pub fn get_module(&self) -> String {
         match &self.package_specs {
             Some(OneOrMore::Single(PackageSpec { module, .. })) => module.to_string(),
@@ -353,7 +684,70 @@ impl Config {
         }
     }
 
+    /// Whether the (first) package spec writes the JavaScript next to the sources
+    pub fn get_in_source(&self) -> bool {
+        match &self.package_specs {
+            Some(OneOrMore::Single(PackageSpec { in_source, .. })) => *in_source,
+            Some(OneOrMore::Multiple(vec)) => vec.first().map(|spec| spec.in_source).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// The output folder of the (first) package spec, if it configures one
+    pub fn get_output_dir(&self) -> Option<String> {
+        match &self.package_specs {
+            Some(OneOrMore::Single(PackageSpec { output_dir, .. })) => output_dir.to_owned(),
+            Some(OneOrMore::Multiple(vec)) => vec.first().and_then(|spec| spec.output_dir.to_owned()),
+            None => None,
+        }
+    }
+
+    /// The folder (relative to the package) the JavaScript is written to: the configured
+    /// `output-dir`, the package itself (`""`) when it is in-source, and otherwise `lib/es6` for
+    /// es6 modules and `lib/js` for the rest.
+    pub fn get_js_output_dir(&self) -> String {
+        match self.get_output_dir() {
+            Some(output_dir) => output_dir,
+            None if self.get_in_source() => "".to_string(),
+            None if self.get_module().starts_with("es6") => "lib/es6".to_string(),
+            None => "lib/js".to_string(),
+        }
+    }
+
     pub fn get_suffix(&self) -> String {
+        self.get_configured_suffix().unwrap_or(".js".to_string())
+    }
+
+    /// Whether the config has `"sources": []`, which is intentional for a package that only
+    /// aggregates other packages (through its dependencies), and has no sources of its own
+    pub fn has_empty_sources(&self) -> bool {
+        matches!(&self.sources, OneOrMore::Multiple(sources) if sources.is_empty())
+    }
+
+    /// Whether the config has neither a name nor any sources, like a placeholder `{}`
+    pub fn is_trivial(&self) -> bool {
+        self.name.trim().is_empty() && self.has_empty_sources()
+    }
+
+    /// The extensions of the files genType generates next to the sources. These are never
+    /// sources themselves.
+    pub fn get_gentype_extensions(&self) -> Vec<String> {
+        match self
+            .gentypeconfig
+            .as_ref()
+            .and_then(|gentypeconfig| gentypeconfig.generated_file_extension.to_owned())
+        {
+            Some(extension) => vec![extension],
+            None => vec![
+                ".gen.tsx".to_string(),
+                ".gen.ts".to_string(),
+                ".gen.js".to_string(),
+            ],
+        }
+    }
+
+    /// The suffix from the package-specs, or the top level suffix, if either is set
+    pub fn get_configured_suffix(&self) -> Option<String> {
         match &self.package_specs {
             Some(OneOrMore::Single(PackageSpec { suffix, .. })) => suffix.to_owned(),
             Some(OneOrMore::Multiple(vec)) => match vec.first() {
@@ -364,13 +758,33 @@ impl Config {
             _ => None,
         }
         .or(self.suffix.to_owned())
-        .unwrap_or(".js".to_string())
+    }
+
+    /// Unknown fields that look like a misspelled dependency field (like `bsDependencies`), when
+    /// that field is absent, as `(unknown field, dependency field)`.
+    pub fn get_misspelled_dependency_fields(&self) -> Vec<(String, String)> {
+        let normalize = |field: &str| field.to_lowercase().replace(['-', '_'], "");
+        [
+            ("bs-dependencies", self.bs_dependencies.is_none()),
+            ("bs-dev-dependencies", self.bs_dev_dependencies.is_none()),
+            ("pinned-dependencies", self.pinned_dependencies.is_none()),
+        ]
+        .into_iter()
+        .filter(|(_field, is_absent)| *is_absent)
+        .flat_map(|(field, _is_absent)| {
+            self.unknown_fields
+                .keys()
+                .filter(move |unknown_field| normalize(unknown_field) == normalize(field))
+                .map(move |unknown_field| (unknown_field.to_owned(), field.to_string()))
+        })
+        .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::Fixture;
 
     #[test]
     fn test_getters() {
@@ -389,4 +803,258 @@ mod tests {
         assert_eq!(config.get_suffix(), ".mjs");
         assert_eq!(config.get_module(), "es6");
     }
+
+    #[test]
+    fn test_in_source() {
+        let config = |package_specs: &str| {
+            serde_json::from_str::<Config>(&format!(
+                r#"{{ "name": "app", "sources": "src", "package-specs": {} }}"#,
+                package_specs
+            ))
+            .unwrap()
+        };
+
+        assert!(config(r#"{ "module": "es6", "in-source": true }"#).get_in_source());
+        assert!(!config(r#"{ "module": "es6" }"#).get_in_source());
+        assert!(
+            config(r#"[ { "module": "es6", "in-source": true }, { "module": "commonjs" } ]"#).get_in_source()
+        );
+        assert!(
+            !config(r#"[ { "module": "commonjs" }, { "module": "es6", "in-source": true } ]"#)
+                .get_in_source()
+        );
+        assert!(!config("[]").get_in_source());
+    }
+
+    #[test]
+    fn test_legacy_config() {
+        let json = r#"
+        {
+            "name": "legacy-app",
+            "version": "0.1.0",
+            "sources": { "dir": "src", "subdirs": true },
+            "refmt": 3,
+            "reason": { "react-jsx": 2 },
+            "generate-merlin": true,
+            "bs-external-includes": [],
+            "js-post-build": { "cmd": "./post-build.sh" },
+            "bs-dependencies": [ "reason-react" ]
+        }
+        "#;
+
+        let config = serde_json::from_str::<Config>(json).unwrap();
+        assert_eq!(config.refmt, Some(3));
+        assert_eq!(config.reason.unwrap().react_jsx, 2);
+        assert_eq!(config.bs_dependencies, Some(vec!["reason-react".to_string()]));
+    }
+
+    #[test]
+    fn test_package_name_validation() {
+        assert!(is_valid_package_name("@teamwalnut/app"));
+        assert!(is_valid_package_name("my-package"));
+        assert!(!is_valid_package_name("my package"));
+        assert!(!is_valid_package_name("1password-ui"));
+    }
+
+    #[test]
+    fn test_extends_chain() {
+        let fixture = Fixture::new("bsconfig-extends");
+        fixture
+            .write(
+                "config/base.json",
+                r#"{ "suffix": ".mjs", "warnings": { "number": "-44" }, "bsc-flags": ["-open Belt"] }"#,
+            )
+            .write(
+                "config/shared.json",
+                r#"{ "extends": "./base.json", "warnings": { "error": true }, "package-specs": { "module": "es6", "in-source": true } }"#,
+            )
+            .write(
+                "bsconfig.json",
+                r#"{ "extends": "./config/shared.json", "name": "app", "sources": "src", "bsc-flags": ["-open Js"] }"#,
+            );
+
+        let config = read(fixture.path("bsconfig.json"));
+
+        assert_eq!(config.name, "app");
+        assert_eq!(config.get_suffix(), ".mjs");
+        assert_eq!(config.get_module(), "es6");
+        let warnings = config.warnings.unwrap();
+        assert_eq!(warnings.number, Some("-44".to_string()));
+        assert!(matches!(warnings.error, Some(Error::Catchall(true))));
+        assert_eq!(flatten_flags(&config.bsc_flags), vec!["-open", "Js"]);
+    }
+
+    #[test]
+    fn test_jsx_module() {
+        let config = |json: &str| serde_json::from_str::<Config>(json).unwrap();
+
+        let custom =
+            config(r#"{ "name": "app", "sources": "src", "jsx": { "version": 4, "module": "Preact" } }"#);
+        assert_eq!(custom.get_jsx_module(), JsxModule::Custom("Preact".to_string()));
+        assert_eq!(custom.get_jsx_module_args(), vec!["-bs-jsx-module", "Preact"]);
+
+        let legacy =
+            config(r#"{ "name": "app", "sources": "src", "jsx-module": "react", "jsx-mode": "classic" }"#);
+        assert_eq!(legacy.get_jsx_module(), JsxModule::React);
+        assert_eq!(legacy.get_jsx_module_args(), vec!["-bs-jsx-module", "react"]);
+        assert_eq!(legacy.get_jsx_mode_args(), vec!["-bs-jsx-mode", "classic"]);
+
+        let default = config(r#"{ "name": "app", "sources": "src", "jsx": { "version": 4 } }"#);
+        assert_eq!(default.get_jsx_module(), JsxModule::React);
+        assert!(default.get_jsx_module_args().is_empty());
+    }
+
+    #[test]
+    fn test_namespace_object() {
+        let config = |namespace: &str| {
+            serde_json::from_str::<Config>(&format!(
+                r#"{{ "name": "my-app", "sources": "src", "namespace": {} }}"#,
+                namespace
+            ))
+            .unwrap()
+            .get_namespace()
+        };
+
+        assert!(matches!(
+            config(r#"{ "entry": "Api" }"#),
+            packages::Namespace::NamespaceWithEntry { namespace, entry } if namespace == "MyApp" && entry == "Api"
+        ));
+        assert!(matches!(
+            config(r#"{ "name": "Server" }"#),
+            packages::Namespace::Namespace(namespace) if namespace == "Server"
+        ));
+        assert!(matches!(
+            config(r#"{ "unknown": 1 }"#),
+            packages::Namespace::Namespace(namespace) if namespace == "MyApp"
+        ));
+    }
+
+    #[test]
+    fn test_trivial_config() {
+        let config = parse("{}", "bsconfig.json").unwrap();
+        assert!(config.is_trivial());
+        assert!(!parse(r#"{ "name": "app", "sources": [] }"#, "bsconfig.json")
+            .unwrap()
+            .is_trivial());
+        assert!(!parse(r#"{ "name": "", "sources": "src" }"#, "bsconfig.json")
+            .unwrap()
+            .is_trivial());
+        // only a config without either is a placeholder, a missing field is still an error
+        assert!(matches!(
+            parse(r#"{ "name": "app" }"#, "bsconfig.json"),
+            Err(ReadError::ConfigInvalid { .. })
+        ));
+        assert!(matches!(
+            parse(r#"{ "sources": "src" }"#, "bsconfig.json"),
+            Err(ReadError::ConfigInvalid { .. })
+        ));
+        let namespaced = parse(r#"{ "namespace": true }"#, "bsconfig.json").unwrap();
+        assert!(matches!(
+            namespaced.get_namespace(),
+            packages::Namespace::NoNamespace
+        ));
+    }
+
+    #[test]
+    fn test_parse_with_extends_relative_to_origin() {
+        let fixture = Fixture::new("bsconfig-parse");
+        fixture.write(
+            "packages/app/base.json",
+            r#"{ "suffix": ".mjs", "uncurried": false }"#,
+        );
+
+        // the config itself is only in memory, it doesn't exist on disk
+        let config = parse(
+            r#"{ "extends": "./base.json", "name": "app", "sources": "src" }"#,
+            &fixture.path("packages/app/bsconfig.json"),
+        )
+        .unwrap();
+
+        assert_eq!(config.name, "app");
+        assert_eq!(config.get_suffix(), ".mjs");
+        assert_eq!(config.uncurried, Some(false));
+        assert!(matches!(
+            parse(r#"{ "name": "app", "sources": "src", }"#, "bsconfig.json"),
+            Err(ReadError::ConfigParse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_extends_cycle() {
+        let fixture = Fixture::new("bsconfig-extends-cycle");
+        fixture
+            .write("base.json", r#"{ "extends": "./bsconfig.json" }"#)
+            .write(
+                "bsconfig.json",
+                r#"{ "extends": "./base.json", "name": "app", "sources": "src" }"#,
+            );
+
+        let error = try_read(&fixture.path("bsconfig.json")).unwrap_err();
+        assert!(matches!(error, ReadError::ExtendsCycle { .. }));
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let fixture = Fixture::new("bsconfig-parse-error");
+        fixture.write(
+            "bsconfig.json",
+            "{\n  \"name\": \"app\",\n  \"sources\": \"src\"\n  \"suffix\": \".mjs\"\n}\n",
+        );
+
+        match try_read(&fixture.path("bsconfig.json")) {
+            Err(ReadError::ConfigParse {
+                line,
+                column,
+                context,
+                ..
+            }) => {
+                assert_eq!(line, 4);
+                assert_eq!(column, 3);
+                assert!(context.contains(">    4 |   \"suffix\": \".mjs\""));
+                assert!(context.contains("     3 |   \"sources\": \"src\""));
+            }
+            result => panic!("Expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_classify_config_change() {
+        let config = |fields: &str| {
+            serde_json::from_str::<Config>(&format!(r#"{{ "name": "app", "sources": "src"{} }}"#, fields))
+                .unwrap()
+        };
+        let old = config(r#", "bsc-flags": ["-w a"], "bs-dependencies": ["dep"]"#);
+        let classify = |fields: &str| classify_config_change(&old, &config(fields));
+
+        assert_eq!(
+            classify(r#", "bsc-flags": ["-w a"], "bs-dependencies": ["dep"], "unknown": 1"#),
+            ConfigChangeKind::None
+        );
+        assert_eq!(
+            classify(r#", "bsc-flags": ["-w b"], "bs-dependencies": ["dep"]"#),
+            ConfigChangeKind::Recompile
+        );
+        assert_eq!(
+            classify(r#", "bsc-flags": ["-w a"], "bs-dependencies": ["dep"], "namespace": true"#),
+            ConfigChangeKind::Rescan
+        );
+        assert_eq!(
+            classify_config_change(
+                &old,
+                &serde_json::from_str::<Config>(
+                    r#"{ "name": "app", "sources": "lib", "bsc-flags": ["-w a"], "bs-dependencies": ["dep"] }"#
+                )
+                .unwrap()
+            ),
+            ConfigChangeKind::Rescan
+        );
+        assert_eq!(
+            classify(r#", "bsc-flags": ["-w a"], "bs-dependencies": ["dep", "other"]"#),
+            ConfigChangeKind::Restructure
+        );
+        assert_eq!(
+            classify(r#", "bsc-flags": ["-w b"], "bs-dependencies": [], "namespace": true"#),
+            ConfigChangeKind::Restructure
+        );
+    }
 }
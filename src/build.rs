@@ -2,7 +2,9 @@ pub mod build_types;
 pub mod clean;
 pub mod compile;
 pub mod deps;
+pub mod diagnostics;
 pub mod logs;
+pub mod module_name;
 pub mod namespaces;
 pub mod packages;
 pub mod parse;
@@ -100,6 +102,7 @@ pub fn get_compiler_args(path: &str, rescript_version: Option<String>, bsc_path:
     };
     let compiler_args = compiler_args(
         &rescript_config,
+        &rescript_config.get_namespace(),
         &root_rescript_config,
         &ast_path,
         &rescript_version,
@@ -120,6 +123,7 @@ pub fn get_compiler_args(path: &str, rescript_version: Option<String>, bsc_path:
 #[derive(Debug, Clone)]
 pub enum InitializeBuildError {
     PackageDependencyValidation,
+    MissingSourceMetadata(packages::MissingSourceMetadata),
 }
 
 impl fmt::Display for InitializeBuildError {
@@ -130,6 +134,7 @@ impl fmt::Display for InitializeBuildError {
                 "{}  {}Could not Validate Package Dependencies",
                 LINE_CLEAR, CROSS,
             ),
+            Self::MissingSourceMetadata(e) => write!(f, "{}  {}{}", LINE_CLEAR, CROSS, e),
         }
     }
 }
@@ -185,7 +190,7 @@ pub fn initialize_build(
         rescript_version,
         bsc_path,
     );
-    packages::parse_packages(&mut build_state);
+    packages::parse_packages(&mut build_state).map_err(InitializeBuildError::MissingSourceMetadata)?;
     let timing_source_files_elapsed = timing_source_files.elapsed();
     println!(
         "{}{} {}Found source files in {:.2}s",
@@ -26,9 +26,17 @@ fn remove_iast(package: &packages::Package, source_file: &str) {
     ));
 }
 
-fn remove_mjs_file(source_file: &str, suffix: &String) {
+// the JavaScript of a source file (relative to the package) is in the output dir of the package
+fn get_mjs_file_location(package: &packages::Package, source_file: &str) -> String {
+    std::path::PathBuf::from(package.output_dir())
+        .join(source_file)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn remove_mjs_file(mjs_file_location: &str, suffix: &String) {
     let _ = std::fs::remove_file(helpers::change_extension(
-        source_file,
+        mjs_file_location,
         // suffix.to_string includes the ., so we need to remove it
         &suffix.to_string()[1..],
     ));
@@ -70,10 +78,7 @@ pub fn clean_mjs_files(build_state: &BuildState) {
                     .get(&build_state.root_config_name)
                     .expect("Could not find root package");
                 Some((
-                    std::path::PathBuf::from(package.path.to_string())
-                        .join(&source_file.implementation.path)
-                        .to_string_lossy()
-                        .to_string(),
+                    get_mjs_file_location(package, &source_file.implementation.path),
                     root_package.bsconfig.get_suffix(),
                 ))
             }
@@ -125,7 +130,11 @@ pub fn cleanup_previous_build(
                 .get(package_name)
                 .expect("Could not find package");
             remove_compile_assets(package, res_file_location);
-            remove_mjs_file(res_file_location, &suffix);
+            let relative_location = std::path::Path::new(res_file_location)
+                .strip_prefix(&package.path)
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or(res_file_location.to_string());
+            remove_mjs_file(&get_mjs_file_location(package, &relative_location), &suffix);
             remove_iast(package, res_file_location);
             remove_ast(package, res_file_location);
             match helpers::get_extension(ast_file_path).as_str() {
@@ -378,7 +387,10 @@ pub fn clean(path: &str, bsc_path: Option<String>) {
         rescript_version,
         bsc_path,
     );
-    packages::parse_packages(&mut build_state);
+    if let Err(e) = packages::parse_packages(&mut build_state) {
+        println!("{}", e);
+        return;
+    }
     clean_mjs_files(&build_state);
     let timing_clean_mjs_elapsed = timing_clean_mjs.elapsed();
     println!(
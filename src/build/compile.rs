@@ -365,6 +365,8 @@ pub fn compile(
 
 pub fn compiler_args(
     config: &bsconfig::Config,
+    // the namespace of the package in the tree, which can differ from the one in its config
+    namespace: &packages::Namespace,
     root_config: &bsconfig::Config,
     ast_path: &str,
     version: &str,
@@ -392,40 +394,34 @@ pub fn compiler_args(
     let deps = vec![normal_deps]
         .concat()
         .par_iter()
-        .map(|package_name| {
+        .filter_map(|package_name| {
             let canonicalized_path = if let Some(packages) = packages {
-                packages
-                    .get(package_name)
-                    .expect("expect package")
-                    .path
-                    .to_string()
+                // a dependency that isn't in the tree is reported when the tree is made
+                packages.get(package_name)?.path.to_string()
             } else {
                 packages::read_dependency(package_name, project_root, project_root, workspace_root)
                     .expect("cannot find dep")
             };
-            vec!["-I".to_string(), packages::get_build_path(&canonicalized_path)]
+            Some(vec![
+                "-I".to_string(),
+                packages::get_build_path(&canonicalized_path),
+            ])
         })
         .collect::<Vec<Vec<String>>>();
 
-    let module_name = helpers::file_path_to_module_name(file_path, &config.get_namespace());
+    let module_name = helpers::file_path_to_module_name(file_path, namespace);
 
-    let namespace_args = match &config.get_namespace() {
+    let namespace_args = match namespace {
         packages::Namespace::NamespaceWithEntry { namespace: _, entry } if &module_name == entry => {
             // if the module is the entry we just want to open the namespace
-            vec![
-                "-open".to_string(),
-                config.get_namespace().to_suffix().unwrap().to_string(),
-            ]
+            vec!["-open".to_string(), namespace.to_suffix().unwrap().to_string()]
         }
         packages::Namespace::Namespace(_)
         | packages::Namespace::NamespaceWithEntry {
             namespace: _,
             entry: _,
         } => {
-            vec![
-                "-bs-ns".to_string(),
-                config.get_namespace().to_suffix().unwrap().to_string(),
-            ]
+            vec!["-bs-ns".to_string(), namespace.to_suffix().unwrap().to_string()]
         }
         packages::Namespace::NoNamespace => vec![],
     };
@@ -536,6 +532,7 @@ fn compile_file(
     let has_interface = module.get_interface().is_some();
     let to_mjs_args = compiler_args(
         &package.bsconfig,
+        &package.namespace,
         &root_package.bsconfig,
         ast_path,
         version,
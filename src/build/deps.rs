@@ -1,5 +1,6 @@
 use super::build_types::*;
 use super::is_dirty;
+use super::module_name::ModuleName;
 use super::packages;
 use crate::helpers;
 use ahash::AHashSet;
@@ -8,7 +9,7 @@ use rayon::prelude::*;
 fn get_dep_modules(
     ast_file: &str,
     namespace: Option<String>,
-    package_modules: &AHashSet<String>,
+    package_modules: &AHashSet<ModuleName>,
     valid_modules: &AHashSet<String>,
 ) -> AHashSet<String> {
     let mut deps = AHashSet::new();
@@ -44,7 +45,8 @@ fn get_dep_modules(
                         _ => dep_first,
                     };
                     let namespaced_name = dep.to_owned() + "-" + namespace;
-                    if package_modules.contains(&namespaced_name) || valid_modules.contains(&namespaced_name)
+                    if package_modules.contains(namespaced_name.as_str())
+                        || valid_modules.contains(&namespaced_name)
                     {
                         namespaced_name
                     } else {
@@ -0,0 +1,149 @@
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    // only produced with `MakeOptions::trace`
+    Trace,
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Trace => write!(f, "trace"),
+            Self::Info => write!(f, "info"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticKind {
+    IgnoredPackage,
+    InvalidPackageName,
+    EmptyDependencies,
+    NonUtf8Path,
+    UnresolvedDependency,
+    InvalidNamespace,
+    UnreadableSourceArchive,
+    DuplicatePackagePath,
+    InvalidModuleName,
+    ResolvedDependency,
+    MisspelledField,
+    SkippedDevDependency,
+    NestedPackageSource,
+    UnreferencedSourceFile,
+    SourceIsFile,
+    SourceTooLarge,
+    DeepSourceTree,
+    TrivialConfig,
+    CircularDependency,
+    DuplicateModule,
+    CaseCollision,
+    EmptyPackage,
+    NamespaceCollision,
+    UnreferencedPackage,
+    Timeout,
+    NamespaceMismatch,
+    IncompatibleConfig,
+    SymlinkDepthExceeded,
+}
+
+/// Something noteworthy that was found while building the package tree. Diagnostics are collected
+/// instead of printed, so that the caller can decide what to do with them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    // the path of the package (or file) the diagnostic is about
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, kind: DiagnosticKind, path: &str, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            path: path.to_string(),
+            message,
+        }
+    }
+
+    pub fn trace(kind: DiagnosticKind, path: &str, message: String) -> Self {
+        Self::new(Severity::Trace, kind, path, message)
+    }
+
+    pub fn info(kind: DiagnosticKind, path: &str, message: String) -> Self {
+        Self::new(Severity::Info, kind, path, message)
+    }
+
+    pub fn warning(kind: DiagnosticKind, path: &str, message: String) -> Self {
+        Self::new(Severity::Warning, kind, path, message)
+    }
+
+    pub fn error(kind: DiagnosticKind, path: &str, message: String) -> Self {
+        Self::new(Severity::Error, kind, path, message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.severity, self.message, self.path)
+    }
+}
+
+/// Collects diagnostics from multiple (rayon) threads. The order in which they are pushed is not
+/// deterministic, so they are sorted when taken out, by path, then severity, then message.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Mutex<Vec<Diagnostic>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, diagnostic: Diagnostic) {
+        self.diagnostics.lock().unwrap().push(diagnostic);
+    }
+
+    pub fn extend(&self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        self.diagnostics.lock().unwrap().extend(diagnostics);
+    }
+
+    pub fn into_sorted(self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.diagnostics.into_inner().unwrap();
+        sort(&mut diagnostics);
+        diagnostics
+    }
+}
+
+pub fn sort(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| {
+        (&a.path, a.severity, &a.message, a.kind).cmp(&(&b.path, b.severity, &b.message, b.kind))
+    });
+}
+
+/// The number of diagnostics of every severity that occurs, from the least to the most severe,
+/// for a summary line
+pub fn severity_counts(diagnostics: &[Diagnostic]) -> Vec<(Severity, usize)> {
+    let mut counts: Vec<(Severity, usize)> = vec![];
+    let mut severities = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.severity)
+        .collect::<Vec<Severity>>();
+    severities.sort();
+    severities
+        .into_iter()
+        .for_each(|severity| match counts.last_mut() {
+            Some((last, count)) if *last == severity => *count += 1,
+            _ => counts.push((severity, 1)),
+        });
+    counts
+}
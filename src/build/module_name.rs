@@ -0,0 +1,118 @@
+use super::packages::Namespace;
+use crate::helpers;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+
+/// The name of a module, as the compiler knows it: the capitalized basename of the source file,
+/// suffixed with `-<Namespace>` when the package is namespaced. It is constructed from a valid
+/// name, so a file path can't be mistaken for a module name. The only exception is `unchecked`.
+///
+/// A cached tree is read back as is, without checking the names, as it can have `unchecked` ones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModuleName(String);
+
+impl ModuleName {
+    /// Checks a module name, like `Foo`, `Foo-Namespace` or `@Namespace` (the module of a namespace
+    /// with an entry).
+    pub fn new(name: &str) -> Result<Self, String> {
+        let without_at = name.strip_prefix('@').unwrap_or(name);
+        let is_valid = without_at.split('-').all(|part| {
+            let mut chars = part.chars();
+            chars.next().is_some_and(|c| c.is_ascii_uppercase())
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'')
+        });
+        if is_valid {
+            Ok(Self(name.to_string()))
+        } else {
+            Err(format!("\"{}\" is not a valid module name", name))
+        }
+    }
+
+    /// The name of the module of a source file (like `helpers::file_path_to_module_name`)
+    pub fn from_file_path(path: &str, namespace: &Namespace) -> Result<Self, String> {
+        Self::new(&helpers::file_path_to_module_name(path, namespace))
+            .map_err(|e| format!("The file {} does not have a valid module name: {}", path, e))
+    }
+
+    /// A name that isn't checked, for a source file with an invalid name. The file is still built
+    /// (the compiler reports what is wrong with it), instead of silently skipping it.
+    pub fn unchecked(name: String) -> Self {
+        Self(name)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ModuleName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ModuleName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// so sets of module names can be queried with a `&str`
+impl Borrow<str> for ModuleName {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<&str> for ModuleName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl TryFrom<String> for ModuleName {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::new(&name)
+    }
+}
+
+impl From<ModuleName> for String {
+    fn from(module_name: ModuleName) -> Self {
+        module_name.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleName;
+    use crate::build::packages::Namespace;
+
+    #[test]
+    fn should_accept_valid_module_names() {
+        assert_eq!(
+            ModuleName::from_file_path("src/foo_bar.res", &Namespace::NoNamespace).unwrap(),
+            "Foo_bar"
+        );
+        assert_eq!(
+            ModuleName::from_file_path("src/Foo.resi", &Namespace::Namespace("App".to_string()))
+                .unwrap()
+                .to_string(),
+            "Foo-App"
+        );
+        assert!(ModuleName::new("@App").is_ok());
+        assert!(ModuleName::new("Foo'").is_ok());
+    }
+
+    #[test]
+    fn should_reject_invalid_module_names() {
+        assert!(ModuleName::from_file_path("src/1foo.res", &Namespace::NoNamespace).is_err());
+        assert!(ModuleName::from_file_path("src/foo bar.res", &Namespace::NoNamespace).is_err());
+        assert!(ModuleName::new("").is_err());
+        assert!(ModuleName::new("Foo-").is_err());
+        assert!(ModuleName::new("src/Foo").is_err());
+    }
+}
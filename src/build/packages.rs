@@ -1,25 +1,42 @@
 use super::build_types::*;
+use super::compile;
+use super::diagnostics::{self, Diagnostic, DiagnosticKind, Diagnostics, Severity};
+use super::module_name::ModuleName;
 use super::namespaces;
 use super::packages;
+use super::parse;
 use crate::bsconfig;
 use crate::helpers;
 use crate::helpers::emojis::*;
 use ahash::{AHashMap, AHashSet};
 use console::style;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use log::{debug, error};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use source_reader::{DeadlineReader, DiskReader, SharedReader, SourceReader, TarReader};
 use std::error;
-use std::fs::{self};
+use std::fmt;
+use std::fs;
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
-#[derive(Debug, Clone)]
+pub mod shared_tree;
+pub mod source_reader;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceFileMeta {
     pub modified: SystemTime,
+    // the size in bytes, with the modification time this tells whether a cached file changed
+    pub len: u64,
+    // matches one of the `MakeOptions::test_file_patterns`
+    #[serde(default)]
+    pub is_test: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Namespace {
     Namespace(String),
     NamespaceWithEntry { namespace: String, entry: String },
@@ -38,27 +55,250 @@ impl Namespace {
 
 #[derive(Debug, Clone)]
 struct Dependency {
+    // the name from its config, or as it is configured when it couldn't be read
     name: String,
     bsconfig: bsconfig::Config,
     path: String,
     is_pinned: bool,
+    is_ignored: bool,
+    // configured as a path (like `"../shared"`) instead of a name
+    is_path: bool,
+    // why the dependency could not be found, and whether it is a peer dependency of its parent
+    unresolved: Option<(String, bool)>,
     dependencies: Vec<Dependency>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
     pub bsconfig: bsconfig::Config,
     pub source_folders: AHashSet<bsconfig::PackageSource>,
     // these are the relative file paths (relative to the package root)
     pub source_files: Option<AHashMap<String, SourceFileMeta>>,
+    // only the paths of the source files, instead of `source_files`, when the tree was built with
+    // `MakeOptions::drop_source_metadata`
+    #[serde(default)]
+    pub source_paths: Option<AHashSet<String>>,
     pub namespace: Namespace,
-    pub modules: Option<AHashSet<String>>,
+    pub modules: Option<AHashSet<ModuleName>>,
     // canonicalized dir of the package
     pub path: String,
     pub dirs: Option<AHashSet<PathBuf>>,
     pub is_pinned_dep: bool,
     pub is_root: bool,
+    // ignored packages are recorded in the tree, but their config is never read and their
+    // sources are never scanned
+    pub is_ignored: bool,
+    // local packages live outside of node_modules (workspace packages, `file:` dependencies),
+    // so they are editable and can change while we are running
+    pub is_local: bool,
+    // the `rescript` package itself, see `MakeOptions::include_runtime`
+    pub is_runtime: bool,
+    // what the package looked like on disk when the tree was saved, see `save_tree`
+    #[serde(default)]
+    pub cache_stamp: Option<CacheStamp>,
+}
+
+/// Options for building the package tree with `make_with`
+#[derive(Debug, Clone, Default)]
+pub struct MakeOptions {
+    /// Names of dependencies that should not be read or scanned (for instance a huge vendored
+    /// package that isn't compiled).
+    pub ignore_packages: Vec<String>,
+    /// Only keep the paths of the source files (in `Package::source_paths`) once the modules are
+    /// computed, for callers that only care about the paths. The build itself needs the metadata
+    /// (see `MissingSourceMetadata`).
+    pub drop_source_metadata: bool,
+    /// Fail when there is a diagnostic of (at least) this severity, for instance to promote
+    /// warnings to errors on CI. `None` never fails.
+    pub fail_on: Option<Severity>,
+    /// Resolves the folder of a dependency, for build systems that do their own module
+    /// resolution. When it returns `None`, the dependency is looked up in node_modules.
+    pub resolve_dependency: Option<DependencyResolver>,
+    /// Also read the `bs-dev-dependencies` of the root package (and their dependencies), and
+    /// record them as dev edges in `PackageTree::edges`.
+    pub include_dev: bool,
+    /// Only keep the source files with one of these paths (for instance the output of
+    /// `git diff --name-only`), so the modules are only the ones that changed. The packages are
+    /// still all resolved. Relative paths are relative to the root folder.
+    pub changed_paths: Option<AHashSet<PathBuf>>,
+    /// Add a `Severity::Trace` diagnostic for every dependency that is resolved, with where it
+    /// was found, to debug a tree that doesn't look as expected.
+    pub trace: bool,
+    /// Skip the `bs-dependencies` of the root package that are only a `devDependency` in its
+    /// package.json, to build exactly what ships. They are kept when `include_dev` is set.
+    pub production_only: bool,
+    /// Prefix the namespace of the local and pinned packages that are namespaced, so `MyApp`
+    /// becomes `<prefix>_MyApp`. Third party packages keep their namespace.
+    pub force_namespace_prefix: Option<String>,
+    /// Which packages have their `"type": "dev"` sources scanned. By default none do.
+    pub dev_sources: DevSourcePolicy,
+    /// Leave the files of a package that is nested in a source folder of another package out of
+    /// the sources of that other package (see `validate_nested_packages`).
+    pub prune_nested_packages: bool,
+    /// Extra (project specific) checks, that run on the built tree after the built-in ones.
+    /// Their diagnostics are part of the result (and count for `fail_on`).
+    pub validators: Vec<Validator>,
+    /// Also add the `rescript` package with the modules of the runtime (`Belt`, `Js`, ...), which
+    /// are available without declaring a dependency, to get a complete index of the modules.
+    /// This is not for building: the runtime is compiled already.
+    pub include_runtime: bool,
+    /// A package with fewer source folders than this is scanned in a batch with other such
+    /// packages, its source folders one by one, instead of on its own with its source folders in
+    /// parallel. Defaults to `DEFAULT_PARALLEL_SCAN_THRESHOLD`.
+    pub parallel_scan_threshold: Option<usize>,
+    /// Also scan the folders of the local packages for source files that are not in any of their
+    /// source folders (see `validate_unreferenced_source_files`), which are silently not built.
+    pub report_unreferenced_sources: bool,
+    /// The folder dependencies are installed in, instead of `node_modules` (for CI systems that
+    /// install them somewhere else). Relative to the root folder. It is looked in before the
+    /// regular resolution, and the packages found there are third party (not local).
+    pub node_modules_dir: Option<String>,
+    /// Like node, look for the dependencies that can't be found otherwise in the folders of the
+    /// `NODE_PATH` environment variable. The packages found there are third party (not local).
+    pub use_node_path: bool,
+    /// The number of files and folders a single source folder can have (recursively), scanning
+    /// it stops when there are more, so a recursive source that is configured too high up (like
+    /// the root of a repository) doesn't hang. Defaults to `DEFAULT_MAX_SOURCE_ENTRIES`.
+    pub max_source_entries: Option<usize>,
+    /// How deeply the folders of a recursive source can be nested before that is reported (as a
+    /// `DeepSourceTree` diagnostic), as it usually means that something like `node_modules` or
+    /// build output is scanned by accident. Defaults to `DEFAULT_MAX_SOURCE_DEPTH`.
+    pub max_source_depth: Option<usize>,
+    /// How many symlinked folders deep a recursive source is followed, so a symlink loop (or a
+    /// pathological chain of them) doesn't take forever. The symlinked folders beyond it are
+    /// skipped, and reported as `SymlinkDepthExceeded`. Defaults to `DEFAULT_MAX_SYMLINK_DEPTH`.
+    pub max_symlink_depth: Option<usize>,
+    /// Stop scanning the packages when this has passed, for editors that rather show part of the
+    /// tree quickly than wait for all of it. The packages that weren't completely scanned by then
+    /// are left out of the tree, and listed in a `Timeout` diagnostic.
+    pub deadline: Option<Instant>,
+    /// Reads the source folders instead of the disk (the packages with a `sources-archive` are
+    /// still read from their archive).
+    pub source_reader: Option<SharedReader>,
+    /// Fail on the first dependency that can't be found (`MakeError::MissingDependency`), instead
+    /// of reporting it and building the tree without it. Missing peer dependencies are still fine.
+    pub strict_dependencies: bool,
+    /// Warn about config fields that the installed compiler (see `PackageTree::compiler_version`)
+    /// doesn't support yet, like `uncurried` before v11 (as `IncompatibleConfig` diagnostics).
+    pub check_compiler_compatibility: bool,
+    /// Globs (like `*_test.res`) for the test files that are next to the other sources, relative
+    /// to the package (`*` also matches across folders). These are tagged `SourceFileMeta::is_test`,
+    /// and like dev sources they are only kept for the packages that have their dev sources
+    /// scanned (see `dev_sources`), so they are left out of a production build.
+    pub test_file_patterns: Vec<String>,
+}
+
+pub const DEFAULT_PARALLEL_SCAN_THRESHOLD: usize = 4;
+
+// the number of tiny packages (see `MakeOptions::parallel_scan_threshold`) scanned in one task
+const SCAN_BATCH_SIZE: usize = 16;
+
+pub const DEFAULT_MAX_SOURCE_ENTRIES: usize = 100_000;
+
+pub const DEFAULT_MAX_SOURCE_DEPTH: usize = 10;
+
+pub const DEFAULT_MAX_SYMLINK_DEPTH: usize = 8;
+
+pub type Validate = dyn Fn(&AHashMap<String, Package>) -> Vec<Diagnostic> + Send + Sync;
+
+#[derive(Clone)]
+pub struct Validator(pub Arc<Validate>);
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator")
+    }
+}
+
+/// Whether the dev sources are scanned, for the root package and for the dependencies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DevSourcePolicy {
+    pub root: bool,
+    pub deps: bool,
+}
+
+/// Called with the name of a dependency, and the folder of the package that depends on it
+pub type ResolveDependency = dyn Fn(&str, &str) -> Option<String> + Send + Sync;
+
+#[derive(Clone)]
+pub struct DependencyResolver(pub Arc<ResolveDependency>);
+
+impl fmt::Debug for DependencyResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DependencyResolver")
+    }
+}
+
+/// The result of `make_with`: the packages keyed by name, and the diagnostics that were
+/// collected while building the tree (in a stable order).
+#[derive(Debug, Clone)]
+pub struct PackageTree {
+    pub packages: AHashMap<String, Package>,
+    pub diagnostics: Vec<Diagnostic>,
+    // the dependency graph, see `dependency_edges`
+    pub edges: Vec<DependencyEdge>,
+    // what ships and what is in the dev tree, see `closures`
+    pub closures: Closures,
+    // the version of the installed `rescript` package, when it can be found
+    pub compiler_version: Option<String>,
+    // what the tree was built with, so it is extended the same way (see `add_dependency`)
+    pub filter: Option<regex::Regex>,
+    pub options: MakeOptions,
+    pub workspace_root: Option<String>,
+}
+
+/// The paths of the packages that can be reached from the root package, through the production
+/// (normal and peer) dependencies only, and through all dependencies (including dev). Dev
+/// dependencies are only in the tree with `MakeOptions::include_dev`, without it these are equal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Closures {
+    pub production: AHashSet<String>,
+    pub full: AHashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DependencyKind {
+    // declared in `bs-dependencies`
+    Normal,
+    // declared in `bs-dev-dependencies`
+    Dev,
+    // declared in `bs-dependencies`, and listed in the `peerDependencies` of the package.json of
+    // the package, so it is provided by whoever depends on the package
+    Peer,
+}
+
+/// A dependency of package `from` on package `to` (by name)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DependencyEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: DependencyKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum MakeError {
+    // there were diagnostics of at least `MakeOptions::fail_on`, these are all the diagnostics
+    FailedOnDiagnostics(Vec<Diagnostic>),
+    // a dependency was not found with `MakeOptions::strict_dependencies`, the chain goes from the
+    // root package to the missing dependency
+    MissingDependency { chain: Vec<String>, message: String },
+}
+
+impl fmt::Display for MakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FailedOnDiagnostics(diagnostics) => {
+                write!(f, "Building the package tree failed on diagnostics:")?;
+                diagnostics
+                    .iter()
+                    .try_for_each(|diagnostic| write!(f, "\n{}", diagnostic))
+            }
+            Self::MissingDependency { chain, message } => {
+                write!(f, "Missing dependency ({}): {}", chain.join(" -> "), message)
+            }
+        }
+    }
 }
 
 pub fn get_build_path(canonical_path: &str) -> String {
@@ -101,6 +341,133 @@ impl Package {
     pub fn get_iast_path(&self, source_file: &str) -> String {
         helpers::get_compiler_asset(self, &packages::Namespace::NoNamespace, source_file, "iast")
     }
+
+    /// The paths of the source files (relative to the package), whether or not their metadata was
+    /// kept (see `MakeOptions::drop_source_metadata`)
+    pub fn source_file_paths(&self) -> Vec<&String> {
+        match (&self.source_files, &self.source_paths) {
+            (Some(source_files), _) => source_files.keys().collect(),
+            (None, Some(source_paths)) => source_paths.iter().collect(),
+            (None, None) => vec![],
+        }
+    }
+
+    /// The number of source folders, after flattening the qualified subdirs
+    pub fn source_folder_count(&self) -> usize {
+        self.source_folders.len()
+    }
+
+    /// The (sorted) source folders that are configured with `"subdirs": true`, so everything below
+    /// them gets scanned. A recursive source high up in the package is the usual suspect for a
+    /// slow scan.
+    pub fn recursive_source_folders(&self) -> Vec<String> {
+        let mut dirs = self
+            .source_folders
+            .iter()
+            .filter(|source| source.subdirs == Some(bsconfig::Subdirs::Recurse(true)))
+            .map(|source| source.dir.to_owned())
+            .collect::<Vec<String>>();
+        dirs.sort();
+        dirs
+    }
+
+    /// Derives the modules from the source files and the namespace, for instance after changing
+    /// the namespace. This doesn't touch the disk. Returns the warnings for the source files that
+    /// don't result in a valid module name. Those are still in the modules, so they are built, and
+    /// the compiler reports what is wrong with them.
+    pub fn recompute_modules(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut modules = AHashSet::from_iter(self.source_file_paths().into_iter().map(|path| {
+            ModuleName::from_file_path(path, &self.namespace).unwrap_or_else(|e| {
+                diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::InvalidModuleName,
+                    &Path::new(&self.path).join(path).to_string_lossy(),
+                    e,
+                ));
+                ModuleName::unchecked(helpers::file_path_to_module_name(path, &self.namespace))
+            })
+        }));
+        // an invalid namespace is reported by `validate_namespaces`
+        let namespace_module = match self.namespace.to_owned() {
+            Namespace::Namespace(namespace) => Some(namespace),
+            Namespace::NamespaceWithEntry { namespace, entry: _ } => Some("@".to_string() + &namespace),
+            Namespace::NoNamespace => None,
+        };
+        if let Some(Ok(namespace_module)) = namespace_module.map(|module| ModuleName::new(&module)) {
+            let _ = modules.insert(namespace_module);
+        }
+        self.modules = Some(modules);
+        diagnostics
+    }
+
+    /// Whether any of the source folders is configured with `"subdirs": true`
+    pub fn has_recursive_sources(&self) -> bool {
+        !self.recursive_source_folders().is_empty()
+    }
+
+    /// The compiler binary this package is compiled with: the `bsc.exe` of the nearest rescript
+    /// package (from the package itself up), falling back to the one of the root package.
+    pub fn bsc_path(&self, build: &AHashMap<String, Package>) -> Option<String> {
+        helpers::find_bsc(Path::new(&self.path))
+            .or_else(|| {
+                build
+                    .values()
+                    .filter(|package| package.is_root)
+                    .find_map(|root| helpers::find_bsc(Path::new(&root.path)))
+            })
+            .map(|path| path.to_string_lossy().to_string())
+    }
+
+    /// The packages in `build` that the `bs-dependencies` of this package resolved to, in the
+    /// order they are declared, and an error diagnostic for every dependency that isn't in the
+    /// tree. A dependency matches both by the name it was installed as and by the name in its
+    /// config, so if these differ, all matches are returned.
+    pub fn direct_dependencies<'a>(
+        &self,
+        build: &'a AHashMap<String, Package>,
+    ) -> (Vec<&'a Package>, Vec<Diagnostic>) {
+        let mut dependencies: Vec<&'a Package> = vec![];
+        let mut diagnostics = vec![];
+        for dependency_name in self.bsconfig.bs_dependencies.as_ref().unwrap_or(&vec![]) {
+            let mut matches = build
+                .iter()
+                .filter(|(key, package)| *key == dependency_name || &package.name == dependency_name)
+                .map(|(_key, package)| package)
+                .collect::<Vec<&Package>>();
+            if matches.is_empty() {
+                diagnostics.push(Diagnostic::error(
+                    DiagnosticKind::UnresolvedDependency,
+                    &self.path,
+                    format!(
+                        "The dependency \"{}\" of package {} is not in the tree",
+                        dependency_name, self.name
+                    ),
+                ));
+            }
+            matches.sort_by(|a, b| a.path.cmp(&b.path));
+            dependencies.extend(matches);
+        }
+        (dependencies, diagnostics)
+    }
+
+    /// The `lib/bs` folders bsc needs as `-I` to compile a module of this package: its own,
+    /// followed by the ones of its transitive `bs-dependencies`, breadth first, so the direct
+    /// dependencies come first (in the order they are declared). Every folder is in it once.
+    pub fn include_paths(&self, build: &AHashMap<String, Package>) -> Vec<String> {
+        let mut include_paths = vec![self.get_bs_build_path()];
+        let mut visited: AHashSet<String> = AHashSet::from_iter([self.path.to_owned()]);
+        let mut queue = std::collections::VecDeque::from([self]);
+        while let Some(package) = queue.pop_front() {
+            let (dependencies, _unresolved) = package.direct_dependencies(build);
+            dependencies.into_iter().for_each(|dependency| {
+                if visited.insert(dependency.path.to_owned()) {
+                    include_paths.push(dependency.get_bs_build_path());
+                    queue.push_back(dependency);
+                }
+            });
+        }
+        include_paths
+    }
 }
 
 impl PartialEq for Package {
@@ -122,54 +489,109 @@ fn matches_filter(filter: &Option<regex::Regex>, path: &str) -> bool {
     }
 }
 
+/// The error of `read_folders` when a source folder has more entries than it is allowed to
+#[derive(Debug)]
+pub struct SourceTooLarge;
+
+impl fmt::Display for SourceTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The source folder has too many files")
+    }
+}
+
+impl error::Error for SourceTooLarge {}
+
+/// Reads the source files in `path`, and its subfolders when `recurse` is set. Every file and
+/// folder takes one of the `remaining_entries`, when there are none left, this fails with
+/// `SourceTooLarge`. The most deeply nested folder that is read is kept in `deepest_folder`.
+/// Symlinked folders are only followed `remaining_symlinks` deep (a `SymlinkDepthExceeded`
+/// warning is reported beyond that), so a symlink loop doesn't get scanned over and over.
+#[allow(clippy::too_many_arguments)]
 pub fn read_folders(
     filter: &Option<regex::Regex>,
+    ignore: &Gitignore,
+    reader: &dyn SourceReader,
     package_dir: &Path,
     path: &Path,
     recurse: bool,
+    remaining_entries: &mut usize,
+    remaining_symlinks: usize,
+    deepest_folder: &mut PathBuf,
+    diagnostics: &Diagnostics,
 ) -> Result<AHashMap<String, SourceFileMeta>, Box<dyn error::Error>> {
     let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
     let path_buf = PathBuf::from(path);
-    let meta = fs::metadata(package_dir.join(path));
-    let path_with_meta = meta.map(|meta| {
-        (
-            path.to_owned(),
-            SourceFileMeta {
-                modified: meta.modified().unwrap(),
-            },
-        )
-    });
+    if path_buf.components().count() > deepest_folder.components().count() {
+        *deepest_folder = path_buf.to_owned();
+    }
 
-    for entry in fs::read_dir(package_dir.join(&path_buf))? {
-        let entry_path_buf = entry.map(|entry| entry.path())?;
-        let metadata = fs::metadata(&entry_path_buf)?;
-        let name = entry_path_buf.file_name().unwrap().to_str().unwrap().to_string();
+    for entry in reader.read_dir(package_dir, &path_buf)? {
+        *remaining_entries = remaining_entries.checked_sub(1).ok_or(SourceTooLarge)?;
+        let entry_path_buf = package_dir.join(&path_buf).join(&entry.name);
+        let name = match entry.name.to_str() {
+            Some(name) => name.to_string(),
+            // module names (and everything downstream) are strings, so we can't build these
+            None => {
+                diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::NonUtf8Path,
+                    &entry_path_buf.to_string_lossy(),
+                    "Skipped a file or folder with a name that is not valid UTF-8".to_string(),
+                ));
+                continue;
+            }
+        };
+
+        if ignore.matched(&entry_path_buf, entry.is_dir).is_ignore() {
+            continue;
+        }
 
         let path_ext = entry_path_buf.extension().and_then(|x| x.to_str());
         let new_path = path_buf.join(&name);
-        if metadata.file_type().is_dir() && recurse {
-            match read_folders(filter, package_dir, &new_path, recurse) {
-                Ok(s) => map.extend(s),
-                Err(e) => println!("Error reading directory: {}", e),
+        if entry.is_dir && recurse {
+            let remaining_symlinks = match entry.is_symlink {
+                true => remaining_symlinks.checked_sub(1),
+                false => Some(remaining_symlinks),
+            };
+            match remaining_symlinks {
+                None => diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::SymlinkDepthExceeded,
+                    &entry_path_buf.to_string_lossy(),
+                    format!(
+                        "Did not follow the symlinked folder \"{}\", it is behind too many other symlinked folders (is there a symlink loop?)",
+                        new_path.to_string_lossy()
+                    ),
+                )),
+                Some(remaining_symlinks) => match read_folders(
+                    filter,
+                    ignore,
+                    reader,
+                    package_dir,
+                    &new_path,
+                    recurse,
+                    remaining_entries,
+                    remaining_symlinks,
+                    deepest_folder,
+                    diagnostics,
+                ) {
+                    Ok(s) => map.extend(s),
+                    Err(e) if e.is::<SourceTooLarge>() || source_reader::is_timeout(e.as_ref()) => return Err(e),
+                    Err(e) => println!("Error reading directory: {}", e),
+                },
             }
         }
 
         match path_ext {
-            Some(extension) if helpers::is_source_file(extension) => match path_with_meta {
-                Ok((ref path, _)) if matches_filter(filter, &name) => {
-                    let mut path = path.to_owned();
-                    path.push(&name);
-                    map.insert(
-                        path.to_string_lossy().to_string(),
-                        SourceFileMeta {
-                            modified: metadata.modified().unwrap(),
-                        },
-                    );
-                }
-
-                Ok(_) => println!("Filtered: {:?}", name),
-                Err(ref e) => println!("Error reading directory: {}", e),
-            },
+            Some(extension) if helpers::is_source_file(extension) && matches_filter(filter, &name) => {
+                map.insert(
+                    new_path.to_string_lossy().to_string(),
+                    SourceFileMeta {
+                        modified: entry.modified,
+                        len: entry.len,
+                        is_test: false,
+                    },
+                );
+            }
+            Some(extension) if helpers::is_source_file(extension) => println!("Filtered: {:?}", name),
             _ => (),
         }
     }
@@ -214,7 +636,125 @@ fn get_source_dirs(source: bsconfig::Source, sub_path: Option<PathBuf>) -> AHash
     source_folders
 }
 
-pub fn read_bsconfig(package_dir: &str) -> bsconfig::Config {
+/// An absolute source dir (as generated configs can have) is made relative to the package when it
+/// is inside of it, so the source files are keyed the same as for a relative dir. An absolute dir
+/// outside of the package is kept as is, joining the package path onto it leaves it untouched.
+fn relative_source_dir(package_path: &Path, mut source: bsconfig::PackageSource) -> bsconfig::PackageSource {
+    let dir = Path::new(&source.dir);
+    if dir.is_absolute() {
+        let dir = dir.canonicalize().unwrap_or(dir.to_path_buf());
+        if let Ok(relative) = dir.strip_prefix(package_path) {
+            source.dir = match relative.as_os_str().is_empty() {
+                true => ".".to_string(),
+                false => relative.to_string_lossy().to_string(),
+            };
+        }
+    }
+    source
+}
+
+/// `path` relative to `base` (both absolute), going up with `..` where they diverge
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    base.components()
+        .skip(common)
+        .map(|_component| std::path::Component::ParentDir)
+        .chain(path.components().skip(common))
+        .collect()
+}
+
+/// A source dir with a glob (like `"src/*"`) is a source for every folder in the package that
+/// matches it, with the same settings. `*` doesn't match across folders, `**` does (but never
+/// into `node_modules` or hidden folders). A dir without a glob is kept as is.
+fn expand_source_glob(package_path: &Path, source: bsconfig::PackageSource) -> Vec<bsconfig::PackageSource> {
+    if !source.dir.contains(['*', '?', '[', '{']) {
+        return vec![source];
+    }
+    let matcher = match globset::GlobBuilder::new(&source.dir)
+        .literal_separator(true)
+        .build()
+    {
+        Ok(glob) => glob.compile_matcher(),
+        Err(e) => {
+            error!("Invalid glob in source dir \"{}\": {}", source.dir, e);
+            return vec![];
+        }
+    };
+    // without `**`, a match can't be nested deeper than the pattern
+    let max_depth = match source.dir.contains("**") {
+        true => None,
+        false => Some(Path::new(&source.dir).components().count()),
+    };
+    let mut dirs = vec![];
+    glob_source_dirs(package_path, Path::new(""), &matcher, max_depth, &mut dirs);
+    dirs.sort();
+    dirs.into_iter()
+        .map(|dir| bsconfig::PackageSource {
+            dir: dir.to_string_lossy().to_string(),
+            ..source.to_owned()
+        })
+        .collect()
+}
+
+fn glob_source_dirs(
+    package_path: &Path,
+    dir: &Path,
+    matcher: &globset::GlobMatcher,
+    max_depth: Option<usize>,
+    dirs: &mut Vec<PathBuf>,
+) {
+    if max_depth.is_some_and(|max_depth| dir.components().count() >= max_depth) {
+        return;
+    }
+    let entries = match fs::read_dir(package_path.join(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') && name != "node_modules")
+        .for_each(|name| {
+            let path = dir.join(name);
+            if matcher.is_match(&path) {
+                dirs.push(path.to_owned());
+            }
+            glob_source_dirs(package_path, &path, matcher, max_depth, dirs);
+        });
+}
+
+/// Makes sure there is only one source folder per dir, when the same dir is configured multiple
+/// times with different settings. A non-dev source wins over a dev source (which isn't scanned),
+/// and then a recursive source over a non-recursive one, so no sources go missing.
+fn merge_source_folders(
+    source_folders: AHashSet<bsconfig::PackageSource>,
+) -> AHashSet<bsconfig::PackageSource> {
+    let precedence = |source: &bsconfig::PackageSource| {
+        (
+            source.type_ != Some("dev".to_string()),
+            source.subdirs == Some(bsconfig::Subdirs::Recurse(true)),
+        )
+    };
+    let mut by_dir: AHashMap<String, bsconfig::PackageSource> = AHashMap::new();
+    source_folders.into_iter().for_each(|source| {
+        match by_dir.get(&source.dir) {
+            Some(current) if precedence(current) >= precedence(&source) => (),
+            _ => {
+                by_dir.insert(source.dir.to_owned(), source);
+            }
+        };
+    });
+    by_dir.into_values().collect()
+}
+
+/// The path of the config of the package in `package_dir`: `rescript.json` when it exists,
+/// `bsconfig.json` otherwise.
+pub fn get_bsconfig_path(package_dir: &str) -> String {
     let prefix = if package_dir.is_empty() {
         "".to_string()
     } else {
@@ -225,19 +765,42 @@ pub fn read_bsconfig(package_dir: &str) -> bsconfig::Config {
     let bsconfig_json_path = prefix.to_string() + "bsconfig.json";
 
     if Path::new(&rescript_json_path).exists() {
-        bsconfig::read(rescript_json_path)
+        rescript_json_path
     } else {
-        bsconfig::read(bsconfig_json_path)
+        bsconfig_json_path
     }
 }
 
+pub fn read_bsconfig(package_dir: &str) -> bsconfig::Config {
+    bsconfig::read(get_bsconfig_path(package_dir))
+}
+
 pub fn read_dependency(
     package_name: &str,
     parent_path: &str,
     project_root: &str,
     workspace_root: &Option<String>,
 ) -> Result<String, String> {
+    // a dependency on a path is relative to the package that depends on it
+    if helpers::is_path_dependency(package_name) {
+        let path = Path::new(parent_path).join(package_name);
+        return path
+            .canonicalize()
+            .map(|canonical_path| canonical_path.to_string_lossy().to_string())
+            .map_err(|e| {
+                format!(
+                    "The package \"{}\" is not found at \"{}\"...\nMore details: {}",
+                    package_name,
+                    path.to_string_lossy(),
+                    e
+                )
+            });
+    }
     let path_from_parent = PathBuf::from(helpers::package_path(parent_path, package_name));
+    // a parent in the pnpm store finds its dependencies next to it in the store, and not (only)
+    // in the project root, which only has the direct dependencies of the project
+    let maybe_path_from_pnpm_store = helpers::get_pnpm_store_node_modules(Path::new(parent_path))
+        .map(|node_modules| node_modules.join(package_name));
     let path_from_project_root = PathBuf::from(helpers::package_path(project_root, package_name));
     let maybe_path_from_workspace_root = workspace_root
         .as_ref()
@@ -245,15 +808,20 @@ pub fn read_dependency(
 
     let path = match (
         path_from_parent,
+        maybe_path_from_pnpm_store,
         path_from_project_root,
         maybe_path_from_workspace_root,
     ) {
-        (path_from_parent, _, _) if path_from_parent.exists() => Ok(path_from_parent),
-        (_, path_from_project_root, _) if path_from_project_root.exists() => Ok(path_from_project_root),
-        (_, _, Some(path_from_workspace_root)) if path_from_workspace_root.exists() => {
+        (path_from_parent, _, _, _) if path_from_parent.exists() => Ok(path_from_parent),
+        (_, Some(path_from_pnpm_store), _, _) if path_from_pnpm_store.exists() => Ok(path_from_pnpm_store),
+        (_, _, path_from_project_root, _) if path_from_project_root.exists() => Ok(path_from_project_root),
+        (_, _, _, Some(path_from_workspace_root)) if path_from_workspace_root.exists() => {
             Ok(path_from_workspace_root)
         }
-        _ => Err(format!(
+        // when the parent is itself installed in a node_modules folder (for instance when we
+        // start building from a dependency), its dependencies live in one of the ancestor
+        // node_modules folders
+        _ => helpers::find_package_in_ancestors(Path::new(parent_path), package_name).ok_or(format!(
             "The package \"{}\" is not found (are node_modules up-to-date?)...",
             package_name
         )),
@@ -274,6 +842,72 @@ pub fn read_dependency(
     Ok(canonical_path)
 }
 
+/// The folders of third party packages besides `node_modules`: `MakeOptions::node_modules_dir`,
+/// and the ones in `NODE_PATH` when `MakeOptions::use_node_path` is set
+fn node_modules_overrides(project_root: &str, options: &MakeOptions) -> Vec<PathBuf> {
+    options
+        .node_modules_dir
+        .iter()
+        .map(|node_modules_dir| Path::new(project_root).join(node_modules_dir))
+        .chain(node_path_folders(options))
+        .collect()
+}
+
+fn node_path_folders(options: &MakeOptions) -> Vec<PathBuf> {
+    match options.use_node_path {
+        true => std::env::var_os("NODE_PATH")
+            .map(|node_path| std::env::split_paths(&node_path).collect())
+            .unwrap_or_default(),
+        false => vec![],
+    }
+}
+
+/// Finds the folder of a dependency with `MakeOptions::resolve_dependency` when it is set, and
+/// in `MakeOptions::node_modules_dir` or node_modules otherwise (or when it doesn't know the
+/// dependency). Only when it is in neither, it is looked for in `NODE_PATH`.
+fn resolve_dependency(
+    package_name: &str,
+    parent_path: &str,
+    project_root: &str,
+    workspace_root: &Option<String>,
+    options: &MakeOptions,
+) -> Result<String, String> {
+    let find_in = |folders: Vec<PathBuf>| {
+        folders
+            .into_iter()
+            .map(|node_modules| node_modules.join(package_name))
+            .find(|path| !helpers::is_path_dependency(package_name) && path.exists())
+            .map(|path| path.to_string_lossy().to_string())
+    };
+    let node_modules_dir = options
+        .node_modules_dir
+        .iter()
+        .map(|node_modules_dir| Path::new(project_root).join(node_modules_dir))
+        .collect();
+    match options
+        .resolve_dependency
+        .as_ref()
+        .and_then(|resolver| resolver.0(package_name, parent_path))
+        .or_else(|| find_in(node_modules_dir))
+    {
+        Some(path) => Path::new(&path)
+            .canonicalize()
+            .map(|canonical_path| canonical_path.to_string_lossy().to_string())
+            .map_err(|e| {
+                format!(
+                    "Failed canonicalizing the package \"{}\" path \"{}\"...\nMore details: {}",
+                    package_name, path, e
+                )
+            }),
+        None => read_dependency(package_name, parent_path, project_root, workspace_root).or_else(|e| {
+            find_in(node_path_folders(options))
+                .and_then(|path| Path::new(&path).canonicalize().ok())
+                .map(|path| path.to_string_lossy().to_string())
+                .ok_or(e)
+        }),
+    }
+}
+
 /// # Make Package
 
 /// Given a bsconfig, recursively finds all dependencies.
@@ -288,17 +922,38 @@ fn read_dependencies(
     parent_path: &str,
     project_root: &str,
     workspace_root: Option<String>,
+    options: &MakeOptions,
+    include_dev: bool,
 ) -> Vec<Dependency> {
+    // most packages are leaves, which don't need their package.json read
+    if !has_dependencies(parent_bsconfig, include_dev) {
+        return vec![];
+    }
+    let dev_dependencies = match include_dev {
+        true => parent_bsconfig.bs_dev_dependencies.to_owned().unwrap_or(vec![]),
+        false => vec![],
+    };
+    let peer_dependencies = read_package_json_dependencies(parent_path, "peerDependencies");
     return parent_bsconfig
         .bs_dependencies
         .to_owned()
         .unwrap_or(vec![])
         .iter()
+        .chain(dev_dependencies.iter())
         .filter_map(|package_name| {
-            if registered_dependencies_set.contains(package_name) {
+            // the same path can be another package for another parent, so a path dependency is
+            // registered by where it is
+            let key = match helpers::is_path_dependency(package_name) {
+                true => {
+                    let path = Path::new(parent_path).join(package_name);
+                    path.canonicalize().unwrap_or(path).to_string_lossy().to_string()
+                }
+                false => package_name.to_owned(),
+            };
+            if registered_dependencies_set.contains(&key) {
                 None
             } else {
-                registered_dependencies_set.insert(package_name.to_owned());
+                registered_dependencies_set.insert(key);
                 Some(package_name.to_owned())
             }
         })
@@ -306,16 +961,53 @@ fn read_dependencies(
         // Read all bsconfig files in parallel instead of blocking
         .par_iter()
         .map(|package_name| {
+            if options.ignore_packages.contains(package_name) {
+                return Dependency {
+                    name: package_name.to_owned(),
+                    bsconfig: bsconfig::Config {
+                        name: package_name.to_owned(),
+                        ..Default::default()
+                    },
+                    path: resolve_dependency(
+                        package_name,
+                        parent_path,
+                        project_root,
+                        &workspace_root,
+                        options,
+                    )
+                    .unwrap_or(helpers::package_path(parent_path, package_name)),
+                    is_pinned: parent_bsconfig
+                        .pinned_dependencies
+                        .as_ref()
+                        .map(|p| p.contains(package_name))
+                        .unwrap_or(false),
+                    is_ignored: true,
+                    is_path: helpers::is_path_dependency(package_name),
+                    unresolved: None,
+                    dependencies: vec![],
+                };
+            }
             let (bsconfig, canonical_path) =
-                match read_dependency(package_name, parent_path, project_root, &workspace_root) {
+                match resolve_dependency(package_name, parent_path, project_root, &workspace_root, options) {
                     Err(error) => {
-                        print!(
-                            "{} {} Error building package tree. {}",
-                            style("[1/2]").bold().dim(),
-                            CROSS,
-                            error
-                        );
-                        std::process::exit(2)
+                        return Dependency {
+                            name: package_name.to_owned(),
+                            bsconfig: bsconfig::Config {
+                                name: package_name.to_owned(),
+                                ..Default::default()
+                            },
+                            // where it was expected
+                            path: Path::new(parent_path)
+                                .join("node_modules")
+                                .join(package_name)
+                                .to_string_lossy()
+                                .to_string(),
+                            is_pinned: false,
+                            is_ignored: false,
+                            is_path: helpers::is_path_dependency(package_name),
+                            unresolved: Some((error, peer_dependencies.contains(package_name))),
+                            dependencies: vec![],
+                        };
                     }
                     Ok(canonical_path) => (read_bsconfig(&canonical_path), canonical_path),
                 };
@@ -325,19 +1017,34 @@ fn read_dependencies(
                 .map(|p| p.contains(&bsconfig.name))
                 .unwrap_or(false);
 
-            let dependencies = read_dependencies(
-                &mut registered_dependencies_set.to_owned(),
-                &bsconfig,
-                &canonical_path,
-                project_root,
-                workspace_root.to_owned(),
-            );
+            // a leaf doesn't need the registered dependencies copied, or where to resolve its
+            // dependencies from
+            let dependencies = match has_dependencies(&bsconfig, false) {
+                true => read_transitive_dependencies(
+                    registered_dependencies_set,
+                    &bsconfig,
+                    &canonical_path,
+                    project_root,
+                    &workspace_root,
+                    options,
+                ),
+                false => vec![],
+            };
 
+            // the tree is keyed by package name, also for a dependency on a path. A path
+            // dependency without a name is keyed by its path, so placeholders don't collide.
+            let is_path = helpers::is_path_dependency(package_name);
             Dependency {
-                name: package_name.to_owned(),
+                name: match is_path && !bsconfig.name.trim().is_empty() {
+                    true => bsconfig.name.to_owned(),
+                    false => package_name.to_owned(),
+                },
                 bsconfig,
                 path: canonical_path,
                 is_pinned,
+                is_ignored: false,
+                is_path,
+                unresolved: None,
                 dependencies,
             }
         })
@@ -374,27 +1081,88 @@ fn make_package(
         }
     };
 
+    // we canonicalize the path name so it's always the same
+    let path = PathBuf::from(package_path)
+        .canonicalize()
+        .expect("Could not canonicalize");
+
+    let source_folders = merge_source_folders(
+        source_folders
+            .into_iter()
+            .map(|source| relative_source_dir(&path, source))
+            .flat_map(|source| expand_source_glob(&path, source))
+            .collect(),
+    );
+
     Package {
         name: bsconfig.name.to_owned(),
         bsconfig: bsconfig.to_owned(),
         source_folders,
         source_files: None,
+        source_paths: None,
         namespace: bsconfig.get_namespace(),
         modules: None,
-        // we canonicalize the path name so it's always the same
-        path: PathBuf::from(package_path)
-            .canonicalize()
-            .expect("Could not canonicalize")
-            .to_string_lossy()
-            .to_string(),
+        // dependencies are found through node_modules, so when the real location is somewhere
+        // else, it was symlinked in (by a workspace or a `file:` dependency)
+        is_local: !helpers::is_inside_node_modules(&path),
+        is_runtime: false,
+        path: path.to_string_lossy().to_string(),
         dirs: None,
         is_pinned_dep,
         is_root,
+        is_ignored: false,
+        cache_stamp: None,
     }
 }
 
-fn read_packages(project_root: &str, workspace_root: Option<String>) -> AHashMap<String, Package> {
-    let root_bsconfig = read_bsconfig(project_root);
+/// A stub for a package that is in the ignore list. It has no sources, so it doesn't contribute
+/// any modules to the build.
+fn make_ignored_package(bsconfig: bsconfig::Config, package_path: &str, is_pinned_dep: bool) -> Package {
+    Package {
+        name: bsconfig.name.to_owned(),
+        bsconfig,
+        source_folders: AHashSet::new(),
+        source_files: Some(AHashMap::new()),
+        source_paths: None,
+        namespace: Namespace::NoNamespace,
+        modules: Some(AHashSet::new()),
+        path: helpers::canonicalize_string_path(package_path).unwrap_or(package_path.to_string()),
+        dirs: Some(AHashSet::new()),
+        is_pinned_dep,
+        is_root: false,
+        is_ignored: true,
+        is_local: false,
+        is_runtime: false,
+        cache_stamp: None,
+    }
+}
+
+fn read_packages(
+    project_root: &str,
+    workspace_root: Option<String>,
+    options: &MakeOptions,
+    diagnostics: &Diagnostics,
+) -> Result<AHashMap<String, Package>, MakeError> {
+    let mut root_bsconfig = read_bsconfig(project_root);
+    if options.production_only && !options.include_dev {
+        let dev_only = dev_only_dependencies(project_root);
+        if let Some(bs_dependencies) = root_bsconfig.bs_dependencies.as_mut() {
+            bs_dependencies.retain(|dependency| {
+                let is_dev_only = dev_only.contains(dependency);
+                if is_dev_only {
+                    diagnostics.push(Diagnostic::info(
+                        DiagnosticKind::SkippedDevDependency,
+                        project_root,
+                        format!(
+                            "Skipped \"{}\", it is a devDependency in package.json",
+                            dependency
+                        ),
+                    ));
+                }
+                !is_dev_only
+            });
+        }
+    }
 
     // Store all packages and completely deduplicate them
     let mut map: AHashMap<String, Package> = AHashMap::new();
@@ -403,24 +1171,247 @@ fn read_packages(project_root: &str, workspace_root: Option<String>) -> AHashMap
         make_package(root_bsconfig.to_owned(), project_root, false, true),
     );
 
+    // most (small) projects have no dependencies at all, then there is nothing to resolve
+    if has_dependencies(&root_bsconfig, options.include_dev) {
+        read_dependency_packages(
+            &mut map,
+            &root_bsconfig,
+            project_root,
+            &workspace_root,
+            options,
+            diagnostics,
+        )?;
+    }
+
+    if options.include_runtime && !map.contains_key(RUNTIME_PACKAGE) {
+        match resolve_dependency(
+            RUNTIME_PACKAGE,
+            project_root,
+            project_root,
+            &workspace_root,
+            options,
+        ) {
+            Ok(path) => {
+                let mut package = make_package(runtime_bsconfig(), &path, false, false);
+                package.is_runtime = true;
+                map.insert(RUNTIME_PACKAGE.to_string(), package);
+            }
+            Err(e) => diagnostics.push(Diagnostic::warning(
+                DiagnosticKind::UnresolvedDependency,
+                project_root,
+                format!("Could not find the runtime: {}", e),
+            )),
+        }
+    }
+
+    Ok(map)
+}
+
+/// The dependencies of a dependency at `canonical_path`, which are registered on their own copy of
+/// the registered dependencies, as the dependencies are read in parallel
+fn read_transitive_dependencies(
+    registered_dependencies_set: &AHashSet<String>,
+    bsconfig: &bsconfig::Config,
+    canonical_path: &str,
+    project_root: &str,
+    workspace_root: &Option<String>,
+    options: &MakeOptions,
+) -> Vec<Dependency> {
+    // a package that is symlinked in from outside of the project (like a workspace of another
+    // repository) has its dependencies installed where it really is, so those are resolved from
+    // its own (workspace) root first, and only then from the project
+    let is_outside_project = !Path::new(canonical_path).starts_with(
+        Path::new(project_root)
+            .canonicalize()
+            .unwrap_or(PathBuf::from(project_root)),
+    );
+    let (dependency_root, dependency_workspace_root) = match is_outside_project {
+        true => (
+            helpers::get_workspace_root(canonical_path).unwrap_or(canonical_path.to_owned()),
+            workspace_root.to_owned().or(Some(project_root.to_string())),
+        ),
+        false => (project_root.to_string(), workspace_root.to_owned()),
+    };
+    read_dependencies(
+        &mut registered_dependencies_set.to_owned(),
+        bsconfig,
+        canonical_path,
+        &dependency_root,
+        dependency_workspace_root,
+        options,
+        false,
+    )
+}
+
+/// The dependencies that are only a `devDependency` in the package.json of a package
+fn dev_only_dependencies(package_dir: &str) -> AHashSet<String> {
+    let production_dependencies = read_package_json_dependencies(package_dir, "dependencies");
+    read_package_json_dependencies(package_dir, "devDependencies")
+        .into_iter()
+        .filter(|dependency| !production_dependencies.contains(dependency))
+        .collect()
+}
+
+/// Whether a package with this config has any dependencies to read, the dev dependencies only
+/// count with `include_dev`
+fn has_dependencies(bsconfig: &bsconfig::Config, include_dev: bool) -> bool {
+    let dev_dependencies = match include_dev {
+        true => bsconfig.bs_dev_dependencies.as_ref(),
+        false => None,
+    };
+    [bsconfig.bs_dependencies.as_ref(), dev_dependencies]
+        .into_iter()
+        .flatten()
+        .any(|dependencies| !dependencies.is_empty())
+}
+
+/// Reads all the (transitive) dependencies of the root package into `map`, which has the root
+/// package already. Unresolved dependencies are reported, and removed from the dependency lists
+/// (or with `MakeOptions::strict_dependencies`, the first one is an error).
+fn read_dependency_packages(
+    map: &mut AHashMap<String, Package>,
+    root_bsconfig: &bsconfig::Config,
+    project_root: &str,
+    workspace_root: &Option<String>,
+    options: &MakeOptions,
+    diagnostics: &Diagnostics,
+) -> Result<(), MakeError> {
     let mut registered_dependencies_set: AHashSet<String> = AHashSet::new();
-    let dependencies = flatten_dependencies(read_dependencies(
+    let dependencies = read_dependencies(
         &mut registered_dependencies_set,
-        &root_bsconfig,
+        root_bsconfig,
         project_root,
         project_root,
-        workspace_root,
-    ));
+        workspace_root.to_owned(),
+        options,
+        options.include_dev,
+    );
+    if options.strict_dependencies {
+        if let Some((chain, message)) =
+            first_missing_dependency(&dependencies, &mut vec![root_bsconfig.name.to_owned()])
+        {
+            return Err(MakeError::MissingDependency { chain, message });
+        }
+    }
+    let dependencies = flatten_dependencies(dependencies);
+    let node_modules_overrides = canonical_node_modules_overrides(project_root, options);
     dependencies.iter().for_each(|d| {
-        if !map.contains_key(&d.name) {
-            map.insert(
-                d.name.to_owned(),
-                make_package(d.bsconfig.to_owned(), &d.path, d.is_pinned, false),
-            );
+        if let Some(diagnostic) = unresolved_dependency_diagnostic(d) {
+            diagnostics.push(diagnostic);
+        } else if !map.contains_key(&d.name) {
+            let package = make_dependency_package(d, &node_modules_overrides, diagnostics);
+            map.insert(d.name.to_owned(), package);
         }
     });
+    name_path_dependencies(map);
+    Ok(())
+}
 
-    map
+/// The diagnostic of a dependency that can't be found
+fn unresolved_dependency_diagnostic(d: &Dependency) -> Option<Diagnostic> {
+    d.unresolved.as_ref().map(|(error, is_peer)| {
+        // a peer dependency is provided by whoever depends on the package, so it is fine when it
+        // isn't there
+        let diagnostic = match is_peer {
+            true => Diagnostic::warning,
+            false => Diagnostic::error,
+        };
+        diagnostic(DiagnosticKind::UnresolvedDependency, &d.path, error.to_owned())
+    })
+}
+
+/// `node_modules_overrides`, canonicalized like the package paths
+fn canonical_node_modules_overrides(project_root: &str, options: &MakeOptions) -> Vec<PathBuf> {
+    node_modules_overrides(project_root, options)
+        .into_iter()
+        .filter_map(|node_modules| node_modules.canonicalize().ok())
+        .collect()
+}
+
+/// The package of a resolved dependency
+fn make_dependency_package(
+    d: &Dependency,
+    node_modules_overrides: &[PathBuf],
+    diagnostics: &Diagnostics,
+) -> Package {
+    if d.is_ignored {
+        diagnostics.push(Diagnostic::info(
+            DiagnosticKind::IgnoredPackage,
+            &d.path,
+            format!("Skipped scanning ignored package \"{}\"", d.name),
+        ));
+        return make_ignored_package(d.bsconfig.to_owned(), &d.path, d.is_pinned);
+    }
+    let mut package = make_package(d.bsconfig.to_owned(), &d.path, d.is_pinned, false);
+    // a dependency on a path is a local package, even when it is in node_modules
+    package.is_local |= d.is_path;
+    // and a package in a folder that replaces node_modules is not
+    package.is_local &= !node_modules_overrides
+        .iter()
+        .any(|node_modules| Path::new(&package.path).starts_with(node_modules));
+    package
+}
+
+/// Replaces the dependencies on a path (like `"../shared"`) with the name of the package that is
+/// there, so the dependencies of every package are names in the tree, like the other ones
+fn name_path_dependencies(map: &mut AHashMap<String, Package>) {
+    let names_by_path = map
+        .iter()
+        .map(|(name, package)| (PathBuf::from(&package.path), name.to_owned()))
+        .collect::<AHashMap<PathBuf, String>>();
+    map.values_mut().for_each(|package| {
+        let package_path = PathBuf::from(&package.path);
+        let bsconfig = &mut package.bsconfig;
+        [
+            &mut bsconfig.bs_dependencies,
+            &mut bsconfig.bs_dev_dependencies,
+            &mut bsconfig.pinned_dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|dependencies| dependencies.iter_mut())
+        .filter(|dependency| helpers::is_path_dependency(dependency))
+        .for_each(|dependency| {
+            if let Some(name) = package_path
+                .join(&*dependency)
+                .canonicalize()
+                .ok()
+                .and_then(|path| names_by_path.get(&path))
+            {
+                *dependency = name.to_owned();
+            }
+        })
+    });
+}
+
+/// The first dependency (depth first, in the order they are configured) that isn't found, other
+/// than peer dependencies: the chain of package names from `chain` to it, and why it wasn't found
+fn first_missing_dependency(
+    dependencies: &[Dependency],
+    chain: &mut Vec<String>,
+) -> Option<(Vec<String>, String)> {
+    dependencies.iter().find_map(|dependency| {
+        chain.push(dependency.name.to_owned());
+        let missing = match &dependency.unresolved {
+            Some((error, false)) => Some((chain.to_owned(), error.to_owned())),
+            Some((_error, true)) => None,
+            None => first_missing_dependency(&dependency.dependencies, chain),
+        };
+        chain.pop();
+        missing
+    })
+}
+
+const RUNTIME_PACKAGE: &str = "rescript";
+
+/// The `rescript` package has no config of its own, the sources of the runtime are next to
+/// their compiled artifacts in `lib/ocaml`
+fn runtime_bsconfig() -> bsconfig::Config {
+    bsconfig::Config {
+        name: RUNTIME_PACKAGE.to_string(),
+        sources: bsconfig::OneOrMore::Single(bsconfig::Source::Shorthand("lib/ocaml".to_string())),
+        ..Default::default()
+    }
 }
 
 /// `get_source_files` is essentially a wrapper around `read_structure`, which read a
@@ -431,10 +1422,18 @@ fn read_packages(project_root: &str, workspace_root: Option<String>) -> AHashMap
 /// can be marked with the type 'dev'. Which means that they may not be around in the distributed
 /// NPM package. The file reader allows for this, just warns when this happens.
 /// TODO -> Check whether we actually need the `fs::Metadata`
+#[allow(clippy::too_many_arguments)]
 pub fn get_source_files(
     package_dir: &Path,
     filter: &Option<regex::Regex>,
+    ignore: &Gitignore,
+    reader: &dyn SourceReader,
     source: &bsconfig::PackageSource,
+    include_dev: bool,
+    max_entries: usize,
+    max_depth: usize,
+    max_symlink_depth: usize,
+    diagnostics: &Diagnostics,
 ) -> AHashMap<String, SourceFileMeta> {
     let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
 
@@ -448,10 +1447,68 @@ pub fn get_source_files(
     };
 
     let path_dir = Path::new(&source.dir);
-    // don't include dev sources for now
-    if type_ != &Some("dev".to_string()) {
-        match read_folders(filter, package_dir, path_dir, recurse) {
-            Ok(files) => map.extend(files),
+    if (include_dev || type_ != &Some("dev".to_string()))
+        && !ignore.matched(package_dir.join(path_dir), true).is_ignore()
+    {
+        let mut deepest_folder = PathBuf::new();
+        match read_folders(
+            filter,
+            ignore,
+            reader,
+            package_dir,
+            path_dir,
+            recurse,
+            &mut max_entries.to_owned(),
+            max_symlink_depth,
+            &mut deepest_folder,
+            diagnostics,
+        ) {
+            Ok(files) => {
+                let depth = deepest_folder
+                    .strip_prefix(path_dir)
+                    .map(|relative| relative.components().count())
+                    .unwrap_or(0);
+                if depth > max_depth {
+                    diagnostics.push(Diagnostic::info(
+                        DiagnosticKind::DeepSourceTree,
+                        &package_dir.join(path_dir).to_string_lossy(),
+                        format!(
+                            "The recursive source \"{}\" is nested {} folders deep (down to \"{}\"), is something like node_modules or build output in it?",
+                            path_dir.to_string_lossy(),
+                            depth,
+                            deepest_folder.to_string_lossy()
+                        ),
+                    ))
+                }
+                map.extend(files)
+            }
+            Err(e) if e.is::<SourceTooLarge>() => diagnostics.push(Diagnostic::error(
+                DiagnosticKind::SourceTooLarge,
+                &package_dir.join(path_dir).to_string_lossy(),
+                format!(
+                    "Stopped scanning the source folder \"{}\", it has more than {} files and folders (is it configured too high up?)",
+                    path_dir.to_string_lossy(),
+                    max_entries
+                ),
+            )),
+            // the package is left out of the tree
+            Err(e) if source_reader::is_timeout(e.as_ref()) => (),
+            // a common copy-paste mistake is to configure a source file instead of its folder
+            Err(_e) if is_file(reader, package_dir, path_dir) => {
+                let parent = path_dir.parent().unwrap_or(Path::new(""));
+                diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::SourceIsFile,
+                    &package_dir.join(path_dir).to_string_lossy(),
+                    format!(
+                        "The source \"{}\" is a file instead of a folder, did you mean \"{}\"?",
+                        path_dir.to_string_lossy(),
+                        match parent.as_os_str().is_empty() {
+                            true => ".".to_string(),
+                            false => parent.to_string_lossy().to_string(),
+                        }
+                    ),
+                ))
+            }
             Err(_e) if type_ == &Some("dev".to_string()) => {
                 println!(
                     "Could not read folder: {}... Probably ok as type is dev",
@@ -465,45 +1522,660 @@ pub fn get_source_files(
     map
 }
 
-/// This takes the tree of packages, and finds all the source files for each, adding them to the
-/// respective packages.
-fn extend_with_children(
-    filter: &Option<regex::Regex>,
-    mut build: AHashMap<String, Package>,
-) -> AHashMap<String, Package> {
-    for (_key, value) in build.iter_mut() {
-        let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
-        value
-            .source_folders
-            .par_iter()
-            .map(|source| get_source_files(Path::new(&value.path), filter, source))
-            .collect::<Vec<AHashMap<String, SourceFileMeta>>>()
-            .into_iter()
-            .for_each(|source| map.extend(source));
+/// Whether `path` (relative to `package_dir`) is a file, according to the listing of its parent
+fn is_file(reader: &dyn SourceReader, package_dir: &Path, path: &Path) -> bool {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => reader
+            .read_dir(package_dir, parent)
+            .is_ok_and(|entries| entries.iter().any(|entry| entry.name == name && !entry.is_dir)),
+        _ => false,
+    }
+}
 
-        let mut modules = AHashSet::from_iter(
-            map.keys()
-                .map(|key| helpers::file_path_to_module_name(key, &value.namespace)),
-        );
-        match value.namespace.to_owned() {
-            Namespace::Namespace(namespace) => {
-                let _ = modules.insert(namespace);
-            }
-            Namespace::NamespaceWithEntry { namespace, entry: _ } => {
-                let _ = modules.insert("@".to_string() + &namespace);
-            }
-            Namespace::NoNamespace => (),
-        }
-        value.modules = Some(modules);
-        let mut dirs = AHashSet::new();
-        map.keys().for_each(|path| {
-            let dir = std::path::Path::new(&path).parent().unwrap();
-            dirs.insert(dir.to_owned());
-        });
-        value.dirs = Some(dirs);
-        value.source_files = Some(map);
+/// Reads the `.rewatchignore` file in the root folder, it holds gitignore style patterns (relative
+/// to the folder of the file) of files and folders that are never scanned for sources.
+pub fn read_rewatchignore(root_folder: &str) -> Gitignore {
+    let path = Path::new(root_folder).join(".rewatchignore");
+    if !path.exists() {
+        return Gitignore::empty();
     }
-    build
+    let mut builder = GitignoreBuilder::new(root_folder);
+    if let Some(e) = builder.add(&path) {
+        println!("Could not read {}: {}", path.to_string_lossy(), e);
+    }
+    builder.build().unwrap_or_else(|e| {
+        println!("Could not parse {}: {}", path.to_string_lossy(), e);
+        Gitignore::empty()
+    })
+}
+
+/// A config that parses, but has no name and no sources (like `{}`) is almost always a
+/// placeholder, or a mistake
+fn validate_trivial_configs(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    packages
+        .values()
+        .filter(|package| !package.is_ignored && !package.is_runtime && package.bsconfig.is_trivial())
+        .map(|package| {
+            Diagnostic::warning(
+                DiagnosticKind::TrivialConfig,
+                &package.path,
+                "The config has no name and no sources, is it a placeholder?".to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Config fields that the compiler `version` doesn't support yet. The runtime is left out, it
+/// always matches the compiler.
+fn validate_compiler_compatibility(packages: &AHashMap<String, Package>, version: &str) -> Vec<Diagnostic> {
+    packages
+        .values()
+        .filter(|package| !package.is_ignored && !package.is_runtime)
+        .flat_map(|package| {
+            package
+                .bsconfig
+                .unsupported_fields(version)
+                .into_iter()
+                .map(|(field, since)| {
+                    Diagnostic::warning(
+                        DiagnosticKind::IncompatibleConfig,
+                        &package.path,
+                        format!(
+                            "\"{}\" is only supported since ReScript v{}, but the installed compiler is {}",
+                            field, since, version
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Warns about packages with a name that doesn't result in a valid namespace. This is advisory,
+/// the namespace is only used when the package is namespaced.
+fn validate_package_names(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    packages
+        .values()
+        .filter(|package| !package.is_ignored && !bsconfig::is_valid_package_name(&package.name))
+        .map(|package| {
+            Diagnostic::warning(
+                DiagnosticKind::InvalidPackageName,
+                &package.path,
+                format!(
+                    "The package name \"{}\" does not result in a valid module name when namespaced",
+                    package.name
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Packages are deduplicated by name, and their paths are canonicalized, so the same package
+/// reached through different paths (symlinks, `./` prefixes) ends up in the tree once. This warns
+/// when the same folder is still in the tree under different names, as it would be built twice.
+fn validate_unique_package_paths(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    let mut by_path: AHashMap<&String, Vec<&String>> = AHashMap::new();
+    packages
+        .iter()
+        .for_each(|(name, package)| by_path.entry(&package.path).or_default().push(name));
+    by_path
+        .into_iter()
+        .filter(|(_path, names)| names.len() > 1)
+        .map(|(path, mut names)| {
+            names.sort();
+            Diagnostic::warning(
+                DiagnosticKind::DuplicatePackagePath,
+                path,
+                format!(
+                    "The same package is in the tree under multiple names: {}",
+                    names
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+            )
+        })
+        .collect()
+}
+
+/// See `MakeOptions::force_namespace_prefix`. A prefixed namespace that isn't a valid module name
+/// is an error, and the package keeps its namespace.
+fn apply_namespace_prefix(packages: &mut AHashMap<String, Package>, prefix: &str, diagnostics: &Diagnostics) {
+    packages
+        .values_mut()
+        .filter(|package| !package.is_ignored && (package.is_local || package.is_pinned_dep))
+        .for_each(|package| {
+            let namespace = match &mut package.namespace {
+                Namespace::Namespace(namespace) | Namespace::NamespaceWithEntry { namespace, .. } => {
+                    namespace
+                }
+                Namespace::NoNamespace => return,
+            };
+            let prefixed = format!("{}_{}", prefix, namespace);
+            if helpers::is_non_exotic_module_name(&prefixed) {
+                *namespace = prefixed;
+            } else {
+                diagnostics.push(Diagnostic::error(
+                    DiagnosticKind::InvalidNamespace,
+                    &package.path,
+                    format!(
+                        "The prefixed namespace \"{}\" of package {} is not a valid module name",
+                        prefixed, package.name
+                    ),
+                ));
+            }
+        });
+}
+
+fn trace_dependencies(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    packages
+        .iter()
+        .filter(|(_name, package)| !package.is_root)
+        .map(|(name, package)| {
+            Diagnostic::trace(
+                DiagnosticKind::ResolvedDependency,
+                &package.path,
+                format!(
+                    "Resolved \"{}\" from {}, found {} sources, {} dependencies",
+                    name,
+                    package.path,
+                    package.source_file_paths().len(),
+                    package
+                        .bsconfig
+                        .bs_dependencies
+                        .as_ref()
+                        .map_or(0, |deps| deps.len())
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Checks namespaces that are configured as a string (other than `"true"`). These are normalized
+/// to PascalCase, which is reported as a warning when it changes the name, and as an error when
+/// even the normalized name isn't a valid module name.
+fn validate_namespaces(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    packages
+        .values()
+        .filter(|package| !package.is_ignored)
+        .filter_map(
+            |package| match (&package.bsconfig.namespace, &package.namespace) {
+                (Some(bsconfig::NamespaceConfig::String(configured)), Namespace::Namespace(namespace))
+                | (
+                    Some(bsconfig::NamespaceConfig::String(configured)),
+                    Namespace::NamespaceWithEntry { namespace, .. },
+                ) if configured != "true" => Some((package, configured, namespace)),
+                _ => None,
+            },
+        )
+        .filter_map(|(package, configured, namespace)| {
+            if namespace.is_empty() || !helpers::is_non_exotic_module_name(namespace) {
+                Some(Diagnostic::error(
+                    DiagnosticKind::InvalidNamespace,
+                    &package.path,
+                    format!(
+                        "The namespace \"{}\" of package {} is not a valid module name",
+                        configured, package.name
+                    ),
+                ))
+            } else if configured != namespace {
+                Some(Diagnostic::warning(
+                    DiagnosticKind::InvalidNamespace,
+                    &package.path,
+                    format!(
+                        "The namespace \"{}\" of package {} is not a valid module name, it is used as \"{}\"",
+                        configured, package.name, namespace
+                    ),
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The packages that are in a scanned source folder of another package, as (the name of the
+/// other package, the folder of the package relative to the other package).
+fn nested_packages(packages: &AHashMap<String, Package>) -> Vec<(String, PathBuf)> {
+    packages
+        .iter()
+        .filter(|(_name, parent)| !parent.is_ignored)
+        .flat_map(|(parent_name, parent)| {
+            packages
+                .values()
+                .filter(move |child| child.path != parent.path)
+                .filter_map(move |child| {
+                    let relative = Path::new(&child.path).strip_prefix(&parent.path).ok()?;
+                    parent
+                        .source_folders
+                        .iter()
+                        .any(|source| {
+                            let dir = Path::new(&source.dir);
+                            match source.subdirs == Some(bsconfig::Subdirs::Recurse(true)) {
+                                true => relative.starts_with(dir),
+                                false => relative == dir,
+                            }
+                        })
+                        .then(|| (parent_name.to_owned(), relative.to_path_buf()))
+                })
+        })
+        .collect()
+}
+
+/// Warns about packages that are in a source folder of another package, because the files of
+/// the nested package then also end up in the other package.
+fn validate_nested_packages(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    nested_packages(packages)
+        .into_iter()
+        .map(|(parent_name, relative)| {
+            let parent = &packages[&parent_name];
+            Diagnostic::warning(
+                DiagnosticKind::NestedPackageSource,
+                &parent.path,
+                format!(
+                    "The package {} has the package in {} in its sources, so it also contains its files",
+                    parent.name,
+                    relative.to_string_lossy()
+                ),
+            )
+        })
+        .collect()
+}
+
+/// See `MakeOptions::prune_nested_packages`
+fn prune_nested_packages(packages: &mut AHashMap<String, Package>) {
+    nested_packages(packages)
+        .into_iter()
+        .for_each(|(parent_name, relative)| {
+            let parent = packages
+                .get_mut(&parent_name)
+                .expect("The package is in the tree");
+            if let Some(source_files) = parent.source_files.as_mut() {
+                source_files.retain(|path, _meta| !Path::new(path).starts_with(&relative));
+            }
+            if let Some(source_paths) = parent.source_paths.as_mut() {
+                source_paths.retain(|path| !Path::new(path).starts_with(&relative));
+            }
+            if let Some(dirs) = parent.dirs.as_mut() {
+                dirs.retain(|dir| !dir.starts_with(&relative));
+            }
+            let _ = parent.recompute_modules();
+        });
+}
+
+/// Points out fields that are probably a misspelled dependency field, as those are ignored, so
+/// the dependencies silently go missing.
+fn validate_dependency_field_names(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    packages
+        .values()
+        .filter(|package| !package.is_ignored)
+        .flat_map(|package| {
+            package
+                .bsconfig
+                .get_misspelled_dependency_fields()
+                .into_iter()
+                .map(|(unknown_field, field)| {
+                    Diagnostic::info(
+                        DiagnosticKind::MisspelledField,
+                        &package.path,
+                        format!(
+                            "The package {} has an unknown field \"{}\", did you mean \"{}\"?",
+                            package.name, unknown_field, field
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Warns about dependencies that declare `bs-dependencies` as an empty list, while they do use
+/// other packages (they have dev or pinned dependencies). That usually means the dependencies
+/// were forgotten, rather than that there are none. An absent field is not reported.
+fn validate_dependency_declarations(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    let is_non_empty =
+        |dependencies: &Option<Vec<String>>| dependencies.as_ref().is_some_and(|d| !d.is_empty());
+    packages
+        .values()
+        .filter(|package| !package.is_root && !package.is_ignored)
+        .filter(|package| package.bsconfig.bs_dependencies.as_ref().is_some_and(|d| d.is_empty()))
+        .filter(|package| {
+            is_non_empty(&package.bsconfig.bs_dev_dependencies)
+                || is_non_empty(&package.bsconfig.pinned_dependencies)
+        })
+        .map(|package| {
+            Diagnostic::warning(
+                DiagnosticKind::EmptyDependencies,
+                &package.path,
+                format!(
+                    "The package {} declares \"bs-dependencies\" as an empty list, but it does depend on other packages",
+                    package.name
+                ),
+            )
+        })
+        .collect()
+}
+
+/// An interface and its implementation can only be paired when they have the same namespace. With
+/// nested packages, the interface can end up in one package and the implementation in the other
+/// (with another namespace), which the compiler reports as a missing implementation and a missing
+/// interface. This reports those as one problem.
+fn validate_namespace_mismatches(packages: &AHashMap<String, Package>) -> Vec<Diagnostic> {
+    let display = |namespace: &Namespace| namespace.to_suffix().unwrap_or("no namespace".to_string());
+    let mut implementations: AHashMap<String, Vec<(&Package, &String)>> = AHashMap::new();
+    packages
+        .values()
+        .filter(|package| !package.is_ignored)
+        .for_each(|package| {
+            package
+                .source_file_paths()
+                .into_iter()
+                .filter(|path| helpers::is_implementation_file(&helpers::get_extension(path)))
+                .for_each(|path| {
+                    implementations
+                        .entry(helpers::file_path_to_module_name(path, &Namespace::NoNamespace))
+                        .or_default()
+                        .push((package, path))
+                })
+        });
+    interfaces_without_implementation(packages)
+        .into_iter()
+        .filter_map(|(name, interface)| {
+            let package = &packages[&name];
+            let module_name = helpers::file_path_to_module_name(&interface, &Namespace::NoNamespace);
+            let (other, implementation) = implementations.get(&module_name)?.iter().find(|(other, _path)| {
+                other.name != package.name
+                    && other.namespace.to_suffix() != package.namespace.to_suffix()
+                    && (Path::new(&other.path).starts_with(&package.path)
+                        || Path::new(&package.path).starts_with(&other.path))
+            })?;
+            Some(Diagnostic::warning(
+                DiagnosticKind::NamespaceMismatch,
+                // the keys of a source folder `.` start with `./`
+                &Path::new(&package.path)
+                    .join(&interface)
+                    .components()
+                    .collect::<PathBuf>()
+                    .to_string_lossy(),
+                format!(
+                    "The interface {} of package {} ({}) can't be paired with the implementation {} of package {} ({}), they have a different namespace",
+                    interface,
+                    package.name,
+                    display(&package.namespace),
+                    implementation,
+                    other.name,
+                    display(&other.namespace)
+                ),
+            ))
+        })
+        .collect()
+}
+
+/// The source files of the local packages that aren't in any of their source folders, so they are
+/// not built. The folders that are never sources (node_modules, lib, hidden folders, the ones in
+/// `.rewatchignore`, and other packages) are skipped.
+fn validate_unreferenced_source_files(
+    packages: &AHashMap<String, Package>,
+    ignore: &Gitignore,
+) -> Vec<Diagnostic> {
+    fn visit(dir: &Path, package_dir: &Path, ignore: &Gitignore, files: &mut Vec<PathBuf>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if name.starts_with('.') || ignore.matched(&path, is_dir).is_ignore() {
+                continue;
+            }
+            if is_dir {
+                let is_package = Path::new(&get_bsconfig_path(&path.to_string_lossy())).exists();
+                if name != "node_modules" && name != "lib" && !is_package {
+                    visit(&path, package_dir, ignore, files);
+                }
+            } else if path
+                .extension()
+                .is_some_and(|extension| helpers::is_source_file(&extension.to_string_lossy()))
+            {
+                if let Ok(relative) = path.strip_prefix(package_dir) {
+                    files.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+    // `./src` and `src` are the same source folder
+    let normalize = |path: &Path| {
+        path.components()
+            .filter(|component| component != &std::path::Component::CurDir)
+            .collect::<PathBuf>()
+    };
+
+    packages
+        .values()
+        .filter(|package| package.is_local && !package.is_ignored && !package.is_runtime)
+        .flat_map(|package| {
+            let package_dir = Path::new(&package.path);
+            let mut files = vec![];
+            visit(package_dir, package_dir, ignore, &mut files);
+            files
+                .into_iter()
+                .filter(|file| {
+                    let parent = normalize(file.parent().unwrap_or(Path::new("")));
+                    !package.source_folders.iter().any(|source| {
+                        let dir = normalize(Path::new(&source.dir));
+                        match source.subdirs == Some(bsconfig::Subdirs::Recurse(true)) {
+                            true => parent.starts_with(&dir),
+                            false => parent == dir,
+                        }
+                    })
+                })
+                .map(|file| {
+                    Diagnostic::warning(
+                        DiagnosticKind::UnreferencedSourceFile,
+                        &package_dir.join(&file).to_string_lossy(),
+                        format!(
+                            "The file {} of package {} is not in any of its source folders, so it is not built",
+                            file.to_string_lossy(),
+                            package.name
+                        ),
+                    )
+                })
+                .collect::<Vec<Diagnostic>>()
+        })
+        .collect()
+}
+
+/// This takes the tree of packages, and finds all the source files for each, adding them to the
+/// respective packages.
+fn extend_with_children(
+    filter: &Option<regex::Regex>,
+    ignore: &Gitignore,
+    mut build: AHashMap<String, Package>,
+    root_folder: &str,
+    options: &MakeOptions,
+    diagnostics: &Diagnostics,
+) -> AHashMap<String, Package> {
+    // canonicalized, like the package paths. Paths that don't exist can't be in the sources.
+    let changed_paths = options.changed_paths.as_ref().map(|changed_paths| {
+        changed_paths
+            .iter()
+            .filter_map(|path| Path::new(root_folder).join(path).canonicalize().ok())
+            .collect::<AHashSet<PathBuf>>()
+    });
+    let parallel_scan_threshold = options
+        .parallel_scan_threshold
+        .unwrap_or(DEFAULT_PARALLEL_SCAN_THRESHOLD);
+    let max_source_entries = options.max_source_entries.unwrap_or(DEFAULT_MAX_SOURCE_ENTRIES);
+    let max_source_depth = options.max_source_depth.unwrap_or(DEFAULT_MAX_SOURCE_DEPTH);
+    let max_symlink_depth = options.max_symlink_depth.unwrap_or(DEFAULT_MAX_SYMLINK_DEPTH);
+    let test_files = test_file_matcher(&options.test_file_patterns);
+    let is_past_deadline = || {
+        options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    };
+    // the root is scanned first, then the other local packages, so when the deadline passes, the
+    // packages that are worked on are most likely there
+    let mut packages = build
+        .iter_mut()
+        .filter(|(_key, value)| !value.is_ignored)
+        .collect::<Vec<(&String, &mut Package)>>();
+    packages.sort_by(|(a_key, a), (b_key, b)| {
+        (!a.is_root, !a.is_local, a_key).cmp(&(!b.is_root, !b.is_local, b_key))
+    });
+    let scan_package = |key: &String, value: &mut Package| -> Option<String> {
+        if is_past_deadline() {
+            return Some(key.to_owned());
+        }
+        let reader: Box<dyn SourceReader> = match &value.bsconfig.sources_archive {
+            None => match &options.source_reader {
+                Some(reader) => Box::new(reader.to_owned()),
+                None => Box::new(DiskReader),
+            },
+            Some(archive) => {
+                let archive_path = Path::new(&value.path).join(archive);
+                match TarReader::open(&archive_path) {
+                    Ok(reader) => Box::new(reader),
+                    Err(e) => {
+                        diagnostics.push(Diagnostic::error(
+                            DiagnosticKind::UnreadableSourceArchive,
+                            &archive_path.to_string_lossy(),
+                            format!(
+                                "Could not read the sources archive of package {}: {}",
+                                value.name, e
+                            ),
+                        ));
+                        Box::new(TarReader::empty())
+                    }
+                }
+            }
+        };
+        let reader = DeadlineReader::new(reader, options.deadline);
+        let include_dev = match value.is_root {
+            true => options.dev_sources.root,
+            false => options.dev_sources.deps,
+        };
+        let scan = |source: &bsconfig::PackageSource| {
+            get_source_files(
+                Path::new(&value.path),
+                filter,
+                ignore,
+                &reader,
+                source,
+                include_dev,
+                max_source_entries,
+                max_source_depth,
+                max_symlink_depth,
+                diagnostics,
+            )
+        };
+        // scanning a few folders in parallel costs more than it gains
+        let sources = if value.source_folders.len() < parallel_scan_threshold {
+            value
+                .source_folders
+                .iter()
+                .map(scan)
+                .collect::<Vec<AHashMap<String, SourceFileMeta>>>()
+        } else {
+            value
+                .source_folders
+                .par_iter()
+                .map(scan)
+                .collect::<Vec<AHashMap<String, SourceFileMeta>>>()
+        };
+        // some folders may not have been read
+        if reader.timed_out() {
+            return Some(key.to_owned());
+        }
+        let mut map: AHashMap<String, SourceFileMeta> = AHashMap::new();
+        sources.into_iter().for_each(|source| map.extend(source));
+        let gentype_extensions = value.bsconfig.get_gentype_extensions();
+        map.retain(|path, _meta| {
+            !gentype_extensions
+                .iter()
+                .any(|extension| path.ends_with(extension))
+        });
+        map.iter_mut()
+            .for_each(|(path, meta)| meta.is_test = test_files.is_match(path));
+        if !include_dev {
+            map.retain(|_path, meta| !meta.is_test);
+        }
+        if let Some(changed_paths) = &changed_paths {
+            map.retain(|path, _meta| changed_paths.contains(&Path::new(&value.path).join(path)));
+        }
+
+        let mut dirs = AHashSet::new();
+        map.keys().for_each(|path| {
+            let dir = std::path::Path::new(&path).parent().unwrap();
+            dirs.insert(dir.to_owned());
+        });
+        value.dirs = Some(dirs);
+        match options.drop_source_metadata {
+            true => value.source_paths = Some(map.into_keys().collect()),
+            false => value.source_files = Some(map),
+        }
+        diagnostics.extend(value.recompute_modules());
+        None
+    };
+    // a tiny package (fewer source folders than the threshold) is scanned in a batch with other
+    // tiny packages, one by one, as a task per package costs more than it gains. Every other
+    // package is scanned on its own, with its source folders in parallel.
+    let is_tiny = |package: &Package| package.source_folders.len() < parallel_scan_threshold;
+    let round_size = rayon::current_num_threads() * SCAN_BATCH_SIZE;
+    let mut rounds: Vec<Vec<(&String, &mut Package)>> = vec![];
+    for (key, value) in packages {
+        match rounds.last_mut() {
+            Some(round) if is_tiny(value) && round.len() < round_size && is_tiny(round[0].1) => {
+                round.push((key, value))
+            }
+            _ => rounds.push(vec![(key, value)]),
+        }
+    }
+    // the rounds are scanned one after the other, in the order of the packages, so the ones that
+    // come first are done before the later ones start. Only the batches of a round are scanned in
+    // parallel.
+    let mut unfinished = rounds
+        .into_iter()
+        .flat_map(|mut round| {
+            round
+                .par_chunks_mut(SCAN_BATCH_SIZE)
+                .flat_map_iter(|batch| {
+                    batch
+                        .iter_mut()
+                        .filter_map(|(key, value)| scan_package(key, value))
+                        .collect::<Vec<String>>()
+                })
+                .collect::<Vec<String>>()
+        })
+        .collect::<Vec<String>>();
+    if !unfinished.is_empty() {
+        unfinished.sort();
+        build.retain(|name, _package| !unfinished.contains(name));
+        diagnostics.push(Diagnostic::warning(
+            DiagnosticKind::Timeout,
+            root_folder,
+            format!(
+                "The deadline passed before these packages were scanned, they are left out: {}",
+                unfinished.join(", ")
+            ),
+        ));
+    }
+    build
+}
+
+/// The globs of `MakeOptions::test_file_patterns`, an invalid one is logged and left out
+fn test_file_matcher(patterns: &[String]) -> globset::GlobSet {
+    let mut builder = globset::GlobSetBuilder::new();
+    patterns
+        .iter()
+        .for_each(|pattern| match globset::Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => error!("Invalid test file pattern \"{}\": {}", pattern, e),
+        });
+    builder.build().unwrap_or_else(|_| globset::GlobSet::empty())
 }
 
 /// Make turns a folder, that should contain a bsconfig, into a tree of Packages.
@@ -517,475 +2189,5998 @@ pub fn make(
     root_folder: &str,
     workspace_root: &Option<String>,
 ) -> AHashMap<String, Package> {
-    let map = read_packages(root_folder, workspace_root.to_owned());
+    let tree = make_with(filter, root_folder, workspace_root, &MakeOptions::default()).expect(
+        "Building the package tree only fails when MakeOptions::fail_on or strict_dependencies is set",
+    );
+    if let Some(unresolved) = tree.diagnostics.iter().find(|diagnostic| {
+        diagnostic.kind == DiagnosticKind::UnresolvedDependency && diagnostic.severity == Severity::Error
+    }) {
+        print!(
+            "{} {} Error building package tree. {}",
+            style("[1/2]").bold().dim(),
+            CROSS,
+            unresolved.message
+        );
+        std::process::exit(2)
+    }
+    tree.packages
+}
+
+/// Same as `make`, but configurable through `MakeOptions`, and it also returns the diagnostics
+/// that were collected along the way.
+pub fn make_with(
+    filter: &Option<regex::Regex>,
+    root_folder: &str,
+    workspace_root: &Option<String>,
+    options: &MakeOptions,
+) -> Result<PackageTree, MakeError> {
+    let diagnostics = Diagnostics::new();
+    let mut result = read_packages(root_folder, workspace_root.to_owned(), options, &diagnostics)?;
+    let compiler_version = helpers::find_rescript_version(Path::new(root_folder));
+    let read = result.keys().cloned().collect::<AHashSet<String>>();
+    complete_packages(
+        filter,
+        root_folder,
+        &mut result,
+        &read,
+        &AHashSet::new(),
+        options,
+        &diagnostics,
+    );
+    let diagnostics = diagnostics.into_sorted();
+    check_fail_on(&diagnostics, options)?;
+
+    create_build_dirs(result.values());
+    let edges = dependency_edges(&result, options.include_dev);
+    Ok(PackageTree {
+        closures: closures(&result, &edges),
+        edges,
+        packages: result,
+        diagnostics,
+        compiler_version,
+        filter: filter.to_owned(),
+        options: options.to_owned(),
+        workspace_root: workspace_root.to_owned(),
+    })
+}
+
+/// What `make_with` does with the packages after reading them, also for the packages that are
+/// added to a tree later (see `add_dependency`). `packages` is the whole tree: the checks see all
+/// of it, the packages in `read` were just read, so they get the namespace prefix and are scanned,
+/// and the ones in `rescan` are only scanned again. The packages that weren't scanned before the
+/// deadline are left out.
+fn complete_packages(
+    filter: &Option<regex::Regex>,
+    root_folder: &str,
+    packages: &mut AHashMap<String, Package>,
+    read: &AHashSet<String>,
+    rescan: &AHashSet<String>,
+    options: &MakeOptions,
+    diagnostics: &Diagnostics,
+) {
+    if options.check_compiler_compatibility {
+        if let Some(version) = helpers::find_rescript_version(Path::new(root_folder)) {
+            diagnostics.extend(validate_compiler_compatibility(packages, &version));
+        }
+    }
+    diagnostics.extend(validate_trivial_configs(packages));
+    diagnostics.extend(validate_package_names(packages));
+    diagnostics.extend(validate_dependency_declarations(packages));
+    diagnostics.extend(validate_dependency_field_names(packages));
+    diagnostics.extend(validate_namespaces(packages));
+    diagnostics.extend(validate_unique_package_paths(packages));
+    diagnostics.extend(validate_nested_packages(packages));
+    let take = |packages: &mut AHashMap<String, Package>, names: &mut dyn Iterator<Item = &String>| {
+        names
+            .filter_map(|name| packages.remove_entry(name))
+            .collect::<AHashMap<String, Package>>()
+    };
+    let mut scan = take(packages, &mut read.iter());
+    if let Some(prefix) = &options.force_namespace_prefix {
+        apply_namespace_prefix(&mut scan, prefix, diagnostics);
+    }
+    scan.extend(take(packages, &mut rescan.iter()));
 
     /* Once we have the deduplicated packages, we can add the source files for each - to minimize
      * the IO */
-    let result = extend_with_children(filter, map);
-    result.values().for_each(|package| match &package.dirs {
-        Some(dirs) => dirs.iter().for_each(|dir| {
-            let _ = std::fs::create_dir_all(std::path::Path::new(&package.get_bs_build_path()).join(dir));
-        }),
-        None => (),
-    });
-    result
+    let ignore = read_rewatchignore(root_folder);
+    packages.extend(extend_with_children(
+        filter,
+        &ignore,
+        scan,
+        root_folder,
+        options,
+        diagnostics,
+    ));
+    if options.prune_nested_packages {
+        prune_nested_packages(packages);
+    }
+    if options.report_unreferenced_sources {
+        diagnostics.extend(validate_unreferenced_source_files(packages, &ignore));
+    }
+    diagnostics.extend(validate_namespace_mismatches(packages));
+    options
+        .validators
+        .iter()
+        .for_each(|validator| diagnostics.extend(validator.0(packages)));
+    if options.trace {
+        diagnostics.extend(trace_dependencies(packages));
+    }
 }
 
-pub fn get_package_name(path: &str) -> String {
-    let bsconfig = read_bsconfig(path);
-    bsconfig.name
+/// See `MakeOptions::fail_on`
+fn check_fail_on(diagnostics: &[Diagnostic], options: &MakeOptions) -> Result<(), MakeError> {
+    match options.fail_on {
+        Some(fail_on)
+            if diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.severity >= fail_on) =>
+        {
+            Err(MakeError::FailedOnDiagnostics(diagnostics.to_vec()))
+        }
+        _ => Ok(()),
+    }
 }
 
-pub fn parse_packages(build_state: &mut BuildState) {
-    build_state
-        .packages
-        .clone()
+/// Creates the build folders of the `packages`, the runtime is never built
+fn create_build_dirs<'a>(packages: impl Iterator<Item = &'a Package>) {
+    packages
+        .filter(|package| !package.is_runtime)
+        .for_each(|package| {
+            package.dirs.iter().flatten().for_each(|dir| {
+                let _ = fs::create_dir_all(Path::new(&package.get_bs_build_path()).join(dir));
+            })
+        });
+}
+
+/// The names of the dependencies in a dependency field (like `peerDependencies`) of the
+/// package.json of a package
+fn read_package_json_dependencies(package_dir: &str, field: &str) -> AHashSet<String> {
+    fs::read_to_string(Path::new(package_dir).join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|package_json| {
+            package_json
+                .get(field)
+                .and_then(|dependencies| dependencies.as_object())
+                .map(|dependencies| dependencies.keys().cloned().collect())
+        })
+        .unwrap_or_default()
+}
+
+/// The dependency graph of the tree, sorted by `from`, then `to`. Only dependencies that are in
+/// the tree have an edge. Dev edges are only there with `include_dev`, as dev dependencies are
+/// otherwise not read (and only those of the root package are ever read).
+pub fn dependency_edges(build: &AHashMap<String, Package>, include_dev: bool) -> Vec<DependencyEdge> {
+    let mut edges = build
         .iter()
-        .for_each(|(package_name, package)| {
-            debug!("Parsing package: {}", package_name);
-            if let Some(package_modules) = package.modules.to_owned() {
-                build_state.module_names.extend(package_modules)
+        .filter(|(_key, package)| !package.is_ignored)
+        .flat_map(|(key, package)| {
+            let peer_dependencies = read_package_json_dependencies(&package.path, "peerDependencies");
+            let normal = package
+                .bsconfig
+                .bs_dependencies
+                .to_owned()
+                .unwrap_or(vec![])
+                .into_iter()
+                .map(move |to| {
+                    let kind = match peer_dependencies.contains(&to) {
+                        true => DependencyKind::Peer,
+                        false => DependencyKind::Normal,
+                    };
+                    (to, kind)
+                });
+            let dev = match include_dev && package.is_root {
+                true => package.bsconfig.bs_dev_dependencies.to_owned().unwrap_or(vec![]),
+                false => vec![],
             }
-            let build_path_abs = package.get_build_path();
-            let bs_build_path = package.get_bs_build_path();
-            helpers::create_build_path(&build_path_abs);
-            helpers::create_build_path(&bs_build_path);
+            .into_iter()
+            .map(|to| (to, DependencyKind::Dev));
+            normal.chain(dev).map(|(to, kind)| DependencyEdge {
+                from: key.to_owned(),
+                to,
+                kind,
+            })
+        })
+        .filter(|edge| build.contains_key(&edge.to))
+        .collect::<Vec<DependencyEdge>>();
+    edges.sort_by(|a, b| (&a.from, &a.to, a.kind).cmp(&(&b.from, &b.to, b.kind)));
+    edges.dedup();
+    edges
+}
 
-            package.namespace.to_suffix().iter().for_each(|namespace| {
-                // generate the mlmap "AST" file for modules that have a namespace configured
-                let source_files = match package.source_files.to_owned() {
-                    Some(source_files) => source_files
-                        .keys()
-                        .map(|key| key.to_owned())
-                        .collect::<Vec<String>>(),
-                    None => unreachable!(),
-                };
-                let entry = match &package.namespace {
-                    packages::Namespace::NamespaceWithEntry { entry, namespace: _ } => Some(entry),
-                    _ => None,
-                };
+/// The closures of the root package over the `edges` (see `Closures`)
+pub fn closures(build: &AHashMap<String, Package>, edges: &[DependencyEdge]) -> Closures {
+    let closure = |include_dev: bool| {
+        let mut reachable: AHashSet<String> = AHashSet::new();
+        let mut queue = build
+            .iter()
+            .filter(|(_name, package)| package.is_root)
+            .map(|(name, _package)| name.to_owned())
+            .collect::<Vec<String>>();
+        while let Some(package_name) = queue.pop() {
+            if reachable.insert(package_name.to_owned()) {
+                queue.extend(
+                    edges
+                        .iter()
+                        .filter(|edge| edge.from == package_name)
+                        .filter(|edge| include_dev || edge.kind != DependencyKind::Dev)
+                        .map(|edge| edge.to.to_owned()),
+                );
+            }
+        }
+        reachable
+            .iter()
+            .map(|name| build[name].path.to_owned())
+            .collect::<AHashSet<String>>()
+    };
+    Closures {
+        production: closure(false),
+        full: closure(true),
+    }
+}
 
-                let depending_modules = source_files
-                    .iter()
-                    .map(|path| helpers::file_path_to_module_name(path, &packages::Namespace::NoNamespace))
-                    .filter(|module_name| {
-                        if let Some(entry) = entry {
-                            module_name != entry
-                        } else {
-                            true
-                        }
-                    })
-                    .filter(|module_name| helpers::is_non_exotic_module_name(module_name))
-                    .collect::<AHashSet<String>>();
+/// The (sorted) names of the packages without any modules, which probably means their sources
+/// are misconfigured (or missing). A package with `"sources": []` is intentionally empty, so it
+/// isn't one of them, and neither are ignored packages.
+pub fn empty_packages(build: &AHashMap<String, Package>) -> Vec<String> {
+    let mut empty = build
+        .iter()
+        .filter(|(_name, package)| !package.is_ignored && !package.bsconfig.has_empty_sources())
+        .filter(|(_name, package)| package.modules.as_ref().is_some_and(|modules| modules.is_empty()))
+        .map(|(name, _package)| name.to_owned())
+        .collect::<Vec<String>>();
+    empty.sort();
+    empty
+}
 
-                let mlmap = namespaces::gen_mlmap(package, namespace, &depending_modules);
+/// The (sorted) names of the packages in the tree that no package depends on (also not as a dev
+/// dependency), other than `root_name` and the runtime. These shouldn't be there, so it points at a resolution
+/// problem, or at a package that can be removed.
+pub fn unreferenced_packages(build: &AHashMap<String, Package>, root_name: &str) -> Vec<String> {
+    let referenced = dependency_edges(build, true)
+        .into_iter()
+        .map(|edge| edge.to)
+        .collect::<AHashSet<String>>();
+    let mut unreferenced = build
+        .iter()
+        .filter(|(name, package)| *name != root_name && !package.is_runtime && !referenced.contains(*name))
+        .map(|(name, _package)| name.to_owned())
+        .collect::<Vec<String>>();
+    unreferenced.sort();
+    unreferenced
+}
 
-                // mlmap will be compiled in the AST generation step
-                // compile_mlmap(&package, namespace, &project_root);
-                let deps = source_files
-                    .iter()
-                    .filter(|path| {
-                        helpers::is_non_exotic_module_name(&helpers::file_path_to_module_name(
-                            path,
-                            &packages::Namespace::NoNamespace,
-                        ))
-                    })
-                    .map(|path| helpers::file_path_to_module_name(path, &package.namespace))
-                    .filter(|module_name| {
-                        if let Some(entry) = entry {
-                            module_name != entry
-                        } else {
-                            true
-                        }
-                    })
-                    .collect::<AHashSet<String>>();
+/// The names of the packages grouped by their resolved namespace (without the `@` of a namespace
+/// with an entry), sorted. The packages without a namespace are grouped under `""`. More than one
+/// package in a namespace group means their modules end up in the same flat module space.
+pub fn packages_by_namespace(build: &AHashMap<String, Package>) -> AHashMap<String, Vec<String>> {
+    let mut groups: AHashMap<String, Vec<String>> = AHashMap::new();
+    build.iter().for_each(|(name, package)| {
+        let namespace = match &package.namespace {
+            Namespace::Namespace(namespace) | Namespace::NamespaceWithEntry { namespace, .. } => {
+                namespace.to_owned()
+            }
+            Namespace::NoNamespace => "".to_string(),
+        };
+        groups.entry(namespace).or_default().push(name.to_owned());
+    });
+    groups.values_mut().for_each(|names| names.sort());
+    groups
+}
 
-                build_state.insert_module(
-                    &helpers::file_path_to_module_name(&mlmap.to_owned(), &packages::Namespace::NoNamespace),
-                    Module {
-                        source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
-                        deps,
-                        dependents: AHashSet::new(),
-                        package_name: package.name.to_owned(),
-                        compile_dirty: false,
-                        last_compiled_cmt: None,
-                        last_compiled_cmi: None,
-                    },
-                );
-            });
+/// The packages with a namespace, sorted by name. With `only_local`, only the local packages (the
+/// ones whose namespace is a decision of the project itself), otherwise the third party ones too.
+pub fn namespaced_packages(build: &AHashMap<String, Package>, only_local: bool) -> Vec<&Package> {
+    let mut packages = build
+        .values()
+        .filter(|package| !package.is_ignored && (package.is_local || !only_local))
+        .filter(|package| !matches!(package.namespace, Namespace::NoNamespace))
+        .collect::<Vec<&Package>>();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
 
-            debug!("Building source file-tree for package: {}", package.name);
-            match &package.source_files {
-                None => (),
-                Some(source_files) => source_files.iter().for_each(|(file, metadata)| {
-                    let namespace = package.namespace.to_owned();
+/// Numbers about a tree, see `tree_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    // the packages that are not ignored
+    pub packages: usize,
+    pub local_packages: usize,
+    pub modules: usize,
+    pub source_files: usize,
+    // the number of source files per lowercase extension (without the dot), like `res` or `re`,
+    // to see how much of a migration (from Reason, or OCaml) is left
+    pub source_files_by_extension: AHashMap<String, usize>,
+}
 
-                    let file_buf = PathBuf::from(file);
-                    let extension = file_buf.extension().unwrap().to_str().unwrap();
-                    let module_name = helpers::file_path_to_module_name(&file.to_owned(), &namespace);
+/// The numbers of packages, modules and source files in the tree (leaving out ignored packages)
+pub fn tree_stats(build: &AHashMap<String, Package>) -> TreeStats {
+    let mut stats = TreeStats::default();
+    build
+        .values()
+        .filter(|package| !package.is_ignored)
+        .for_each(|package| {
+            stats.packages += 1;
+            stats.local_packages += package.is_local as usize;
+            stats.modules += package.modules.as_ref().map(|modules| modules.len()).unwrap_or(0);
+            package
+                .source_file_counts()
+                .into_iter()
+                .for_each(|(extension, count)| {
+                    stats.source_files += count;
+                    *stats.source_files_by_extension.entry(extension).or_default() += count;
+                });
+        });
+    stats
+}
 
-                    if helpers::is_implementation_file(extension) {
-                        build_state
-                            .modules
-                            .entry(module_name.to_string())
-                            .and_modify(|module| {
-                                if let SourceType::SourceFile(ref mut source_file) = module.source_type {
-                                    if &source_file.implementation.path != file {
-                                        error!("Duplicate files found for module: {}", &module_name);
-                                        error!("file 1: {}", &source_file.implementation.path);
-                                        error!("file 2: {}", &file);
+/// The checks `health_check` runs, all of them by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthChecks {
+    // cycles in the `bs-dependencies`
+    pub cycles: bool,
+    // package names that don't make a valid namespace, and packages that are in the tree twice
+    pub package_names: bool,
+    // modules with the same name in multiple packages
+    pub module_collisions: bool,
+    // modules of which the names only differ in case, their files collide on a case-insensitive
+    // file system (macOS, Windows)
+    pub case_collisions: bool,
+    // dependencies that aren't in the tree
+    pub missing_dependencies: bool,
+    // packages without any modules (see `empty_packages`)
+    pub empty_packages: bool,
+    // multiple packages in the same namespace
+    pub namespace_collisions: bool,
+    // packages nothing depends on (see `unreferenced_packages`)
+    pub unreferenced_packages: bool,
+}
 
-                                        panic!("Unable to continue... See log output above...");
-                                    }
-                                    source_file.implementation.path = file.to_owned();
-                                    source_file.implementation.last_modified = metadata.modified;
-                                    source_file.implementation.parse_dirty = true;
-                                }
-                            })
-                            .or_insert(Module {
-                                source_type: SourceType::SourceFile(SourceFile {
-                                    implementation: Implementation {
-                                        path: file.to_owned(),
-                                        parse_state: ParseState::Pending,
-                                        compile_state: CompileState::Pending,
-                                        last_modified: metadata.modified,
-                                        parse_dirty: true,
-                                    },
-                                    interface: None,
-                                }),
-                                deps: AHashSet::new(),
-                                dependents: AHashSet::new(),
-                                package_name: package.name.to_owned(),
-                                compile_dirty: true,
-                                last_compiled_cmt: None,
-                                last_compiled_cmi: None,
-                            });
-                    } else {
-                        // remove last character of string: resi -> res, rei -> re, mli -> ml
-                        let mut implementation_filename = file.to_owned();
-                        implementation_filename.pop();
-                        match source_files.get(&implementation_filename) {
-                            None => {
-                                println!(
-                                "{}Warning: No implementation file found for interface file (skipping): {}",
-                                LINE_CLEAR, file
-                            )
-                            }
-                            Some(_) => {
-                                build_state
-                                    .modules
-                                    .entry(module_name.to_string())
-                                    .and_modify(|module| {
-                                        if let SourceType::SourceFile(ref mut source_file) =
-                                            module.source_type
-                                        {
-                                            source_file.interface = Some(Interface {
-                                                path: file.to_owned(),
-                                                parse_state: ParseState::Pending,
-                                                compile_state: CompileState::Pending,
-                                                last_modified: metadata.modified,
-                                                parse_dirty: true,
-                                            });
-                                        }
-                                    })
-                                    .or_insert(Module {
-                                        source_type: SourceType::SourceFile(SourceFile {
-                                            // this will be overwritten later
-                                            implementation: Implementation {
-                                                path: implementation_filename.to_string(),
-                                                parse_state: ParseState::Pending,
-                                                compile_state: CompileState::Pending,
-                                                last_modified: metadata.modified,
-                                                parse_dirty: true,
-                                            },
-                                            interface: Some(Interface {
-                                                path: file.to_owned(),
-                                                parse_state: ParseState::Pending,
-                                                compile_state: CompileState::Pending,
-                                                last_modified: metadata.modified,
-                                                parse_dirty: true,
-                                            }),
-                                        }),
-                                        deps: AHashSet::new(),
-                                        dependents: AHashSet::new(),
-                                        package_name: package.name.to_owned(),
-                                        compile_dirty: true,
-                                        last_compiled_cmt: None,
-                                        last_compiled_cmi: None,
-                                    });
-                            }
-                        }
+impl Default for HealthChecks {
+    fn default() -> Self {
+        Self {
+            cycles: true,
+            package_names: true,
+            module_collisions: true,
+            case_collisions: true,
+            missing_dependencies: true,
+            empty_packages: true,
+            namespace_collisions: true,
+            unreferenced_packages: true,
+        }
+    }
+}
+
+/// Runs all the (enabled) `checks` on a tree that was already built, for linting the structure of
+/// a project in one go. The diagnostics are sorted (like the ones of `make_with`), see
+/// `diagnostics::severity_counts` for a summary.
+pub fn health_check(
+    build: &AHashMap<String, Package>,
+    root_name: &str,
+    checks: &HealthChecks,
+) -> Vec<Diagnostic> {
+    let packages = || {
+        let mut packages = build
+            .values()
+            .filter(|package| !package.is_ignored)
+            .collect::<Vec<&Package>>();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        packages
+    };
+    let path_of = |name: &str| {
+        build
+            .get(name)
+            .map(|package| package.path.to_owned())
+            .unwrap_or_default()
+    };
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+
+    if checks.cycles {
+        if let Err(circular) = reverse_topological_order(build) {
+            diagnostics.push(Diagnostic::error(
+                DiagnosticKind::CircularDependency,
+                &path_of(circular.cycle.first().map(|name| name.as_str()).unwrap_or("")),
+                circular.to_string(),
+            ));
+        }
+    }
+    if checks.package_names {
+        diagnostics.extend(validate_package_names(build));
+        diagnostics.extend(validate_unique_package_paths(build));
+    }
+    if checks.module_collisions || checks.case_collisions {
+        let mut owners: AHashMap<&str, Vec<&Package>> = AHashMap::new();
+        packages().into_iter().for_each(|package| {
+            package
+                .modules
+                .iter()
+                .flatten()
+                .for_each(|module| owners.entry(module.as_str()).or_default().push(package))
+        });
+        let mut modules = owners.keys().copied().collect::<Vec<&str>>();
+        modules.sort();
+        if checks.module_collisions {
+            modules
+                .iter()
+                .filter(|module| owners[*module].len() > 1)
+                .for_each(|module| {
+                    let names = owners[module]
+                        .iter()
+                        .map(|package| package.name.to_owned())
+                        .collect::<Vec<String>>();
+                    diagnostics.push(Diagnostic::error(
+                        DiagnosticKind::DuplicateModule,
+                        &owners[module][0].path,
+                        format!(
+                            "The module {} is in multiple packages: {}",
+                            module,
+                            names.join(", ")
+                        ),
+                    ))
+                });
+        }
+        if checks.case_collisions {
+            let mut by_lowercase: AHashMap<String, Vec<&str>> = AHashMap::new();
+            modules.iter().for_each(|module| {
+                by_lowercase
+                    .entry(module.to_lowercase())
+                    .or_default()
+                    .push(module)
+            });
+            let mut collisions = by_lowercase
+                .into_values()
+                .filter(|modules| modules.len() > 1)
+                .collect::<Vec<Vec<&str>>>();
+            collisions.sort();
+            collisions.into_iter().for_each(|modules| {
+                diagnostics.push(Diagnostic::warning(
+                    DiagnosticKind::CaseCollision,
+                    &owners[modules[0]][0].path,
+                    format!(
+                        "The modules {} only differ in case, their files collide on a case-insensitive file system",
+                        modules.join(", ")
+                    ),
+                ))
+            });
+        }
+    }
+    if checks.missing_dependencies {
+        packages().into_iter().for_each(|package| {
+            package
+                .bsconfig
+                .bs_dependencies
+                .iter()
+                .flatten()
+                .filter(|dependency| !build.contains_key(*dependency))
+                .for_each(|dependency| {
+                    diagnostics.push(Diagnostic::error(
+                        DiagnosticKind::UnresolvedDependency,
+                        &package.path,
+                        format!(
+                            "The package {} depends on {}, which is not in the tree",
+                            package.name, dependency
+                        ),
+                    ))
+                })
+        });
+    }
+    if checks.empty_packages {
+        empty_packages(build).into_iter().for_each(|name| {
+            diagnostics.push(Diagnostic::warning(
+                DiagnosticKind::EmptyPackage,
+                &path_of(&name),
+                format!(
+                    "The package {} has no modules, are its sources configured correctly?",
+                    name
+                ),
+            ))
+        });
+    }
+    if checks.namespace_collisions {
+        let mut groups = packages_by_namespace(build)
+            .into_iter()
+            .filter(|(namespace, names)| !namespace.is_empty() && names.len() > 1)
+            .collect::<Vec<(String, Vec<String>)>>();
+        groups.sort();
+        groups.into_iter().for_each(|(namespace, names)| {
+            diagnostics.push(Diagnostic::error(
+                DiagnosticKind::NamespaceCollision,
+                &path_of(&names[0]),
+                format!(
+                    "The packages {} all have the namespace {}",
+                    names.join(", "),
+                    namespace
+                ),
+            ))
+        });
+    }
+    if checks.unreferenced_packages {
+        unreferenced_packages(build, root_name)
+            .into_iter()
+            .for_each(|name| {
+                diagnostics.push(Diagnostic::info(
+                    DiagnosticKind::UnreferencedPackage,
+                    &path_of(&name),
+                    format!("Nothing depends on the package {}", name),
+                ))
+            });
+    }
+
+    diagnostics::sort(&mut diagnostics);
+    diagnostics
+}
+
+/// An outline of the tree (like `npm ls`), starting at `root_name`: every package on its own
+/// line, indented by two spaces per level, with its namespace and number of modules. The
+/// dependencies of a package are only listed the first time it is printed, later occurrences
+/// (and cycles) are marked `deduped`.
+pub fn print_tree(build: &AHashMap<String, Package>, root_name: &str) -> String {
+    fn visit(
+        build: &AHashMap<String, Package>,
+        edges: &AHashMap<&String, Vec<&DependencyEdge>>,
+        package_name: &String,
+        depth: usize,
+        visited: &mut AHashSet<String>,
+        lines: &mut Vec<String>,
+    ) {
+        let indentation = "  ".repeat(depth);
+        if !visited.insert(package_name.to_owned()) {
+            lines.push(format!("{}{} deduped", indentation, package_name));
+            return;
+        }
+        let package = &build[package_name];
+        lines.push(format!(
+            "{}{} ({}, {} modules)",
+            indentation,
+            package_name,
+            package
+                .namespace
+                .to_suffix()
+                .unwrap_or("no namespace".to_string()),
+            package.modules.as_ref().map_or(0, |modules| modules.len())
+        ));
+        edges
+            .get(package_name)
+            .into_iter()
+            .flatten()
+            .for_each(|edge| visit(build, edges, &edge.to, depth + 1, visited, lines));
+    }
+
+    let dependency_edges = dependency_edges(build, true);
+    let mut edges: AHashMap<&String, Vec<&DependencyEdge>> = AHashMap::new();
+    dependency_edges
+        .iter()
+        .for_each(|edge| edges.entry(&edge.from).or_default().push(edge));
+    let mut lines = vec![];
+    if build.contains_key(root_name) {
+        visit(
+            build,
+            &edges,
+            &root_name.to_string(),
+            0,
+            &mut AHashSet::new(),
+            &mut lines,
+        );
+    }
+    lines.join("\n")
+}
+
+/// A path the watcher needs to watch. Recursive paths need their whole subtree watched.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WatchPath {
+    pub path: String,
+    pub recursive: bool,
+}
+
+/// The paths that need to be watched to pick up all changes to the tree: the source folders of
+/// every package (absolute), and their config files, since a config change needs a rebuild.
+/// Watching a recursive source folder recursively means watching a whole (deep) tree, so instead
+/// it is watched along with all of its current subfolders, non-recursively. A new subfolder is
+/// still caught, as its parent is watched (the watcher then builds the tree again, and starts
+/// watching it).
+pub fn watch_paths(build: &AHashMap<String, Package>) -> AHashSet<WatchPath> {
+    fn subfolders(dir: &Path, folders: &mut Vec<PathBuf>) {
+        child_folders(dir).into_iter().for_each(|folder| {
+            subfolders(&folder, folders);
+            folders.push(folder);
+        })
+    }
+
+    let mut paths: AHashSet<WatchPath> = AHashSet::new();
+    build
+        .values()
+        .filter(|package| !package.is_ignored)
+        .for_each(|package| {
+            paths.insert(WatchPath {
+                path: get_bsconfig_path(&package.path),
+                recursive: false,
+            });
+            package.source_folders.iter().for_each(|source| {
+                let dir = Path::new(&package.path).join(&source.dir);
+                let mut folders = vec![];
+                if source.subdirs == Some(bsconfig::Subdirs::Recurse(true)) {
+                    subfolders(&dir, &mut folders);
+                }
+                folders.push(dir);
+                paths.extend(folders.into_iter().map(|folder| WatchPath {
+                    path: folder.to_string_lossy().to_string(),
+                    recursive: false,
+                }));
+            })
+        });
+    paths
+}
+
+fn child_folders(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The smallest set of paths (sorted) that covers the same as `watch_paths`, for watchers that can
+/// watch recursively: a recursive source folder is one recursive path, and so is a folder of
+/// which all subfolders are (non-recursive) source folders too. The folders and config files in
+/// a recursively watched folder are left out. A folder with a subfolder that isn't a source is
+/// still watched non-recursively, so configuring one folder doesn't watch its whole tree.
+pub fn compressed_watch_paths(build: &AHashMap<String, Package>) -> Vec<WatchPath> {
+    // whether all the subfolders of `dir` are watched, stops at the first one that isn't
+    fn is_covered(dir: &Path, folders: &AHashSet<PathBuf>, roots: &[PathBuf]) -> bool {
+        child_folders(dir).iter().all(|folder| {
+            roots.iter().any(|root| folder.starts_with(root))
+                || (folders.contains(folder) && is_covered(folder, folders, roots))
+        })
+    }
+
+    let mut roots: Vec<PathBuf> = vec![];
+    let mut folders: AHashSet<PathBuf> = AHashSet::new();
+    let mut files: Vec<PathBuf> = vec![];
+    build
+        .values()
+        .filter(|package| !package.is_ignored)
+        .for_each(|package| {
+            files.push(PathBuf::from(get_bsconfig_path(&package.path)));
+            package.source_folders.iter().for_each(|source| {
+                let dir = Path::new(&package.path).join(&source.dir);
+                match source.subdirs == Some(bsconfig::Subdirs::Recurse(true)) {
+                    true => roots.push(dir),
+                    false => {
+                        folders.insert(dir);
                     }
+                }
+            })
+        });
+    let covered = folders
+        .iter()
+        .filter(|folder| is_covered(folder, &folders, &roots))
+        .cloned()
+        .collect::<Vec<PathBuf>>();
+    roots.extend(covered);
+    roots.sort();
+    roots.dedup();
+    // the roots are sorted, so a root that is in another root comes after it
+    let mut compressed: Vec<PathBuf> = vec![];
+    roots.into_iter().for_each(|root| {
+        if !compressed.iter().any(|other| root.starts_with(other)) {
+            compressed.push(root)
+        }
+    });
+
+    let mut paths = compressed
+        .iter()
+        .map(|root| WatchPath {
+            path: root.to_string_lossy().to_string(),
+            recursive: true,
+        })
+        .chain(
+            folders
+                .iter()
+                .chain(files.iter())
+                .filter(|path| !compressed.iter().any(|root| path.starts_with(root)))
+                .map(|path| WatchPath {
+                    path: path.to_string_lossy().to_string(),
+                    recursive: false,
                 }),
+        )
+        .collect::<Vec<WatchPath>>();
+    paths.sort_by(|a, b| a.path.cmp(&b.path));
+    paths
+}
+
+/// A cycle in the `bs-dependencies` of the packages: every package depends on the next one, and
+/// the last one on the first one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircularDependency {
+    pub cycle: Vec<String>,
+}
+
+impl fmt::Display for CircularDependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Circular dependency: {}", self.cycle.join(" -> "))?;
+        match self.cycle.first() {
+            Some(first) => write!(f, " -> {}", first),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The names of the packages in leaf-first order, the reverse of the build order: every package
+/// comes before the packages in its `bs-dependencies`, so the root comes first. This is the order
+/// to tear down in, or to aggregate over from the bottom up. Unlike `packages_in_build_order`,
+/// this fails on a dependency cycle, as there is no such order then.
+pub fn reverse_topological_order(
+    build: &AHashMap<String, Package>,
+) -> Result<Vec<String>, CircularDependency> {
+    fn visit(
+        build: &AHashMap<String, Package>,
+        package_name: &String,
+        visited: &mut AHashSet<String>,
+        stack: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), CircularDependency> {
+        if let Some(position) = stack.iter().position(|name| name == package_name) {
+            return Err(CircularDependency {
+                cycle: stack[position..].to_vec(),
+            });
+        }
+        if !visited.insert(package_name.to_owned()) {
+            return Ok(());
+        }
+        if let Some(package) = build.get(package_name) {
+            stack.push(package_name.to_owned());
+            let mut dependencies = package.bsconfig.bs_dependencies.to_owned().unwrap_or(vec![]);
+            dependencies.sort();
+            for dependency in dependencies
+                .iter()
+                .filter(|dependency| build.contains_key(*dependency))
+            {
+                visit(build, dependency, visited, stack, order)?;
             }
-        });
+            stack.pop();
+            order.push(package_name.to_owned());
+        }
+        Ok(())
+    }
+
+    let mut package_names = build.keys().collect::<Vec<&String>>();
+    package_names.sort();
+    let mut visited = AHashSet::new();
+    let mut order = vec![];
+    for package_name in package_names {
+        visit(build, package_name, &mut visited, &mut vec![], &mut order)?;
+    }
+    order.reverse();
+    Ok(order)
 }
 
-impl Package {
-    pub fn get_jsx_args(&self) -> Vec<String> {
-        self.bsconfig.get_jsx_args()
+/// The names of the packages in the order they need to be built: every package comes after the
+/// packages in its `bs-dependencies`. Ties are broken by name, so the order is deterministic. A
+/// dependency cycle between packages is broken at an arbitrary (but stable) point.
+pub fn packages_in_build_order(build: &AHashMap<String, Package>) -> Vec<String> {
+    fn visit(
+        build: &AHashMap<String, Package>,
+        package_name: &String,
+        visited: &mut AHashSet<String>,
+        order: &mut Vec<String>,
+    ) {
+        if !visited.insert(package_name.to_owned()) {
+            return;
+        }
+        if let Some(package) = build.get(package_name) {
+            let mut dependencies = package.bsconfig.bs_dependencies.to_owned().unwrap_or(vec![]);
+            dependencies.sort();
+            dependencies
+                .iter()
+                .filter(|dependency| build.contains_key(*dependency))
+                .for_each(|dependency| visit(build, dependency, visited, order));
+            order.push(package_name.to_owned());
+        }
+    }
+
+    let mut package_names = build.keys().collect::<Vec<&String>>();
+    package_names.sort();
+    let mut visited = AHashSet::new();
+    let mut order = vec![];
+    package_names
+        .into_iter()
+        .for_each(|package_name| visit(build, package_name, &mut visited, &mut order));
+    order
+}
+
+/// The `(package path, module name)` pairs of all modules in the tree, in an order where the
+/// modules of a package come after the modules of all of its dependencies. Within a package, the
+/// modules are sorted by name.
+pub fn modules_in_build_order(build: &AHashMap<String, Package>) -> Vec<(String, String)> {
+    packages_in_build_order(build)
+        .iter()
+        .flat_map(|package_name| {
+            let package = &build[package_name];
+            let mut modules = package
+                .modules
+                .as_ref()
+                .map(|modules| modules.iter().cloned().collect::<Vec<ModuleName>>())
+                .unwrap_or(vec![]);
+            modules.sort();
+            modules
+                .into_iter()
+                .map(|module_name| (package.path.to_owned(), module_name.into()))
+                .collect::<Vec<(String, String)>>()
+        })
+        .collect()
+}
+
+/// The names of the packages that can be reached from the root package(s) through the edges of
+/// the dependency graph, including the dev edges of the dev dependencies that are in the tree
+/// (see `MakeOptions::include_dev`).
+fn reachable_packages(build: &AHashMap<String, Package>) -> AHashSet<String> {
+    let mut edges: AHashMap<String, Vec<String>> = AHashMap::new();
+    dependency_edges(build, true)
+        .into_iter()
+        .for_each(|edge| edges.entry(edge.from).or_default().push(edge.to));
+    let mut reachable: AHashSet<String> = AHashSet::new();
+    let mut queue = build
+        .iter()
+        .filter(|(_key, package)| package.is_root)
+        .map(|(key, _package)| key.to_owned())
+        .collect::<Vec<String>>();
+    while let Some(package_name) = queue.pop() {
+        if reachable.insert(package_name.to_owned()) {
+            queue.extend(edges.get(&package_name).cloned().unwrap_or_default());
+        }
+    }
+    reachable
+}
+
+/// The names of the packages that need to be rebuilt after `changed_module` (with or without the
+/// namespace suffix) of the package `package_name` changed. A change to the implementation of a
+/// module with an interface only affects the package the module is in. A change to an interface,
+/// or to an implementation without one (which makes it the interface), also affects the packages
+/// that (transitively) depend on that package, as their compiled interfaces can include the types
+/// of the module. Empty when the package doesn't have the module.
+pub fn dependents_to_rebuild(
+    build: &AHashMap<String, Package>,
+    package_name: &str,
+    changed_module: &str,
+    is_interface_change: bool,
+) -> AHashSet<String> {
+    let Some(package) = build.get(package_name) else {
+        return AHashSet::new();
+    };
+    let suffixed = package
+        .namespace
+        .to_suffix()
+        .map(|suffix| format!("{}-{}", changed_module, suffix));
+    let Some(module_name) = std::iter::once(changed_module.to_string())
+        .chain(suffixed)
+        .find(|name| {
+            package
+                .modules
+                .as_ref()
+                .is_some_and(|modules| modules.contains(name.as_str()))
+        })
+    else {
+        return AHashSet::new();
+    };
+    let has_interface = package.source_file_paths().into_iter().any(|path| {
+        helpers::is_interface_file(&helpers::get_extension(path))
+            && helpers::file_path_to_module_name(path, &package.namespace) == module_name
+    });
+    let mut rebuild = AHashSet::from_iter([package_name.to_string()]);
+    if is_interface_change || !has_interface {
+        let mut queue = Vec::from_iter(rebuild.iter().cloned());
+        while let Some(package_name) = queue.pop() {
+            build
+                .iter()
+                .filter(|(_name, package)| {
+                    package
+                        .bsconfig
+                        .bs_dependencies
+                        .as_ref()
+                        .is_some_and(|dependencies| dependencies.contains(&package_name))
+                })
+                .for_each(|(name, _package)| {
+                    if rebuild.insert(name.to_owned()) {
+                        queue.push(name.to_owned());
+                    }
+                });
+        }
+    }
+    rebuild
+}
+
+/// The names of `package_name` and all packages it (transitively) depends on through
+/// `bs-dependencies`.
+pub fn transitive_dependencies(build: &AHashMap<String, Package>, package_name: &str) -> AHashSet<String> {
+    let mut closure: AHashSet<String> = AHashSet::new();
+    let mut queue = vec![package_name.to_string()];
+    while let Some(package_name) = queue.pop() {
+        if let Some(package) = build.get(&package_name) {
+            if closure.insert(package_name) {
+                queue.extend(package.bsconfig.bs_dependencies.to_owned().unwrap_or(vec![]));
+            }
+        }
+    }
+    closure
+}
+
+/// Explains why `package_name` is in the tree (like `npm ls <package>`): the dependency paths
+/// (lists of package names) from the root to the package, through `bs-dependencies`. As the
+/// number of paths can explode in a big tree, at most `max_paths` paths are returned.
+pub fn explain_package(
+    build: &AHashMap<String, Package>,
+    package_name: &str,
+    max_paths: usize,
+) -> Vec<Vec<String>> {
+    fn visit(
+        build: &AHashMap<String, Package>,
+        package_name: &str,
+        max_paths: usize,
+        path: &mut Vec<String>,
+        paths: &mut Vec<Vec<String>>,
+    ) {
+        let current = path.last().unwrap();
+        if current == package_name {
+            paths.push(path.to_owned());
+            return;
+        }
+        let mut dependencies = build[current]
+            .bsconfig
+            .bs_dependencies
+            .to_owned()
+            .unwrap_or(vec![]);
+        dependencies.sort();
+        for dependency in dependencies {
+            if paths.len() >= max_paths {
+                return;
+            }
+            // a cycle can't lead anywhere new
+            if build.contains_key(&dependency) && !path.contains(&dependency) {
+                path.push(dependency);
+                visit(build, package_name, max_paths, path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    let mut roots = build
+        .iter()
+        .filter(|(_name, package)| package.is_root)
+        .map(|(name, _package)| name.to_owned())
+        .collect::<Vec<String>>();
+    roots.sort();
+    let mut paths = vec![];
+    for root in roots {
+        if paths.len() < max_paths {
+            visit(build, package_name, max_paths, &mut vec![root], &mut paths);
+        }
     }
+    paths
+}
+
+/// The paths of the packages that need to be built before `module_name` can be compiled: the
+/// transitive dependencies of the package the module is in. A module name can be given with or
+/// without the namespace suffix. As multiple packages can have a module with the same name, the
+/// result is keyed by the path of the package the module was found in.
+pub fn module_dependency_closure(
+    build: &AHashMap<String, Package>,
+    module_name: &str,
+) -> AHashMap<String, AHashSet<String>> {
+    build
+        .iter()
+        .filter(|(_name, package)| {
+            package.modules.as_ref().is_some_and(|modules| {
+                modules.iter().any(|module| {
+                    module == &module_name || module.as_str().split('-').next() == Some(module_name)
+                })
+            })
+        })
+        .map(|(name, package)| {
+            let closure = transitive_dependencies(build, name)
+                .iter()
+                .filter(|dependency| *dependency != name)
+                .map(|dependency| build[dependency].path.to_owned())
+                .collect::<AHashSet<String>>();
+            (package.path.to_owned(), closure)
+        })
+        .collect()
+}
+
+/// The module names that a source file refers to, found with a textual scan for `open X`,
+/// `include X`, `module M = X` and `X.y`. This is conservative: it doesn't know about comments,
+/// strings, or local modules that shadow a module of the package (so it can find too much).
+fn referenced_module_names(contents: &str) -> AHashSet<String> {
+    static REFERENCES: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let references = REFERENCES.get_or_init(|| {
+        regex::Regex::new(
+            r"(?:\b(?:open|include)!?\s+|\bmodule\s+[A-Z][A-Za-z0-9_']*\s*=\s*)([A-Z][A-Za-z0-9_']*)|\b([A-Z][A-Za-z0-9_']*)\.",
+        )
+        .expect("Could not parse regex")
+    });
+    references
+        .captures_iter(contents)
+        .filter_map(|captures| captures.get(1).or(captures.get(2)))
+        .map(|reference| reference.as_str().to_string())
+        .collect()
+}
+
+/// The module level dependency graph: for every module, the modules it refers to, in its own
+/// package or in one of the packages it depends on (a namespaced dependency is referred to by its
+/// namespace module). Modules are keyed by their compiler name, like in `Package::modules`. It is
+/// based on `referenced_module_names`, so it has the same limitations.
+pub fn build_module_graph(build: &AHashMap<String, Package>) -> AHashMap<String, AHashSet<String>> {
+    build
+        .par_iter()
+        .filter(|(_name, package)| !package.is_ignored)
+        .flat_map(|(_name, package)| {
+            let own_modules = package.modules.to_owned().unwrap_or_default();
+            let dependency_modules = package
+                .bsconfig
+                .bs_dependencies
+                .to_owned()
+                .unwrap_or(vec![])
+                .iter()
+                .filter_map(|dependency| build.get(dependency))
+                .flat_map(|dependency| dependency.modules.to_owned().unwrap_or_default())
+                .collect::<AHashSet<ModuleName>>();
+            let resolve = |reference: &str| {
+                let namespaced = package
+                    .namespace
+                    .to_suffix()
+                    .map(|suffix| reference.to_string() + "-" + &suffix);
+                match namespaced {
+                    Some(namespaced) if own_modules.contains(namespaced.as_str()) => Some(namespaced),
+                    _ if own_modules.contains(reference) || dependency_modules.contains(reference) => {
+                        Some(reference.to_string())
+                    }
+                    _ => None,
+                }
+            };
+            package
+                .source_file_paths()
+                .into_iter()
+                .map(|path| {
+                    let module_name = helpers::file_path_to_module_name(path, &package.namespace);
+                    let references = fs::read_to_string(Path::new(&package.path).join(path))
+                        .map(|contents| referenced_module_names(&contents))
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|reference| resolve(reference))
+                        .filter(|reference| *reference != module_name)
+                        .collect::<AHashSet<String>>();
+                    (module_name, references)
+                })
+                .collect::<Vec<(String, AHashSet<String>)>>()
+        })
+        .collect::<Vec<(String, AHashSet<String>)>>()
+        .into_iter()
+        .fold(AHashMap::new(), |mut graph, (module_name, references)| {
+            // the interface and the implementation of a module are merged
+            graph
+                .entry(module_name)
+                .or_insert_with(AHashSet::new)
+                .extend(references);
+            graph
+        })
+}
+
+/// Adds `dependency_name` to the `bs-dependencies` of the package at `parent_path` (for a
+/// dependency that was installed while watching), and reads the packages of it and its subtree
+/// that aren't in the tree yet, like `make_with` would (with the filter and the options the tree
+/// was built with). The packages that are already in the tree are not read or scanned again, and
+/// the new diagnostics are added to the ones of the tree. With `MakeOptions::fail_on`, the tree is
+/// left as it is when there are new diagnostics of at least that severity. Returns the (sorted) paths of the added packages, or why the dependency can't be
+/// found.
+pub fn add_dependency(
+    tree: &mut PackageTree,
+    parent_path: &str,
+    dependency_name: &str,
+) -> Result<Vec<String>, String> {
+    let mut build = tree.packages.to_owned();
+    let options = &tree.options;
+    let root_path = build
+        .values()
+        .find(|package| package.is_root)
+        .map(|root| root.path.to_owned())
+        .ok_or("There is no root package in the tree".to_string())?;
+    let parent = build
+        .values()
+        .find(|package| package.path == parent_path)
+        .ok_or(format!("There is no package at {}", parent_path))?;
+    let parent_bsconfig = bsconfig::Config {
+        bs_dependencies: Some(vec![dependency_name.to_string()]),
+        pinned_dependencies: parent.bsconfig.pinned_dependencies.to_owned(),
+        ..Default::default()
+    };
+
+    let mut registered_dependencies_set = build.keys().cloned().collect::<AHashSet<String>>();
+    let dependencies = flatten_dependencies(read_dependencies(
+        &mut registered_dependencies_set,
+        &parent_bsconfig,
+        parent_path,
+        &root_path,
+        tree.workspace_root.to_owned(),
+        options,
+        false,
+    ));
+    if let Some((error, _is_peer)) = dependencies
+        .iter()
+        .find(|d| d.name == dependency_name)
+        .and_then(|d| d.unresolved.to_owned())
+    {
+        return Err(error);
+    }
+
+    let diagnostics = Diagnostics::new();
+    let node_modules_overrides = canonical_node_modules_overrides(&root_path, options);
+    let mut added: AHashSet<String> = AHashSet::new();
+    dependencies.iter().for_each(|d| {
+        if let Some(diagnostic) = unresolved_dependency_diagnostic(d) {
+            diagnostics.push(diagnostic);
+        } else if !build.contains_key(&d.name) {
+            let package = make_dependency_package(d, &node_modules_overrides, &diagnostics);
+            build.insert(d.name.to_owned(), package);
+            added.insert(d.name.to_owned());
+        }
+    });
+    if let Some(parent) = build.values_mut().find(|package| package.path == parent_path) {
+        let dependencies = parent.bsconfig.bs_dependencies.get_or_insert_with(Vec::new);
+        if !dependencies
+            .iter()
+            .any(|dependency| dependency == dependency_name)
+        {
+            dependencies.push(dependency_name.to_string());
+        }
+    }
+
+    complete_packages(
+        &tree.filter,
+        &root_path,
+        &mut build,
+        &added,
+        &AHashSet::new(),
+        options,
+        &diagnostics,
+    );
+    // the checks see the whole tree, so only what they didn't report before is new
+    let mut diagnostics = diagnostics.into_sorted();
+    diagnostics.retain(|diagnostic| !tree.diagnostics.contains(diagnostic));
+    check_fail_on(&diagnostics, options).map_err(|e| e.to_string())?;
+
+    // a package that wasn't scanned before the deadline is left out
+    let added = added
+        .iter()
+        .filter_map(|name| build.get(name))
+        .collect::<Vec<&Package>>();
+    create_build_dirs(added.iter().copied());
+    let mut added_paths = added
+        .iter()
+        .map(|package| package.path.to_owned())
+        .collect::<Vec<String>>();
+    added_paths.sort();
+    tree.packages = build;
+    tree.diagnostics.extend(diagnostics);
+    diagnostics::sort(&mut tree.diagnostics);
+    tree.edges = dependency_edges(&tree.packages, tree.options.include_dev);
+    tree.closures = closures(&tree.packages, &tree.edges);
+    Ok(added_paths)
+}
+
+/// Removes `dependency_name` from the `bs-dependencies` of the package at `parent_path`, and
+/// removes the packages that can't be reached anymore from the tree. Packages that are still
+/// depended on through another path stay. Returns the (sorted) paths of the removed packages.
+pub fn remove_dependency(
+    build: &mut AHashMap<String, Package>,
+    parent_path: &str,
+    dependency_name: &str,
+) -> Vec<String> {
+    match build.values_mut().find(|package| package.path == parent_path) {
+        Some(parent) => {
+            if let Some(dependencies) = parent.bsconfig.bs_dependencies.as_mut() {
+                dependencies.retain(|dependency| dependency != dependency_name)
+            }
+        }
+        None => return vec![],
+    }
+
+    let reachable = reachable_packages(build);
+    let mut removed = vec![];
+    build.retain(|package_name, package| {
+        let keep = reachable.contains(package_name);
+        if !keep {
+            removed.push(package.path.to_owned());
+        }
+        keep
+    });
+    removed.sort();
+    removed
+}
+
+/// The module names that change when the namespace of `package` changes to `namespace`: the old
+/// and the new name of every module, and the namespace modules that go away or come in. The
+/// watcher needs to invalidate everything that refers to one of these. Empty when the namespace
+/// stays the same.
+pub fn modules_affected_by_namespace_change(package: &Package, namespace: &Namespace) -> AHashSet<String> {
+    let module_names = |namespace: &Namespace| {
+        let mut package = package.to_owned();
+        package.namespace = namespace.to_owned();
+        let _ = package.recompute_modules();
+        package
+            .modules
+            .unwrap_or_default()
+            .into_iter()
+            .map(String::from)
+            .collect::<AHashSet<String>>()
+    };
+    let old = module_names(&package.namespace);
+    let new = module_names(namespace);
+    old.symmetric_difference(&new).cloned().collect()
+}
+
+/// The modules that need to be (re)compiled because their artifact in `lib/bs` (the `.cmj` of an
+/// implementation, the `.cmti` of an interface) is missing, or older than the source file. This
+/// only looks at modification times, so it's cheap, but it doesn't know about dependencies.
+pub fn stale_modules(build: &AHashMap<String, Package>) -> AHashSet<String> {
+    build
+        .par_iter()
+        .filter(|(_name, package)| !package.is_ignored)
+        .flat_map(|(_name, package)| {
+            package
+                .source_file_paths()
+                .into_iter()
+                .filter_map(|path| {
+                    let extension = if helpers::is_interface_file(&helpers::get_extension(path)) {
+                        "cmti"
+                    } else {
+                        "cmj"
+                    };
+                    let module_name = helpers::file_path_to_module_name(path, &package.namespace);
+                    let artifact = Path::new(&package.get_bs_build_path())
+                        .join(Path::new(path).parent().unwrap())
+                        .join(module_name.to_owned() + "." + extension);
+                    let source_modified = match package
+                        .source_files
+                        .as_ref()
+                        .and_then(|source_files| source_files.get(path))
+                    {
+                        Some(meta) => Some(meta.modified),
+                        // the metadata was dropped, see `MakeOptions::drop_source_metadata`
+                        None => fs::metadata(Path::new(&package.path).join(path))
+                            .and_then(|metadata| metadata.modified())
+                            .ok(),
+                    };
+                    let artifact_modified = fs::metadata(artifact)
+                        .and_then(|metadata| metadata.modified())
+                        .ok();
+                    match (source_modified, artifact_modified) {
+                        (Some(source_modified), Some(artifact_modified))
+                            if artifact_modified >= source_modified =>
+                        {
+                            None
+                        }
+                        _ => Some(module_name),
+                    }
+                })
+                .collect::<Vec<String>>()
+        })
+        .collect::<Vec<String>>()
+        .into_iter()
+        .collect()
+}
+
+/// The modules that need to be recompiled when `changed_modules` changed: those, and every module
+/// that (transitively) refers to one of them in the module graph (see `build_module_graph`). The
+/// modules in a cycle refer to each other, so they are all stale as soon as one of them is.
+pub fn stale_modules_transitive(
+    build: &AHashMap<String, Package>,
+    changed_modules: &AHashSet<String>,
+) -> AHashSet<String> {
+    let mut referenced_by: AHashMap<String, Vec<String>> = AHashMap::new();
+    build_module_graph(build)
+        .into_iter()
+        .for_each(|(module_name, references)| {
+            references.into_iter().for_each(|reference| {
+                referenced_by
+                    .entry(reference)
+                    .or_default()
+                    .push(module_name.to_owned())
+            })
+        });
+    let mut stale: AHashSet<String> = AHashSet::new();
+    let mut queue = changed_modules.iter().cloned().collect::<Vec<String>>();
+    while let Some(module_name) = queue.pop() {
+        if stale.insert(module_name.to_owned()) {
+            queue.extend(referenced_by.get(&module_name).cloned().unwrap_or_default());
+        }
+    }
+    stale
+}
+
+/// The source files (as `(package name, path)`, sorted) of one kind (interfaces or
+/// implementations) that don't have a file of the other kind for the same module.
+fn unpaired_source_files(build: &AHashMap<String, Package>, interfaces: bool) -> Vec<(String, String)> {
+    let mut unpaired = build
+        .iter()
+        .filter(|(_name, package)| !package.is_ignored)
+        .flat_map(|(name, package)| {
+            let source_files = package.source_file_paths();
+            let (of_kind, other_kind): (Vec<&String>, Vec<&String>) = source_files
+                .into_iter()
+                .partition(|path| helpers::is_interface_file(&helpers::get_extension(path)) == interfaces);
+            let other_modules = other_kind
+                .iter()
+                .map(|path| helpers::file_path_to_module_name(path, &package.namespace))
+                .collect::<AHashSet<String>>();
+            of_kind
+                .into_iter()
+                .filter(|path| {
+                    !other_modules.contains(&helpers::file_path_to_module_name(path, &package.namespace))
+                })
+                .map(|path| (name.to_owned(), path.to_owned()))
+                .collect::<Vec<(String, String)>>()
+        })
+        .collect::<Vec<(String, String)>>();
+    unpaired.sort();
+    unpaired
+}
+
+/// The implementation files (as `(package name, path)`) that have no interface file. Some teams
+/// lint against these.
+pub fn modules_without_interface(build: &AHashMap<String, Package>) -> Vec<(String, String)> {
+    unpaired_source_files(build, false)
+}
+
+/// The interface files (as `(package name, path)`) that have no implementation file. These are
+/// skipped by the build, so this is almost always a bug.
+pub fn interfaces_without_implementation(build: &AHashMap<String, Package>) -> Vec<(String, String)> {
+    unpaired_source_files(build, true)
+}
+
+/// The version of the format `save_tree` writes. A cache in any other format is not loaded.
+const TREE_CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct TreeCache {
+    version: u32,
+    rewatch_version: String,
+    packages: AHashMap<String, Package>,
+}
+
+/// Writes the tree to `path` (as json), to load it again with `load_tree` in a later run instead
+/// of building it from scratch. The source files are stored with their modification times and
+/// sizes, so the loaded tree can be checked for changes.
+pub fn save_tree(build: &AHashMap<String, Package>, path: &str) -> std::io::Result<()> {
+    let mut packages = build.to_owned();
+    packages
+        .values_mut()
+        .filter(|package| !package.is_ignored)
+        .for_each(|package| package.cache_stamp = Some(cache_stamp(package)));
+    let cache = TreeCache {
+        version: TREE_CACHE_VERSION,
+        rewatch_version: env!("CARGO_PKG_VERSION").to_string(),
+        packages,
+    };
+    fs::write(path, serde_json::to_string(&cache)?)
+}
+
+/// Loads a tree that was written by `save_tree`. A cache that is missing, unreadable, or written
+/// by another version (of the format or of rewatch) is treated as absent, so the tree needs to be
+/// built again.
+pub fn load_tree(path: &str) -> Option<AHashMap<String, Package>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let cache = serde_json::from_str::<TreeCache>(&contents).ok()?;
+    (cache.version == TREE_CACHE_VERSION && cache.rewatch_version == env!("CARGO_PKG_VERSION"))
+        .then_some(cache.packages)
+}
+
+/// What `reconcile_cache` found to be different on disk from a cached tree. The files are
+/// `(package name, path)`, and everything is sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDiff {
+    pub added_files: Vec<(String, String)>,
+    pub removed_files: Vec<(String, String)>,
+    // files with another modification time or size than the cached one
+    pub modified_files: Vec<(String, String)>,
+    // packages with a changed config, with what that change needs (see `classify_config_change`)
+    pub changed_configs: Vec<(String, bsconfig::ConfigChangeKind)>,
+    // packages of which the folder (or the config) is gone, these are left out of the tree
+    pub removed_packages: Vec<String>,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self == &TreeDiff::default()
+    }
+}
+
+/// Brings a tree that was loaded with `load_tree` up to date with the disk, and returns what
+/// changed. Only the packages of which the config or one of the folders changed since the tree
+/// was saved (see `CacheStamp`) are scanned again, like `make_with` does (with the `filter` and
+/// the `options` the tree was built with), of the other packages only the source files are
+/// checked for modifications. The diagnostics are those of the checks of `make_with`, and a
+/// package that wasn't scanned again before the deadline is one of the removed packages. This
+/// doesn't resolve any dependencies, so when a config changed its dependencies
+/// (`ConfigChangeKind::Restructure`), the tree needs to be built again with `make`.
+pub fn reconcile_cache(
+    cached: AHashMap<String, Package>,
+    root_folder: &str,
+    filter: &Option<regex::Regex>,
+    options: &MakeOptions,
+) -> (AHashMap<String, Package>, TreeDiff, Vec<Diagnostic>) {
+    let mut diff = TreeDiff::default();
+    let names_by_path = cached
+        .iter()
+        .map(|(name, package)| (PathBuf::from(&package.path), name.to_owned()))
+        .collect::<AHashMap<PathBuf, String>>();
+    let mut cached_packages: AHashMap<String, Package> = AHashMap::new();
+    // the packages of which the config is read again, and the ones that only need a scan
+    let mut read: AHashSet<String> = AHashSet::new();
+    let mut rescan: AHashSet<String> = AHashSet::new();
+    let mut build: AHashMap<String, Package> = AHashMap::new();
+    cached.into_iter().for_each(|(name, mut package)| {
+        // ignored packages were never read
+        if package.is_ignored {
+            build.insert(name, package);
+            return;
+        }
+        if !Path::new(&package.path).is_dir() {
+            diff.removed_packages.push(name);
+            return;
+        }
+        let stamp = package.cache_stamp.take();
+        let current = cache_stamp(&package);
+        let mut needs_scan = stamp
+            .as_ref()
+            .is_none_or(|stamp| stamp.folders != current.folders);
+        // the runtime has no config of its own
+        let config_changed = stamp
+            .as_ref()
+            .is_none_or(|stamp| stamp.config_modified != current.config_modified);
+        if config_changed && !package.is_runtime {
+            let mut bsconfig = match bsconfig::try_read(&get_bsconfig_path(&package.path)) {
+                Ok(bsconfig) => bsconfig,
+                Err(_) => {
+                    diff.removed_packages.push(name);
+                    return;
+                }
+            };
+            normalize_dependencies(&mut bsconfig, &package, &names_by_path, options);
+            match bsconfig::classify_config_change(&package.bsconfig, &bsconfig) {
+                bsconfig::ConfigChangeKind::None => package.bsconfig = bsconfig,
+                bsconfig::ConfigChangeKind::Recompile => {
+                    diff.changed_configs
+                        .push((name.to_owned(), bsconfig::ConfigChangeKind::Recompile));
+                    package.bsconfig = bsconfig;
+                }
+                kind => {
+                    diff.changed_configs.push((name.to_owned(), kind));
+                    let mut changed =
+                        make_package(bsconfig, &package.path, package.is_pinned_dep, package.is_root);
+                    changed.is_local = package.is_local;
+                    changed.source_files = package.source_files.to_owned();
+                    changed.source_paths = package.source_paths.to_owned();
+                    package = changed;
+                    read.insert(name.to_owned());
+                    needs_scan = true;
+                }
+            }
+        }
+        // the folders didn't change, so neither did the files in them, but they can be modified
+        // (the modification time of an archive is in the stamp)
+        if !needs_scan && package.bsconfig.sources_archive.is_none() {
+            let package_dir = PathBuf::from(&package.path);
+            match package.source_files.as_mut() {
+                Some(source_files) => {
+                    for (path, meta) in source_files.iter_mut() {
+                        match fs::metadata(package_dir.join(path)) {
+                            Ok(metadata) => {
+                                let modified = metadata.modified().unwrap_or(meta.modified);
+                                if (modified, metadata.len()) != (meta.modified, meta.len) {
+                                    diff.modified_files.push((name.to_owned(), path.to_owned()));
+                                    meta.modified = modified;
+                                    meta.len = metadata.len();
+                                }
+                            }
+                            Err(_) => needs_scan = true,
+                        }
+                    }
+                }
+                // without the metadata, a modification can't be told
+                None => needs_scan = true,
+            }
+        }
+        match needs_scan {
+            true => {
+                cached_packages.insert(name.to_owned(), package.to_owned());
+                if !read.contains(&name) {
+                    rescan.insert(name.to_owned());
+                }
+            }
+            false => package.cache_stamp = Some(cache_stamp(&package)),
+        }
+        build.insert(name, package);
+    });
+
+    let diagnostics = Diagnostics::new();
+    complete_packages(
+        filter,
+        root_folder,
+        &mut build,
+        &read,
+        &rescan,
+        options,
+        &diagnostics,
+    );
+    cached_packages.iter().for_each(|(name, cached)| {
+        // it wasn't scanned before the deadline
+        let Some(package) = build.get_mut(name) else {
+            diff.removed_packages.push(name.to_owned());
+            return;
+        };
+        let cached_paths = cached
+            .source_file_paths()
+            .into_iter()
+            .collect::<AHashSet<&String>>();
+        let current_paths = package
+            .source_file_paths()
+            .into_iter()
+            .collect::<AHashSet<&String>>();
+        current_paths.iter().for_each(|path| {
+            let meta = |package: &Package| {
+                package
+                    .source_files
+                    .as_ref()
+                    .and_then(|source_files| source_files.get(*path))
+                    .map(|meta| (meta.modified, meta.len))
+            };
+            if !cached_paths.contains(path) {
+                diff.added_files.push((name.to_owned(), path.to_string()))
+            } else if meta(cached) != meta(package) {
+                diff.modified_files.push((name.to_owned(), path.to_string()))
+            }
+        });
+        cached_paths
+            .difference(&current_paths)
+            .for_each(|path| diff.removed_files.push((name.to_owned(), path.to_string())));
+        package.cache_stamp = Some(cache_stamp(package));
+    });
+    diff.added_files.sort();
+    diff.removed_files.sort();
+    diff.modified_files.sort();
+    diff.changed_configs.sort_by(|a, b| a.0.cmp(&b.0));
+    diff.removed_packages.sort();
+    (build, diff, diagnostics.into_sorted())
+}
+
+/// What a cached package looked like on disk when the tree was saved, so `reconcile_cache` can
+/// tell whether it changed without scanning it again
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStamp {
+    // the modification time of the config
+    pub config_modified: Option<SystemTime>,
+    // the modification times of the source folders, the folders with source files, and the
+    // folders in between (or of the sources archive), `None` when it doesn't exist. Adding or
+    // removing a file changes the one of its folder.
+    pub folders: AHashMap<PathBuf, Option<SystemTime>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// The `CacheStamp` of a package as it is on disk now. A folder without any source files in or
+/// below it is not in there, so a file that is added to it is only found by a scan.
+fn cache_stamp(package: &Package) -> CacheStamp {
+    let package_dir = Path::new(&package.path);
+    let source_dirs = package
+        .source_folders
+        .iter()
+        .map(|source| PathBuf::from(&source.dir))
+        .collect::<Vec<PathBuf>>();
+    let mut folders = source_dirs.iter().cloned().collect::<AHashSet<PathBuf>>();
+    package.dirs.iter().flatten().for_each(|dir| {
+        dir.ancestors()
+            .filter(|folder| {
+                source_dirs
+                    .iter()
+                    .any(|source_dir| folder.starts_with(source_dir))
+            })
+            .for_each(|folder| {
+                folders.insert(folder.to_path_buf());
+            })
+    });
+    if let Some(archive) = &package.bsconfig.sources_archive {
+        folders.insert(PathBuf::from(archive));
+    }
+    CacheStamp {
+        config_modified: modified(Path::new(&get_bsconfig_path(&package.path))),
+        folders: folders
+            .into_iter()
+            .map(|folder| {
+                let modified = modified(&package_dir.join(&folder));
+                (folder, modified)
+            })
+            .collect(),
+    }
+}
+
+/// Makes the dependencies of a config that is read again for a cached package like `make` has
+/// them in the tree, so the configs can be compared: a dependency on a path is named (see
+/// `name_path_dependencies`), and a dependency that is dev only (see
+/// `MakeOptions::production_only`) is left out.
+fn normalize_dependencies(
+    bsconfig: &mut bsconfig::Config,
+    package: &Package,
+    names_by_path: &AHashMap<PathBuf, String>,
+    options: &MakeOptions,
+) {
+    let package_path = Path::new(&package.path);
+    let name = |dependency: &mut String| {
+        if let Some(name) = package_path
+            .join(&*dependency)
+            .canonicalize()
+            .ok()
+            .filter(|_path| helpers::is_path_dependency(dependency))
+            .and_then(|path| names_by_path.get(&path))
+        {
+            *dependency = name.to_owned();
+        }
+    };
+    if package.is_root && options.production_only && !options.include_dev {
+        let dev_only = dev_only_dependencies(&package.path);
+        if let Some(dependencies) = bsconfig.bs_dependencies.as_mut() {
+            dependencies.retain(|dependency| !dev_only.contains(dependency))
+        }
+    }
+    [
+        &mut bsconfig.bs_dependencies,
+        &mut bsconfig.bs_dev_dependencies,
+        &mut bsconfig.pinned_dependencies,
+    ]
+    .into_iter()
+    .flatten()
+    .for_each(|dependencies| dependencies.iter_mut().for_each(name));
+}
+
+/// A hash of the logical tree: the packages, their modules and (the contents of) their config
+/// files. The same tree always gets the same fingerprint, and adding or removing a module, or
+/// changing a config changes it. Changes to the contents of the source files don't.
+pub fn tree_fingerprint(build: &AHashMap<String, Package>) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    let mut package_names = build.keys().collect::<Vec<&String>>();
+    package_names.sort();
+    package_names.into_iter().for_each(|package_name| {
+        let package = &build[package_name];
+        hasher.update(package_name.as_bytes());
+        hasher.update(b"\0");
+        if let Some(config_hash) = helpers::compute_file_hash(&get_bsconfig_path(&package.path)) {
+            hasher.update(config_hash.as_bytes());
+        }
+        let mut modules = package
+            .modules
+            .as_ref()
+            .map(|modules| modules.iter().collect::<Vec<&ModuleName>>())
+            .unwrap_or(vec![]);
+        modules.sort();
+        modules.into_iter().for_each(|module_name| {
+            hasher.update(module_name.as_str().as_bytes());
+            hasher.update(b"\0");
+        });
+        hasher.update(b"\0");
+    });
+    hasher.finalize()
+}
+
+/// Finds source files with byte-identical contents (for instance a generated file that got copied
+/// into multiple packages). Every source file in the tree is hashed, so this isn't part of `make`
+/// and needs to be called explicitly. Returns the clusters of absolute paths that share the same
+/// contents, sorted so the result is deterministic.
+pub fn duplicate_file_report(build: &AHashMap<String, Package>) -> Vec<Vec<String>> {
+    let mut clusters: AHashMap<blake3::Hash, Vec<String>> = AHashMap::new();
+    build
+        .values()
+        .flat_map(|package| {
+            package
+                .source_file_paths()
+                .into_iter()
+                .map(|file| Path::new(&package.path).join(file).to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+        })
+        .collect::<Vec<String>>()
+        .par_iter()
+        .filter_map(|path| helpers::compute_file_hash(path).map(|hash| (hash, path.to_owned())))
+        .collect::<Vec<(blake3::Hash, String)>>()
+        .into_iter()
+        .for_each(|(hash, path)| clusters.entry(hash).or_default().push(path));
+
+    let mut duplicates = clusters
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            paths
+        })
+        .collect::<Vec<Vec<String>>>();
+    duplicates.sort();
+    duplicates
+}
+
+/// The effective config of the package in `package_dir`, for debugging: the settings it is
+/// actually compiled with. The `extends` are already merged when the config is read; on top of
+/// that, jsx and uncurried come from the root package, the module format from the root's
+/// package-specs, the suffix is `Package::js_suffix`, and absent dependency lists are empty.
+/// `None` when there is no (root) package in that folder.
+pub fn resolved_config(build: &AHashMap<String, Package>, package_dir: &str) -> Option<bsconfig::Config> {
+    let package_dir = Path::new(package_dir)
+        .canonicalize()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(package_dir.to_string());
+    let package = build.values().find(|package| package.path == package_dir)?;
+    let root = build.values().find(|package| package.is_root)?;
+
+    let mut config = package.bsconfig.to_owned();
+    let suffix = package.js_suffix(root);
+    config.package_specs = Some(bsconfig::OneOrMore::Single(bsconfig::PackageSpec {
+        module: root.bsconfig.get_module(),
+        in_source: package.is_in_source(),
+        suffix: Some(suffix.to_owned()),
+        output_dir: package.bsconfig.get_output_dir(),
+    }));
+    config.suffix = Some(suffix);
+    config.jsx = root.bsconfig.jsx.to_owned();
+    config.jsx_mode = root.bsconfig.jsx_mode.to_owned();
+    config.jsx_module = root.bsconfig.jsx_module.to_owned();
+    config.reason = root.bsconfig.reason.to_owned();
+    config.uncurried = root.bsconfig.uncurried;
+    [
+        &mut config.bs_dependencies,
+        &mut config.bs_dev_dependencies,
+        &mut config.pinned_dependencies,
+    ]
+    .into_iter()
+    .for_each(|dependencies| {
+        dependencies.get_or_insert_with(Vec::new);
+    });
+    Some(config)
+}
+
+pub fn get_package_name(path: &str) -> String {
+    let bsconfig = read_bsconfig(path);
+    bsconfig.name
+}
+
+/// A tree that was built with `MakeOptions::drop_source_metadata` can't be built, as the build
+/// needs the modification times of the source files
+#[derive(Debug, Clone)]
+pub struct MissingSourceMetadata {
+    pub package: String,
+}
+
+impl fmt::Display for MissingSourceMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The source metadata of package {} was dropped (see MakeOptions::drop_source_metadata), but it is needed to build",
+            self.package
+        )
+    }
+}
+
+impl error::Error for MissingSourceMetadata {}
+
+pub fn parse_packages(build_state: &mut BuildState) -> Result<(), MissingSourceMetadata> {
+    if let Some(package) = build_state
+        .packages
+        .values()
+        .find(|package| package.source_files.is_none() && package.source_paths.is_some())
+    {
+        return Err(MissingSourceMetadata {
+            package: package.name.to_owned(),
+        });
+    }
+    build_state
+        .packages
+        .clone()
+        .iter()
+        .for_each(|(package_name, package)| {
+            debug!("Parsing package: {}", package_name);
+            if let Some(package_modules) = package.modules.to_owned() {
+                build_state
+                    .module_names
+                    .extend(package_modules.into_iter().map(String::from))
+            }
+            let build_path_abs = package.get_build_path();
+            let bs_build_path = package.get_bs_build_path();
+            helpers::create_build_path(&build_path_abs);
+            helpers::create_build_path(&bs_build_path);
+
+            package.namespace.to_suffix().iter().for_each(|namespace| {
+                // generate the mlmap "AST" file for modules that have a namespace configured
+                let source_files = match package.source_files.to_owned() {
+                    Some(source_files) => source_files
+                        .keys()
+                        .map(|key| key.to_owned())
+                        .collect::<Vec<String>>(),
+                    None => unreachable!(),
+                };
+                let entry = match &package.namespace {
+                    packages::Namespace::NamespaceWithEntry { entry, namespace: _ } => Some(entry),
+                    _ => None,
+                };
+
+                let depending_modules = source_files
+                    .iter()
+                    .map(|path| helpers::file_path_to_module_name(path, &packages::Namespace::NoNamespace))
+                    .filter(|module_name| {
+                        if let Some(entry) = entry {
+                            module_name != entry
+                        } else {
+                            true
+                        }
+                    })
+                    .filter(|module_name| helpers::is_non_exotic_module_name(module_name))
+                    .collect::<AHashSet<String>>();
+
+                let mlmap = namespaces::gen_mlmap(package, namespace, &depending_modules);
+
+                // mlmap will be compiled in the AST generation step
+                // compile_mlmap(&package, namespace, &project_root);
+                let deps = source_files
+                    .iter()
+                    .filter(|path| {
+                        helpers::is_non_exotic_module_name(&helpers::file_path_to_module_name(
+                            path,
+                            &packages::Namespace::NoNamespace,
+                        ))
+                    })
+                    .map(|path| helpers::file_path_to_module_name(path, &package.namespace))
+                    .filter(|module_name| {
+                        if let Some(entry) = entry {
+                            module_name != entry
+                        } else {
+                            true
+                        }
+                    })
+                    .collect::<AHashSet<String>>();
+
+                build_state.insert_module(
+                    &helpers::file_path_to_module_name(&mlmap.to_owned(), &packages::Namespace::NoNamespace),
+                    Module {
+                        source_type: SourceType::MlMap(MlMap { parse_dirty: false }),
+                        deps,
+                        dependents: AHashSet::new(),
+                        package_name: package.name.to_owned(),
+                        compile_dirty: false,
+                        last_compiled_cmt: None,
+                        last_compiled_cmi: None,
+                    },
+                );
+            });
+
+            debug!("Building source file-tree for package: {}", package.name);
+            match &package.source_files {
+                None => (),
+                Some(source_files) => source_files.iter().for_each(|(file, metadata)| {
+                    let namespace = package.namespace.to_owned();
+
+                    let file_buf = PathBuf::from(file);
+                    let extension = file_buf.extension().unwrap().to_str().unwrap();
+                    let module_name = helpers::file_path_to_module_name(&file.to_owned(), &namespace);
+
+                    if helpers::is_implementation_file(extension) {
+                        build_state
+                            .modules
+                            .entry(module_name.to_string())
+                            .and_modify(|module| {
+                                if let SourceType::SourceFile(ref mut source_file) = module.source_type {
+                                    if &source_file.implementation.path != file {
+                                        error!("Duplicate files found for module: {}", &module_name);
+                                        error!("file 1: {}", &source_file.implementation.path);
+                                        error!("file 2: {}", &file);
+
+                                        panic!("Unable to continue... See log output above...");
+                                    }
+                                    source_file.implementation.path = file.to_owned();
+                                    source_file.implementation.last_modified = metadata.modified;
+                                    source_file.implementation.parse_dirty = true;
+                                }
+                            })
+                            .or_insert(Module {
+                                source_type: SourceType::SourceFile(SourceFile {
+                                    implementation: Implementation {
+                                        path: file.to_owned(),
+                                        parse_state: ParseState::Pending,
+                                        compile_state: CompileState::Pending,
+                                        last_modified: metadata.modified,
+                                        parse_dirty: true,
+                                    },
+                                    interface: None,
+                                }),
+                                deps: AHashSet::new(),
+                                dependents: AHashSet::new(),
+                                package_name: package.name.to_owned(),
+                                compile_dirty: true,
+                                last_compiled_cmt: None,
+                                last_compiled_cmi: None,
+                            });
+                    } else {
+                        // remove last character of string: resi -> res, rei -> re, mli -> ml
+                        let mut implementation_filename = file.to_owned();
+                        implementation_filename.pop();
+                        match source_files.get(&implementation_filename) {
+                            None => {
+                                println!(
+                                "{}Warning: No implementation file found for interface file (skipping): {}",
+                                LINE_CLEAR, file
+                            )
+                            }
+                            Some(_) => {
+                                build_state
+                                    .modules
+                                    .entry(module_name.to_string())
+                                    .and_modify(|module| {
+                                        if let SourceType::SourceFile(ref mut source_file) =
+                                            module.source_type
+                                        {
+                                            source_file.interface = Some(Interface {
+                                                path: file.to_owned(),
+                                                parse_state: ParseState::Pending,
+                                                compile_state: CompileState::Pending,
+                                                last_modified: metadata.modified,
+                                                parse_dirty: true,
+                                            });
+                                        }
+                                    })
+                                    .or_insert(Module {
+                                        source_type: SourceType::SourceFile(SourceFile {
+                                            // this will be overwritten later
+                                            implementation: Implementation {
+                                                path: implementation_filename.to_string(),
+                                                parse_state: ParseState::Pending,
+                                                compile_state: CompileState::Pending,
+                                                last_modified: metadata.modified,
+                                                parse_dirty: true,
+                                            },
+                                            interface: Some(Interface {
+                                                path: file.to_owned(),
+                                                parse_state: ParseState::Pending,
+                                                compile_state: CompileState::Pending,
+                                                last_modified: metadata.modified,
+                                                parse_dirty: true,
+                                            }),
+                                        }),
+                                        deps: AHashSet::new(),
+                                        dependents: AHashSet::new(),
+                                        package_name: package.name.to_owned(),
+                                        compile_dirty: true,
+                                        last_compiled_cmt: None,
+                                        last_compiled_cmi: None,
+                                    });
+                            }
+                        }
+                    }
+                }),
+            }
+        });
+    Ok(())
+}
+
+impl Package {
+    pub fn get_jsx_args(&self) -> Vec<String> {
+        self.bsconfig.get_jsx_args()
+    }
+
+    pub fn get_jsx_mode_args(&self) -> Vec<String> {
+        self.bsconfig.get_jsx_mode_args()
+    }
+
+    pub fn get_jsx_module_args(&self) -> Vec<String> {
+        self.bsconfig.get_jsx_module_args()
+    }
+
+    pub fn get_uncurried_args(&self, version: &str, root_package: &packages::Package) -> Vec<String> {
+        root_package.bsconfig.get_uncurried_args(version)
+    }
+
+    pub fn is_in_source(&self) -> bool {
+        self.bsconfig.get_in_source()
+    }
+
+    /// The (absolute) folder the JavaScript of this package is written to, see
+    /// `bsconfig::Config::get_js_output_dir`.
+    pub fn output_dir(&self) -> String {
+        match self.bsconfig.get_js_output_dir().as_str() {
+            "" => self.path.to_owned(),
+            output_dir => Path::new(&self.path)
+                .join(output_dir)
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// The suffix of the JavaScript files of this package: its own (package-specs) suffix, and only
+    /// when it doesn't configure one, the suffix of the root package (or the default).
+    pub fn js_suffix(&self, root_package: &packages::Package) -> String {
+        self.bsconfig
+            .get_configured_suffix()
+            .unwrap_or_else(|| root_package.bsconfig.get_suffix())
+    }
+
+    /// The contents of the namespace module of this package (what the compiler makes of the
+    /// `.mlmap`): an alias for every module, `module Foo = Foo-Namespace`, sorted. `None` when the
+    /// package has no namespace, or its modules were not computed yet.
+    pub fn namespace_file_contents(&self) -> Option<String> {
+        let namespace = match &self.namespace {
+            Namespace::Namespace(namespace) | Namespace::NamespaceWithEntry { namespace, .. } => namespace,
+            Namespace::NoNamespace => return None,
+        };
+        let suffix = format!("-{}", namespace);
+        let mut aliases = self
+            .modules
+            .as_ref()?
+            .iter()
+            .filter_map(|module| {
+                module
+                    .as_str()
+                    .strip_suffix(&suffix)
+                    .map(|name| format!("module {} = {}\n", name, module))
+            })
+            .collect::<Vec<String>>();
+        aliases.sort();
+        Some(aliases.concat())
+    }
+
+    /// A deterministic, line based description of the package (its name, namespace, source
+    /// folders, dependencies and modules, all sorted), to commit as a golden file, so a change in
+    /// how the tree is resolved shows up in a diff. Paths are relative to the package, so the
+    /// snapshot is the same on every machine.
+    pub fn snapshot(&self) -> String {
+        let mut lines = vec![
+            format!("name: {}", self.name),
+            format!(
+                "namespace: {}",
+                match &self.namespace {
+                    Namespace::Namespace(namespace) => namespace.to_owned(),
+                    Namespace::NamespaceWithEntry { namespace, entry } =>
+                        format!("{} (entry: {})", namespace, entry),
+                    Namespace::NoNamespace => "none".to_string(),
+                }
+            ),
+        ];
+        let mut source_folders = self
+            .source_folders
+            .iter()
+            .map(|source| {
+                let dir = Path::new(&source.dir);
+                let dir = match dir.is_absolute() {
+                    true => relative_path(dir, Path::new(&self.path)),
+                    false => dir.to_path_buf(),
+                };
+                let mut line = format!("  {}", dir.to_string_lossy());
+                if source.subdirs == Some(bsconfig::Subdirs::Recurse(true)) {
+                    line.push_str(" (recursive)");
+                }
+                if let Some(type_) = &source.type_ {
+                    line.push_str(&format!(" ({})", type_));
+                }
+                line
+            })
+            .collect::<Vec<String>>();
+        source_folders.sort();
+        let section = |title: &str, mut items: Vec<String>| {
+            items.sort();
+            std::iter::once(format!("{}:", title))
+                .chain(items.into_iter().map(|item| format!("  {}", item)))
+                .collect::<Vec<String>>()
+        };
+        lines.push("source folders:".to_string());
+        lines.extend(source_folders);
+        lines.extend(section(
+            "dependencies",
+            self.bsconfig.bs_dependencies.to_owned().unwrap_or_default(),
+        ));
+        lines.extend(section(
+            "dev dependencies",
+            self.bsconfig.bs_dev_dependencies.to_owned().unwrap_or_default(),
+        ));
+        lines.extend(section(
+            "modules",
+            self.modules
+                .iter()
+                .flatten()
+                .map(|module| module.as_str().to_string())
+                .collect(),
+        ));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// The (absolute) JavaScript files this package will produce, sorted, whether or not they
+    /// were compiled already: one for every implementation file (interfaces don't produce any),
+    /// under `output_dir` with the `js_suffix`, and one for the namespace module.
+    pub fn expected_output_files(&self, root_package: &packages::Package) -> Vec<String> {
+        let output_dir = PathBuf::from(self.output_dir());
+        let suffix = self.js_suffix(root_package);
+        let mut output_files = self
+            .source_file_paths()
+            .into_iter()
+            .filter(|path| helpers::is_implementation_file(&helpers::get_extension(path)))
+            .map(|path| {
+                let path = Path::new(path);
+                let file_name = format!("{}{}", helpers::get_basename(&path.to_string_lossy()), suffix);
+                output_dir
+                    .join(path.parent().unwrap_or(Path::new("")))
+                    .join(file_name)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+        // with an entry, the namespace module is one of the source files
+        if let Namespace::Namespace(namespace) = &self.namespace {
+            output_files.push(
+                output_dir
+                    .join(format!("{}{}", namespace, suffix))
+                    .to_string_lossy()
+                    .to_string(),
+            );
+        }
+        output_files.sort();
+        output_files
+    }
+
+    /// The (sorted) source files grouped by their lowercase extension (without the dot), so
+    /// interfaces are their own group. Files without an extension are under `""`.
+    pub fn source_files_by_extension(&self) -> AHashMap<String, Vec<String>> {
+        let mut by_extension: AHashMap<String, Vec<String>> = AHashMap::new();
+        self.source_file_paths().into_iter().for_each(|path| {
+            let extension = Path::new(path)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            by_extension.entry(extension).or_default().push(path.to_owned());
+        });
+        by_extension.values_mut().for_each(|paths| paths.sort());
+        by_extension
+    }
+
+    /// The number of source files per lowercase extension (see `source_files_by_extension`)
+    pub fn source_file_counts(&self) -> AHashMap<String, usize> {
+        self.source_files_by_extension()
+            .into_iter()
+            .map(|(extension, paths)| (extension, paths.len()))
+            .collect()
+    }
+
+    /// The bsc invocations that build `module_name` (the name as it appears in `modules`, so
+    /// including the namespace suffix), see `ModuleArgs`. These are the same command lines the
+    /// build uses. The source files are read, as the ppxes that run depend on their contents.
+    pub fn compile_args(
+        &self,
+        module_name: &str,
+        root_package: &Package,
+        packages: &AHashMap<String, Package>,
+        version: &str,
+        workspace_root: &Option<String>,
+    ) -> Result<ModuleArgs, String> {
+        if self.source_files.is_none() && self.source_paths.is_none() {
+            return Err(format!("The source files of package {} were not read", self.name));
+        }
+        let source_files = self.source_file_paths();
+        let is_module =
+            |path: &&String| helpers::file_path_to_module_name(path, &self.namespace) == module_name;
+        let implementation = source_files
+            .iter()
+            .copied()
+            .filter(is_module)
+            .find(|path| helpers::is_implementation_file(&helpers::get_extension(path)))
+            .ok_or(format!(
+                "Could not find an implementation file for module {} in package {}",
+                module_name, self.name
+            ))?;
+        let interface = source_files
+            .iter()
+            .copied()
+            .filter(is_module)
+            .find(|path| helpers::is_interface_file(&helpers::get_extension(path)));
+
+        let parse_args = |path: &String| {
+            let contents = fs::read_to_string(Path::new(&self.path).join(path))
+                .map_err(|e| format!("Could not read {} of package {}: {}", path, self.name, e))?;
+            let (_ast_path, args) = parse::parser_args(
+                &self.bsconfig,
+                &root_package.bsconfig,
+                path,
+                version,
+                workspace_root,
+                &root_package.path,
+                &contents,
+            );
+            Ok::<Vec<String>, String>(args)
+        };
+        let compile_args = |ast_path: &str, is_interface: bool| {
+            compile::compiler_args(
+                &self.bsconfig,
+                &self.namespace,
+                &root_package.bsconfig,
+                ast_path,
+                version,
+                implementation,
+                is_interface,
+                interface.is_some(),
+                &root_package.path,
+                workspace_root,
+                &Some(packages),
+            )
+        };
+        Ok(ModuleArgs {
+            parse: std::iter::once(implementation)
+                .chain(interface)
+                .map(parse_args)
+                .collect::<Result<Vec<Vec<String>>, String>>()?,
+            interface: interface.map(|interface| compile_args(&self.get_iast_path(interface), true)),
+            implementation: compile_args(&self.get_ast_path(implementation), false),
+        })
+    }
+}
+
+/// The bsc invocations that build a module (see `Package::compile_args`), in the order they run.
+/// Like in the build, they run in the build folder of the package (`get_build_path`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleArgs {
+    // parsing the source files to their ASTs, which runs the ppxes: the implementation, and the
+    // interface if there is one
+    pub parse: Vec<Vec<String>>,
+    // compiling the AST of the interface, if there is one
+    pub interface: Option<Vec<String>>,
+    // compiling the AST of the implementation to JavaScript
+    pub implementation: Vec<String>,
+}
+
+fn get_unallowed_dependents(
+    packages: &AHashMap<String, Package>,
+    package_name: &String,
+    dependencies: &Vec<String>,
+) -> Option<String> {
+    for deps_package_name in dependencies {
+        if let Some(deps_package) = packages.get(deps_package_name) {
+            let deps_allowed_dependents = deps_package.bsconfig.allowed_dependents.to_owned();
+            if let Some(allowed_dependents) = deps_allowed_dependents {
+                if !allowed_dependents.contains(package_name) {
+                    return Some(deps_package_name.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+#[derive(Debug, Clone)]
+struct UnallowedDependency {
+    bs_deps: Vec<String>,
+    pinned_deps: Vec<String>,
+    bs_dev_deps: Vec<String>,
+}
+
+pub fn validate_packages_dependencies(packages: &AHashMap<String, Package>) -> bool {
+    let mut detected_unallowed_dependencies: AHashMap<String, UnallowedDependency> = AHashMap::new();
+
+    for (package_name, package) in packages {
+        let bs_dependencies = &package.bsconfig.bs_dependencies.to_owned().unwrap_or(vec![]);
+        let pinned_dependencies = &package.bsconfig.pinned_dependencies.to_owned().unwrap_or(vec![]);
+        let dev_dependencies = &package.bsconfig.bs_dev_dependencies.to_owned().unwrap_or(vec![]);
+
+        vec![
+            ("bs-dependencies", bs_dependencies),
+            ("pinned-dependencies", pinned_dependencies),
+            ("bs-dev-dependencies", dev_dependencies),
+        ]
+        .iter()
+        .for_each(|(dependency_type, dependencies)| {
+            if let Some(unallowed_dependency_name) =
+                get_unallowed_dependents(packages, package_name, dependencies)
+            {
+                let empty_unallowed_deps = UnallowedDependency {
+                    bs_deps: vec![],
+                    pinned_deps: vec![],
+                    bs_dev_deps: vec![],
+                };
+
+                let unallowed_dependency = detected_unallowed_dependencies.entry(String::from(package_name));
+                let value = unallowed_dependency.or_insert_with(|| empty_unallowed_deps);
+                match *dependency_type {
+                    "bs-dependencies" => value.bs_deps.push(unallowed_dependency_name),
+                    "pinned-dependencies" => value.pinned_deps.push(unallowed_dependency_name),
+                    "bs-dev-dependencies" => value.bs_dev_deps.push(unallowed_dependency_name),
+                    _ => (),
+                }
+            }
+        });
+    }
+    for (package_name, unallowed_deps) in detected_unallowed_dependencies.iter() {
+        println!(
+            "\n{}: {} has the following unallowed dependencies:",
+            console::style("Error").red(),
+            console::style(package_name).bold()
+        );
+
+        vec![
+            ("bs-dependencies", unallowed_deps.bs_deps.to_owned()),
+            ("pinned-dependencies", unallowed_deps.pinned_deps.to_owned()),
+            ("bs-dev-dependencies", unallowed_deps.bs_dev_deps.to_owned()),
+        ]
+        .iter()
+        .for_each(|(deps_type, map)| {
+            if !map.is_empty() {
+                println!(
+                    "{} dependencies: {}",
+                    console::style(deps_type).bold().dim(),
+                    console::style(map.join(" \n -")).bold().dim()
+                );
+            }
+        });
+    }
+    let has_any_unallowed_dependent = detected_unallowed_dependencies.len() > 0;
+
+    if has_any_unallowed_dependent {
+        println!(
+            "\nUpdate the {} value in the {} of the unallowed dependencies to solve the issue!",
+            console::style("unallowed_dependents").bold().dim(),
+            console::style("bsconfig.json").bold().dim()
+        )
+    }
+    !has_any_unallowed_dependent
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bsconfig::Source;
+    use crate::build::diagnostics::{DiagnosticKind, Severity};
+    use crate::helpers;
+    use crate::testing::Fixture;
+    use ahash::{AHashMap, AHashSet};
+
+    use super::{Namespace, Package};
+
+    fn create_package(
+        name: String,
+        bs_deps: Vec<String>,
+        pinned_deps: Vec<String>,
+        dev_deps: Vec<String>,
+        allowed_dependents: Option<Vec<String>>,
+    ) -> Package {
+        return Package {
+            name: name.clone(),
+            bsconfig: crate::bsconfig::Config {
+                name: name.clone(),
+                sources: crate::bsconfig::OneOrMore::Single(Source::Shorthand(String::from("Source"))),
+                package_specs: None,
+                warnings: None,
+                suffix: None,
+                pinned_dependencies: Some(pinned_deps),
+                bs_dependencies: Some(bs_deps),
+                bs_dev_dependencies: Some(dev_deps),
+                ppx_flags: None,
+                bsc_flags: None,
+                reason: None,
+                refmt: None,
+                namespace: None,
+                jsx: None,
+                jsx_mode: None,
+                jsx_module: None,
+                uncurried: None,
+                gentypeconfig: None,
+                namespace_entry: None,
+                allowed_dependents,
+                sources_archive: None,
+                unknown_fields: serde_json::Map::new(),
+            },
+            source_folders: AHashSet::new(),
+            source_files: None,
+            source_paths: None,
+            namespace: Namespace::Namespace(String::from("Package1")),
+            modules: None,
+            path: String::from("./something"),
+            dirs: None,
+            is_pinned_dep: false,
+            is_root: false,
+            is_ignored: false,
+            is_local: false,
+            is_runtime: false,
+            cache_stamp: None,
+        };
+    }
+    #[test]
+    fn should_return_false_with_invalid_parents_as_bs_dependencies() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            String::from("Package1"),
+            create_package(
+                String::from("Package1"),
+                vec![String::from("Package2")],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+        packages.insert(
+            String::from("Package2"),
+            create_package(
+                String::from("Package2"),
+                vec![],
+                vec![],
+                vec![],
+                Some(vec![String::from("Package3")]),
+            ),
+        );
+
+        let is_valid = super::validate_packages_dependencies(&packages);
+        assert_eq!(is_valid, false)
+    }
+
+    #[test]
+    fn should_return_false_with_invalid_parents_as_pinned_dependencies() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            String::from("Package1"),
+            create_package(
+                String::from("Package1"),
+                vec![],
+                vec![String::from("Package2")],
+                vec![],
+                None,
+            ),
+        );
+        packages.insert(
+            String::from("Package2"),
+            create_package(
+                String::from("Package2"),
+                vec![],
+                vec![],
+                vec![],
+                Some(vec![String::from("Package3")]),
+            ),
+        );
+
+        let is_valid = super::validate_packages_dependencies(&packages);
+        assert_eq!(is_valid, false)
+    }
+
+    #[test]
+    fn should_return_false_with_invalid_parents_as_dev_dependencies() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            String::from("Package1"),
+            create_package(
+                String::from("Package1"),
+                vec![],
+                vec![],
+                vec![String::from("Package2")],
+                None,
+            ),
+        );
+        packages.insert(
+            String::from("Package2"),
+            create_package(
+                String::from("Package2"),
+                vec![],
+                vec![],
+                vec![],
+                Some(vec![String::from("Package3")]),
+            ),
+        );
+
+        let is_valid = super::validate_packages_dependencies(&packages);
+        assert_eq!(is_valid, false)
+    }
+
+    #[test]
+    fn should_return_true_with_no_invalid_parent() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        packages.insert(
+            String::from("Package1"),
+            create_package(
+                String::from("Package1"),
+                vec![String::from("Package2")],
+                vec![],
+                vec![],
+                None,
+            ),
+        );
+        packages.insert(
+            String::from("Package2"),
+            create_package(
+                String::from("Package2"),
+                vec![],
+                vec![],
+                vec![],
+                Some(vec![String::from("Package1")]),
+            ),
+        );
+
+        let is_valid = super::validate_packages_dependencies(&packages);
+        assert_eq!(is_valid, true)
+    }
+
+    #[test]
+    fn should_resolve_dependencies_from_a_package_inside_node_modules() {
+        let fixture = Fixture::new("nested-root");
+        fixture
+            .write("package.json", r#"{ "name": "app" }"#)
+            .package("node_modules/dep-a", "dep-a", &["dep-b"])
+            .package("node_modules/dep-b", "dep-b", &[])
+            .write("node_modules/dep-a/src/A.res", "let a = 1")
+            .write("node_modules/dep-b/src/B.res", "let b = 1");
+
+        let packages = super::make(&None, &fixture.path("node_modules/dep-a"), &None);
+
+        assert_eq!(packages.len(), 2);
+        assert!(packages["dep-a"].is_root);
+        assert_eq!(packages["dep-b"].path, fixture.path("node_modules/dep-b"));
+        assert!(packages["dep-b"].modules.as_ref().unwrap().contains("B"));
+    }
+
+    #[test]
+    fn should_not_scan_or_recurse_into_ignored_packages() {
+        let fixture = Fixture::new("ignore-packages");
+        fixture
+            .package("", "root", &["dep", "vendored"])
+            .package("node_modules/dep", "dep", &[])
+            .package("node_modules/vendored", "vendored", &["vendored-dep"])
+            .package("node_modules/vendored-dep", "vendored-dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1")
+            .write("node_modules/vendored/src/Vendored.res", "let a = 1");
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                ignore_packages: vec!["vendored".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let vendored = &tree.packages["vendored"];
+        assert!(vendored.is_ignored);
+        assert!(vendored.modules.as_ref().unwrap().is_empty());
+        assert!(!tree.packages.contains_key("vendored-dep"));
+        assert!(tree.packages["dep"].modules.as_ref().unwrap().contains("Dep"));
+        assert!(tree
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::IgnoredPackage && d.severity == Severity::Info));
+    }
+
+    #[test]
+    fn should_cluster_identical_files_across_packages() {
+        let fixture = Fixture::new("duplicate-files");
+        fixture
+            .package("", "root", &["dep"])
+            .package("node_modules/dep", "dep", &[])
+            .write("src/Generated.res", "let generated = true")
+            .write("src/Root.res", "let root = true")
+            .write("node_modules/dep/src/Copy.res", "let generated = true");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::duplicate_file_report(&packages),
+            vec![vec![
+                fixture.path("node_modules/dep/src/Copy.res"),
+                fixture.path("src/Generated.res"),
+            ]]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_mark_symlinked_dependencies_as_local() {
+        let fixture = Fixture::new("local-dependencies");
+        fixture
+            .package("", "root", &["shared", "external"])
+            .package("packages/shared", "shared", &[])
+            .symlink("packages/shared", "node_modules/shared")
+            .package("node_modules/external", "external", &[]);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert!(packages["shared"].is_local);
+        assert_eq!(packages["shared"].path, fixture.path("packages/shared"));
+        assert!(!packages["external"].is_local);
+    }
+
+    #[test]
+    fn should_compute_the_same_modules_without_source_metadata() {
+        let fixture = Fixture::new("drop-source-metadata");
+        fixture
+            .package("", "root", &[])
+            .write("src/First.res", "let a = 1")
+            .write("src/Second.res", "let b = 1")
+            .write("src/Second.resi", "let b: int");
+
+        let with_metadata = super::make(&None, &fixture.path(""), &None);
+        let without_metadata = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                drop_source_metadata: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .packages;
+
+        let root = &without_metadata["root"];
+        assert_eq!(root.modules, with_metadata["root"].modules);
+        assert!(root.source_files.is_none());
+        assert_eq!(root.source_paths.as_ref().unwrap().len(), 3);
+        assert_eq!(root.source_file_paths().len(), 3);
+    }
+
+    #[test]
+    fn should_not_build_without_source_metadata() {
+        let fixture = Fixture::new("build-without-source-metadata");
+        fixture
+            .package("", "root", &[])
+            .write("src/First.res", "let a = 1");
+        let packages = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                drop_source_metadata: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .packages;
+        let mut build_state = crate::build::build_types::BuildState::new(
+            fixture.path(""),
+            "root".to_string(),
+            packages,
+            None,
+            "11.0.0".to_string(),
+            "bsc".to_string(),
+        );
+
+        let error = super::parse_packages(&mut build_state).unwrap_err();
+        assert_eq!(error.package, "root");
+    }
+
+    #[test]
+    fn should_warn_about_package_names_that_are_not_valid_namespaces() {
+        let fixture = Fixture::new("invalid-package-names");
+        fixture
+            .package("", "my app", &["2fa"])
+            .package("node_modules/2fa", "2fa", &[]);
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        let mut invalid_names = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::InvalidPackageName)
+            .map(|d| d.message.to_owned())
+            .collect::<Vec<String>>();
+        invalid_names.sort();
+        assert_eq!(invalid_names.len(), 2);
+        assert!(invalid_names[0].contains("\"2fa\""));
+        assert!(invalid_names[1].contains("\"my app\""));
+    }
+
+    #[test]
+    fn should_return_diagnostics_in_a_stable_order() {
+        let fixture = Fixture::new("stable-diagnostics");
+        fixture
+            .package("", "0root", &["1a", "1b", "ignored"])
+            .package("node_modules/1a", "1a", &["1c"])
+            .package("node_modules/1b", "1b", &[])
+            .package("node_modules/1c", "1c", &[]);
+        let options = super::MakeOptions {
+            ignore_packages: vec!["ignored".to_string()],
+            ..Default::default()
+        };
+
+        let diagnostics = super::make_with(&None, &fixture.path(""), &None, &options)
+            .unwrap()
+            .diagnostics;
+
+        let paths = diagnostics
+            .iter()
+            .map(|d| d.path.to_owned())
+            .collect::<Vec<String>>();
+        let mut sorted_paths = paths.to_owned();
+        sorted_paths.sort();
+        assert_eq!(diagnostics.len(), 5);
+        assert_eq!(paths, sorted_paths);
+        for _ in 0..5 {
+            assert_eq!(
+                super::make_with(&None, &fixture.path(""), &None, &options)
+                    .unwrap()
+                    .diagnostics,
+                diagnostics
+            );
+        }
+    }
+
+    #[test]
+    fn should_not_scan_paths_matched_by_rewatchignore() {
+        let fixture = Fixture::new("rewatchignore");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": { "dir": "src", "subdirs": true } }"#,
+            )
+            .write(".rewatchignore", "generated/\n")
+            .write("src/Real.res", "let a = 1")
+            .write("src/generated/Generated.res", "let a = 1")
+            .write("src/nested/generated/Deep.res", "let a = 1")
+            .write("src/nested/Nested.res", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        let mut modules = Vec::from_iter(packages["root"].modules.to_owned().unwrap());
+        modules.sort();
+        assert_eq!(modules, vec!["Nested", "Real"]);
+    }
+
+    #[test]
+    fn should_compute_the_compile_args_of_a_namespaced_module() {
+        let fixture = Fixture::new("compile-args");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "namespace": "App", "sources": "src", "bs-dependencies": ["dep"],
+                     "bsc-flags": ["-open Belt"], "ppx-flags": ["my-ppx/ppx"],
+                     "warnings": { "number": "-44", "error": true } }"#,
+            )
+            .package("node_modules/dep", "dep", &[])
+            .write("src/Main.res", "let a = 1")
+            .write("src/Main.resi", "let a: int");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let root = &packages["root"];
+
+        let args = root
+            .compile_args("Main-App", root, &packages, "11.0.0", &None)
+            .unwrap();
+        assert_eq!(
+            args.parse,
+            vec![
+                vec![
+                    "-bs-v",
+                    "11.0.0",
+                    "-ppx",
+                    &fixture.path("node_modules/my-ppx/ppx"),
+                    "-uncurried",
+                    "-open",
+                    "Belt",
+                    "-absname",
+                    "-bs-ast",
+                    "-o",
+                    "Main.ast",
+                    "../../src/Main.res",
+                ],
+                vec![
+                    "-bs-v",
+                    "11.0.0",
+                    "-ppx",
+                    &fixture.path("node_modules/my-ppx/ppx"),
+                    "-uncurried",
+                    "-open",
+                    "Belt",
+                    "-absname",
+                    "-bs-ast",
+                    "-o",
+                    "Main.iast",
+                    "../../src/Main.resi",
+                ],
+            ]
+        );
+        assert_eq!(
+            args.interface.unwrap().last(),
+            Some(&fixture.path("lib/ocaml/Main.iast"))
+        );
+        assert_eq!(
+            args.implementation,
+            vec![
+                "-bs-ns",
+                "App",
+                "-bs-read-cmi",
+                "-I",
+                ".",
+                "-I",
+                &fixture.path("node_modules/dep/lib/ocaml"),
+                "-uncurried",
+                "-open",
+                "Belt",
+                "-w",
+                "-44",
+                "-warn-error",
+                "A",
+                "-bs-package-name",
+                "root",
+                "-bs-package-output",
+                "commonjs:src:.js",
+                &fixture.path("lib/ocaml/Main.ast"),
+            ]
+        );
+        assert!(root
+            .compile_args("Missing-App", root, &packages, "11.0.0", &None)
+            .is_err());
+    }
+
+    #[test]
+    fn should_distinguish_absent_and_empty_dependencies() {
+        let fixture = Fixture::new("absent-dependencies");
+        fixture
+            .package("", "root", &["absent", "empty", "forgotten"])
+            .write("node_modules/absent/bsconfig.json", r#"{ "name": "absent", "sources": "src" }"#)
+            .package("node_modules/empty", "empty", &[])
+            .write(
+                "node_modules/forgotten/bsconfig.json",
+                r#"{ "name": "forgotten", "sources": "src", "bs-dependencies": [], "bs-dev-dependencies": ["empty"] }"#,
+            );
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        assert_eq!(tree.packages["absent"].bsconfig.bs_dependencies, None);
+        assert_eq!(tree.packages["empty"].bsconfig.bs_dependencies, Some(vec![]));
+        let warnings = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::EmptyDependencies)
+            .collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, fixture.path("node_modules/forgotten"));
+    }
+
+    #[test]
+    fn should_report_recursive_source_folders() {
+        let fixture = Fixture::new("recursive-sources");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [
+                     { "dir": "src", "subdirs": true },
+                     { "dir": "test", "subdirs": ["unit"] }
+                   ] }"#,
+            )
+            .mkdir("src")
+            .mkdir("test/unit");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(packages["root"].source_folder_count(), 3);
+        assert_eq!(packages["root"].recursive_source_folders(), vec!["src"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_resolve_transitive_dependencies_through_the_pnpm_store() {
+        let fixture = Fixture::new("pnpm-store");
+        fixture
+            .package("", "root", &["a"])
+            .package("node_modules/.pnpm/a@1.0.0/node_modules/a", "a", &["b"])
+            .package("node_modules/.pnpm/b@1.0.0/node_modules/b", "b", &[])
+            .write("node_modules/.pnpm/b@1.0.0/node_modules/b/src/B.res", "let b = 1")
+            // a hoisted version of b that a does not depend on
+            .package("node_modules/b", "b", &[])
+            .symlink("node_modules/.pnpm/a@1.0.0/node_modules/a", "node_modules/a")
+            .symlink(
+                "node_modules/.pnpm/b@1.0.0/node_modules/b",
+                "node_modules/.pnpm/a@1.0.0/node_modules/b",
+            );
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(packages.len(), 3);
+        assert_eq!(
+            packages["a"].path,
+            fixture.path("node_modules/.pnpm/a@1.0.0/node_modules/a")
+        );
+        assert_eq!(
+            packages["b"].path,
+            fixture.path("node_modules/.pnpm/b@1.0.0/node_modules/b")
+        );
+        assert!(packages["b"].modules.as_ref().unwrap().contains("B"));
+    }
+
+    #[test]
+    fn should_watch_source_folders_and_config_files() {
+        let fixture = Fixture::new("watch-paths");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": { "dir": "src", "subdirs": true }, "bs-dependencies": ["dep"] }"#,
+            )
+            .mkdir("src/nested/deep")
+            .package("node_modules/dep", "dep", &[]);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let watch_paths = super::watch_paths(&packages);
+
+        let watch_path = |path: &str| super::WatchPath {
+            path: fixture.path(path),
+            recursive: false,
+        };
+        assert!(packages["root"].has_recursive_sources());
+        assert!(!packages["dep"].has_recursive_sources());
+        assert_eq!(
+            watch_paths,
+            AHashSet::from_iter([
+                watch_path("bsconfig.json"),
+                watch_path("src"),
+                watch_path("src/nested"),
+                watch_path("src/nested/deep"),
+                watch_path("node_modules/dep/bsconfig.json"),
+                watch_path("node_modules/dep/src"),
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn should_skip_files_with_a_name_that_is_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let fixture = Fixture::new("non-utf8-paths");
+        fixture
+            .package("", "root", &[])
+            .write("src/Valid.res", "let a = 1");
+        let invalid_path =
+            std::path::Path::new(&fixture.path("src")).join(OsStr::from_bytes(b"Invalid\xff.res"));
+        std::fs::write(&invalid_path, "let a = 1").unwrap();
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        assert_eq!(
+            Vec::from_iter(tree.packages["root"].modules.to_owned().unwrap()),
+            vec!["Valid"]
+        );
+        let skipped = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::NonUtf8Path)
+            .collect::<Vec<_>>();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].path, invalid_path.to_string_lossy());
+    }
+
+    #[test]
+    fn should_order_modules_after_the_modules_of_their_dependencies() {
+        let fixture = Fixture::new("build-order");
+        fixture
+            .package("", "app", &["ui", "core"])
+            .package("node_modules/ui", "ui", &["core"])
+            .package("node_modules/core", "core", &[])
+            .write("src/App.res", "let a = 1")
+            .write("node_modules/ui/src/Button.res", "let a = 1")
+            .write("node_modules/ui/src/Alert.res", "let a = 1")
+            .write("node_modules/core/src/Core.res", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::packages_in_build_order(&packages),
+            vec!["core", "ui", "app"]
+        );
+        assert_eq!(
+            super::modules_in_build_order(&packages),
+            vec![
+                (fixture.path("node_modules/core"), "Core".to_string()),
+                (fixture.path("node_modules/ui"), "Alert".to_string()),
+                (fixture.path("node_modules/ui"), "Button".to_string()),
+                (packages["app"].path.to_owned(), "App".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_change_the_fingerprint_when_the_tree_changes() {
+        let fixture = Fixture::new("tree-fingerprint");
+        fixture
+            .package("", "root", &["dep"])
+            .package("node_modules/dep", "dep", &[])
+            .write("src/Root.res", "let a = 1")
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let fingerprint = || super::tree_fingerprint(&super::make(&None, &fixture.path(""), &None));
+
+        let initial = fingerprint();
+        assert_eq!(fingerprint(), initial);
+
+        fixture.write("src/Root.res", "let a = 2");
+        assert_eq!(fingerprint(), initial);
+
+        fixture.write("node_modules/dep/src/Added.res", "let a = 1");
+        let with_module = fingerprint();
+        assert_ne!(with_module, initial);
+
+        fixture.write(
+            "node_modules/dep/bsconfig.json",
+            r#"{ "name": "dep", "sources": "src", "suffix": ".mjs" }"#,
+        );
+        assert_ne!(fingerprint(), with_module);
+    }
+
+    #[test]
+    fn should_list_the_direct_dependencies_of_a_package() {
+        let fixture = Fixture::new("direct-dependencies");
+        fixture
+            .package("", "root", &["a", "b"])
+            .package("node_modules/a", "a", &["c"])
+            .package("node_modules/b", "b", &[])
+            .package("node_modules/c", "c", &[]);
+
+        let mut packages = super::make(&None, &fixture.path(""), &None);
+        let (dependencies, diagnostics) = packages["root"].direct_dependencies(&packages);
+
+        assert_eq!(
+            dependencies
+                .iter()
+                .map(|p| p.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["a", "b"]
+        );
+        assert!(diagnostics.is_empty());
+
+        packages.remove("c");
+        let (dependencies, diagnostics) = packages["a"].direct_dependencies(&packages);
+        assert!(dependencies.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnresolvedDependency);
+    }
+
+    #[test]
+    fn should_prune_packages_that_are_only_reachable_through_a_removed_dependency() {
+        let fixture = Fixture::new("remove-dependency");
+        fixture
+            .package("", "root", &["a", "b"])
+            .package("node_modules/a", "a", &["a-only", "shared"])
+            .package("node_modules/a-only", "a-only", &["a-only-nested"])
+            .package("node_modules/a-only-nested", "a-only-nested", &[])
+            .package("node_modules/b", "b", &["shared"])
+            .package("node_modules/shared", "shared", &[]);
+        let mut packages = super::make(&None, &fixture.path(""), &None);
+        let root_path = packages["root"].path.to_owned();
+
+        let removed = super::remove_dependency(&mut packages, &root_path, "a");
+
+        assert_eq!(
+            removed,
+            vec![
+                fixture.path("node_modules/a"),
+                fixture.path("node_modules/a-only"),
+                fixture.path("node_modules/a-only-nested"),
+            ]
+        );
+        let mut remaining = Vec::from_iter(packages.keys().cloned());
+        remaining.sort();
+        assert_eq!(remaining, vec!["b", "root", "shared"]);
+    }
+
+    #[test]
+    fn should_keep_the_dev_dependencies_when_removing_a_dependency() {
+        let fixture = Fixture::new("remove-dependency-with-dev");
+        fixture
+            .package("", "root", &["a", "b"])
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["a", "b"], "bs-dev-dependencies": ["tester"] }"#,
+            )
+            .package("node_modules/a", "a", &[])
+            .package("node_modules/b", "b", &[])
+            .package("node_modules/tester", "tester", &["tester-nested"])
+            .package("node_modules/tester-nested", "tester-nested", &[]);
+        let options = super::MakeOptions {
+            include_dev: true,
+            ..Default::default()
+        };
+        let mut packages = super::make_with(&None, &fixture.path(""), &None, &options)
+            .unwrap()
+            .packages;
+        let root_path = packages["root"].path.to_owned();
+
+        let removed = super::remove_dependency(&mut packages, &root_path, "a");
+
+        assert_eq!(removed, vec![fixture.path("node_modules/a")]);
+        let mut remaining = Vec::from_iter(packages.keys().cloned());
+        remaining.sort();
+        assert_eq!(remaining, vec!["b", "root", "tester", "tester-nested"]);
+    }
+
+    #[test]
+    fn should_only_add_the_packages_of_a_new_dependency_that_are_new() {
+        let fixture = Fixture::new("add-dependency");
+        fixture
+            .package("", "root", &["a"])
+            .package("node_modules/a", "a", &["shared"])
+            .package("node_modules/shared", "shared", &[])
+            .package("node_modules/new", "new", &["new-nested", "shared"])
+            .package("node_modules/new-nested", "new-nested", &["a"])
+            .write("node_modules/new-nested/src/Nested.res", "let a = 1");
+        let mut tree =
+            super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+        let root_path = tree.packages["root"].path.to_owned();
+
+        let added = super::add_dependency(&mut tree, &root_path, "new").unwrap();
+        let packages = &tree.packages;
+
+        assert_eq!(
+            added,
+            vec![
+                fixture.path("node_modules/new"),
+                fixture.path("node_modules/new-nested")
+            ]
+        );
+        let mut names = Vec::from_iter(packages.keys().cloned());
+        names.sort();
+        assert_eq!(names, vec!["a", "new", "new-nested", "root", "shared"]);
+        assert_eq!(
+            packages["root"].bsconfig.bs_dependencies,
+            Some(vec!["a".to_string(), "new".to_string()])
+        );
+        assert!(packages["new-nested"]
+            .modules
+            .as_ref()
+            .unwrap()
+            .contains("Nested"));
+        assert!(tree
+            .closures
+            .production
+            .contains(&fixture.path("node_modules/new-nested")));
+        assert_eq!(super::add_dependency(&mut tree, &root_path, "new"), Ok(vec![]));
+        assert!(super::add_dependency(&mut tree, &root_path, "missing").is_err());
+        assert_eq!(
+            tree.packages["root"]
+                .bsconfig
+                .bs_dependencies
+                .as_ref()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn should_add_a_dependency_with_the_options_of_the_tree() {
+        let fixture = Fixture::new("add-dependency-options");
+        fixture
+            .package("", "root", &[])
+            .package("deps/new", "new", &[])
+            .write("deps/new/src/1invalid.res", "let a = 1");
+        let options = super::MakeOptions {
+            node_modules_dir: Some("deps".to_string()),
+            ..Default::default()
+        };
+        let mut tree = super::make_with(&None, &fixture.path(""), &None, &options).unwrap();
+        let root_path = tree.packages["root"].path.to_owned();
+
+        let added = super::add_dependency(&mut tree, &root_path, "new").unwrap();
+
+        assert_eq!(added, vec![fixture.path("deps/new")]);
+        assert!(!tree.packages["new"].is_local);
+        assert!(tree
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::InvalidModuleName));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_add_a_local_dependency_like_make_would() {
+        let fixture = Fixture::new("add-local-dependency");
+        fixture
+            .package("", "root", &[])
+            .write(
+                "packages/other/bsconfig.json",
+                r#"{ "name": "other", "sources": "src", "namespace": true }"#,
+            )
+            .write("packages/other/src/Other.res", "let a = 1")
+            .write("packages/other/src/Skipped.res", "let a = 1")
+            .symlink("packages/other", "node_modules/other");
+        let options = super::MakeOptions {
+            force_namespace_prefix: Some("Org".to_string()),
+            ..Default::default()
+        };
+        let filter = Some(regex::Regex::new("Other").unwrap());
+        let mut tree = super::make_with(&filter, &fixture.path(""), &None, &options).unwrap();
+        let root_path = tree.packages["root"].path.to_owned();
+
+        super::add_dependency(&mut tree, &root_path, "other").unwrap();
+        fixture.package("", "root", &["other"]);
+        let made = super::make_with(&filter, &fixture.path(""), &None, &options).unwrap();
+
+        assert_eq!(
+            tree.packages["other"].namespace.to_suffix(),
+            Some("Org_Other".to_string())
+        );
+        assert_eq!(
+            tree.packages["other"].namespace.to_suffix(),
+            made.packages["other"].namespace.to_suffix()
+        );
+        assert_eq!(tree.packages["other"].modules, made.packages["other"].modules);
+        assert!(!tree.packages["other"]
+            .modules
+            .as_ref()
+            .unwrap()
+            .contains("Skipped-Org_Other"));
+    }
+
+    #[test]
+    fn should_keep_a_removed_dependency_that_is_still_reachable() {
+        let fixture = Fixture::new("remove-reachable-dependency");
+        fixture
+            .package("", "root", &["a", "b"])
+            .package("node_modules/a", "a", &["shared"])
+            .package("node_modules/b", "b", &["shared"])
+            .package("node_modules/shared", "shared", &[]);
+        let mut packages = super::make(&None, &fixture.path(""), &None);
+        let a_path = packages["a"].path.to_owned();
+
+        let removed = super::remove_dependency(&mut packages, &a_path, "shared");
+
+        assert!(removed.is_empty());
+        assert!(packages.contains_key("shared"));
+        assert_eq!(packages["a"].bsconfig.bs_dependencies, Some(vec![]));
+    }
+
+    #[test]
+    fn should_fail_on_diagnostics_of_the_configured_severity() {
+        let fixture = Fixture::new("fail-on-warnings");
+        fixture.package("", "my app", &[]);
+        let options = |fail_on| super::MakeOptions {
+            fail_on,
+            ..Default::default()
+        };
+
+        assert!(super::make_with(&None, &fixture.path(""), &None, &options(None)).is_ok());
+        assert!(super::make_with(&None, &fixture.path(""), &None, &options(Some(Severity::Error))).is_ok());
+        match super::make_with(&None, &fixture.path(""), &None, &options(Some(Severity::Warning))) {
+            Err(super::MakeError::FailedOnDiagnostics(diagnostics)) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].kind, DiagnosticKind::InvalidPackageName);
+            }
+            other => panic!("Expected the warning to fail the build, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_find_the_compiler_binary_of_the_nearest_rescript_package() {
+        let fixture = Fixture::new("bsc-path");
+        let bsc_path = format!("node_modules/rescript/{}/bsc.exe", helpers::get_bin_subfolder());
+        fixture
+            .package("", "root", &["dep"])
+            .package("node_modules/dep", "dep", &[])
+            .write(&bsc_path, "");
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            packages["root"].bsc_path(&packages),
+            Some(fixture.path(&bsc_path))
+        );
+        assert_eq!(packages["dep"].bsc_path(&packages), Some(fixture.path(&bsc_path)));
+
+        let nested_bsc_path = format!(
+            "node_modules/dep/node_modules/rescript/{}/bsc.exe",
+            helpers::get_bin_subfolder()
+        );
+        fixture.write(&nested_bsc_path, "");
+        assert_eq!(
+            packages["dep"].bsc_path(&packages),
+            Some(fixture.path(&nested_bsc_path))
+        );
+    }
+
+    #[test]
+    fn should_validate_string_namespaces() {
+        let fixture = Fixture::new("string-namespaces");
+        let package = |dir: &str, name: &str, namespace: &str| {
+            fixture.write(
+                &format!("{}/bsconfig.json", dir),
+                &format!(
+                    r#"{{ "name": "{}", "sources": "src", "namespace": "{}" }}"#,
+                    name, namespace
+                ),
+            );
+        };
+        fixture.write(
+            "bsconfig.json",
+            r#"{ "name": "root", "sources": "src", "bs-dependencies": ["valid", "derived", "normalized", "invalid"] }"#,
+        );
+        package("node_modules/valid", "valid", "MyNamespace");
+        package("node_modules/derived", "derived-name", "true");
+        package("node_modules/normalized", "normalized", "my-ns");
+        package("node_modules/invalid", "invalid", "1st");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+        let namespace_diagnostics = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::InvalidNamespace)
+            .map(|d| (d.path.to_owned(), d.severity))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tree.packages["valid"].namespace.to_suffix(),
+            Some("MyNamespace".to_string())
+        );
+        assert_eq!(
+            tree.packages["derived"].namespace.to_suffix(),
+            Some("DerivedName".to_string())
+        );
+        assert_eq!(
+            tree.packages["normalized"].namespace.to_suffix(),
+            Some("MyNs".to_string())
+        );
+        assert_eq!(
+            namespace_diagnostics,
+            vec![
+                (fixture.path("node_modules/invalid"), Severity::Error),
+                (fixture.path("node_modules/normalized"), Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_compute_the_packages_a_module_depends_on() {
+        let fixture = Fixture::new("module-closure");
+        fixture
+            .package("", "root", &["a"])
+            .package("node_modules/a", "a", &["b"])
+            .package("node_modules/b", "b", &["c"])
+            .package("node_modules/c", "c", &[])
+            .write("node_modules/a/src/Shared.res", "let a = 1")
+            .write("node_modules/c/src/Shared.res", "let a = 1")
+            .write("node_modules/b/src/B.res", "let a = 1");
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        let closure = super::module_dependency_closure(&packages, "B");
+        assert_eq!(closure.len(), 1);
+        assert_eq!(
+            closure[&fixture.path("node_modules/b")],
+            AHashSet::from_iter([fixture.path("node_modules/c")])
+        );
+
+        let closure = super::module_dependency_closure(&packages, "Shared");
+        assert_eq!(closure.len(), 2);
+        assert_eq!(
+            closure[&fixture.path("node_modules/a")],
+            AHashSet::from_iter([fixture.path("node_modules/b"), fixture.path("node_modules/c")])
+        );
+        assert!(closure[&fixture.path("node_modules/c")].is_empty());
+    }
+
+    fn make_tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_700_000_000);
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn should_read_sources_from_a_tar_archive() {
+        let archive = make_tar(&[
+            ("src/A.res", "let a = 1"),
+            ("src/nested/B.res", "let b = 1"),
+            ("src/nested/B.resi", "let b: int"),
+            ("src/README.md", "readme"),
+            ("test/T.res", "let t = 1"),
+        ]);
+        let reader = super::source_reader::TarReader::new(std::io::Cursor::new(archive)).unwrap();
+
+        let files = super::read_folders(
+            &None,
+            &ignore::gitignore::Gitignore::empty(),
+            &reader,
+            std::path::Path::new("/packages/archived"),
+            std::path::Path::new("src"),
+            true,
+            &mut super::DEFAULT_MAX_SOURCE_ENTRIES.to_owned(),
+            super::DEFAULT_MAX_SYMLINK_DEPTH,
+            &mut std::path::PathBuf::new(),
+            &crate::build::diagnostics::Diagnostics::new(),
+        )
+        .unwrap();
+
+        let mut paths = Vec::from_iter(files.keys().cloned());
+        paths.sort();
+        assert_eq!(paths, vec!["src/A.res", "src/nested/B.res", "src/nested/B.resi"]);
+    }
+
+    #[test]
+    fn should_scan_a_package_with_a_sources_archive() {
+        let fixture = Fixture::new("sources-archive");
+        fixture.package("", "root", &["archived"]).write(
+            "node_modules/archived/bsconfig.json",
+            r#"{ "name": "archived", "sources": "src", "sources-archive": "sources.tar" }"#,
+        );
+        std::fs::write(
+            fixture.path("node_modules/archived/sources.tar"),
+            make_tar(&[("src/Archived.res", "let a = 1")]),
+        )
+        .unwrap();
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            Vec::from_iter(packages["archived"].modules.to_owned().unwrap()),
+            vec!["Archived"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_add_a_package_reached_through_different_paths_once() {
+        let fixture = Fixture::new("path-variants");
+        fixture
+            .package("", "root", &["a", "b", "shared"])
+            .package("node_modules/a", "a", &["shared"])
+            .package("packages/b", "b", &["shared"])
+            .symlink("packages/b", "node_modules/b")
+            .package("node_modules/shared", "shared", &[])
+            .symlink("node_modules/shared", "packages/b/node_modules/shared")
+            .write("node_modules/shared/src/Shared.res", "let a = 1");
+
+        let tree = super::make_with(
+            &None,
+            &format!("{}/.", fixture.path("")),
+            &None,
+            &super::MakeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(tree.packages.len(), 4);
+        assert_eq!(tree.packages["shared"].path, fixture.path("node_modules/shared"));
+        assert_eq!(tree.packages["root"].path, fixture.path("").trim_end_matches('/'));
+        assert!(!tree
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::DuplicatePackagePath));
+    }
+
+    #[test]
+    fn should_warn_about_a_package_that_is_in_the_tree_under_multiple_names() {
+        let fixture = Fixture::new("duplicate-package-paths");
+        fixture.package("", "root", &[]);
+        let mut packages = super::make(&None, &fixture.path(""), &None);
+        packages.insert("alias".to_string(), packages["root"].to_owned());
+
+        let diagnostics = super::validate_unique_package_paths(&packages);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.ends_with("alias, root"));
+    }
+
+    #[test]
+    fn should_resolve_dependencies_with_a_custom_resolver() {
+        let fixture = Fixture::new("custom-resolver");
+        fixture
+            .package("app", "app", &["lib", "from-node-modules"])
+            .package("libraries/lib-1.0", "lib", &[])
+            .package("app/node_modules/from-node-modules", "from-node-modules", &[]);
+        let libraries = fixture.path("libraries");
+        let options = super::MakeOptions {
+            resolve_dependency: Some(super::DependencyResolver(std::sync::Arc::new(
+                move |package_name: &str, _parent_path: &str| match package_name {
+                    "lib" => Some(format!("{}/lib-1.0", libraries)),
+                    _ => None,
+                },
+            ))),
+            ..Default::default()
+        };
+
+        let packages = super::make_with(&None, &fixture.path("app"), &None, &options)
+            .unwrap()
+            .packages;
+
+        assert_eq!(packages["lib"].path, fixture.path("libraries/lib-1.0"));
+        assert_eq!(
+            packages["from-node-modules"].path,
+            fixture.path("app/node_modules/from-node-modules")
+        );
+    }
+
+    #[test]
+    fn should_resolve_dependencies_from_a_custom_node_modules_dir() {
+        let fixture = Fixture::new("node-modules-dir");
+        fixture
+            .package("", "root", &["dep"])
+            .package("deps/dep", "dep", &["nested"])
+            .package("deps/nested", "nested", &[])
+            .package("node_modules/dep", "dep", &[]);
+        let options = super::MakeOptions {
+            node_modules_dir: Some("deps".to_string()),
+            ..Default::default()
+        };
+
+        let packages = super::make_with(&None, &fixture.path(""), &None, &options)
+            .unwrap()
+            .packages;
+
+        assert_eq!(packages["dep"].path, fixture.path("deps/dep"));
+        assert_eq!(packages["nested"].path, fixture.path("deps/nested"));
+        assert!(!packages["dep"].is_local);
+        assert!(packages["root"].is_local);
+    }
+
+    #[test]
+    fn should_only_look_in_node_path_for_a_dependency_that_is_not_in_node_modules() {
+        let fixture = Fixture::new("node-path");
+        fixture
+            .package("", "root", &["dep", "global"])
+            .package("node_modules/dep", "dep", &[])
+            .package("global-modules/dep", "dep", &[])
+            .package("global-modules/global", "global", &[]);
+        std::env::set_var("NODE_PATH", fixture.path("global-modules"));
+        let make = |use_node_path| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    use_node_path,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .packages
+        };
+
+        let packages = make(true);
+        let without_node_path = make(false);
+
+        assert_eq!(packages["dep"].path, fixture.path("node_modules/dep"));
+        assert_eq!(packages["global"].path, fixture.path("global-modules/global"));
+        assert!(!packages["global"].is_local);
+        assert!(!without_node_path.contains_key("global"));
+    }
+
+    #[test]
+    fn should_use_the_suffix_of_the_package_itself() {
+        let fixture = Fixture::new("js-suffix");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "suffix": ".mjs", "bs-dependencies": ["own", "inherited"] }"#,
+            )
+            .write(
+                "node_modules/own/bsconfig.json",
+                r#"{ "name": "own", "sources": "src",
+                     "package-specs": { "module": "commonjs", "in-source": true, "suffix": ".bs.js" } }"#,
+            )
+            .write(
+                "node_modules/inherited/bsconfig.json",
+                r#"{ "name": "inherited", "sources": "src" }"#,
+            );
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let root = &packages["root"];
+
+        assert_eq!(root.js_suffix(root), ".mjs");
+        assert_eq!(packages["own"].js_suffix(root), ".bs.js");
+        assert_eq!(packages["inherited"].js_suffix(root), ".mjs");
+    }
+
+    #[test]
+    fn should_explain_all_paths_to_a_package() {
+        let fixture = Fixture::new("explain-package");
+        fixture
+            .package("", "root", &["left", "right"])
+            .package("node_modules/left", "left", &["shared"])
+            .package("node_modules/right", "right", &["shared"])
+            .package("node_modules/shared", "shared", &[]);
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::explain_package(&packages, "shared", 10),
+            vec![vec!["root", "left", "shared"], vec!["root", "right", "shared"]]
+        );
+        assert_eq!(
+            super::explain_package(&packages, "shared", 1),
+            vec![vec!["root", "left", "shared"]]
+        );
+        assert_eq!(super::explain_package(&packages, "root", 10), vec![vec!["root"]]);
+    }
+
+    #[test]
+    fn should_not_pick_up_files_generated_by_gentype_as_sources() {
+        let fixture = Fixture::new("gentype-files");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["custom"] }"#,
+            )
+            .write("src/Foo.res", "let a = 1")
+            .write("src/Foo.gen.tsx", "export const a = 1")
+            .write(
+                "node_modules/custom/bsconfig.json",
+                r#"{ "name": "custom", "sources": "src", "gentypeconfig": { "generatedFileExtension": ".gen.ml" } }"#,
+            )
+            .write("node_modules/custom/src/Bar.res", "let a = 1")
+            .write("node_modules/custom/src/Bar.gen.ml", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            Vec::from_iter(packages["root"].modules.to_owned().unwrap()),
+            vec!["Foo"]
+        );
+        assert_eq!(
+            Vec::from_iter(packages["custom"].source_files.to_owned().unwrap().into_keys()),
+            vec!["src/Bar.res"]
+        );
+    }
+
+    #[test]
+    fn should_report_modules_with_a_missing_or_outdated_artifact() {
+        let fixture = Fixture::new("stale-modules");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "namespace": true }"#,
+            )
+            .write("src/Fresh.res", "let a = 1")
+            .write("src/Outdated.res", "let a = 1")
+            .write("src/Missing.res", "let a = 1");
+        let set_modified = |path: &str, modified: std::time::SystemTime| {
+            std::fs::File::options()
+                .write(true)
+                .open(fixture.path(path))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fixture
+            .write("lib/bs/src/Fresh-Root.cmj", "")
+            .write("lib/bs/src/Outdated-Root.cmj", "");
+        set_modified("src/Fresh.res", earlier);
+        set_modified("lib/bs/src/Outdated-Root.cmj", earlier);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::stale_modules(&packages),
+            AHashSet::from_iter(["Outdated-Root".to_string(), "Missing-Root".to_string()])
+        );
+    }
+
+    #[test]
+    fn should_tag_dependency_edges_with_their_kind() {
+        let fixture = Fixture::new("dependency-edges");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["normal", "peer"], "bs-dev-dependencies": ["dev"] }"#,
+            )
+            .write("package.json", r#"{ "name": "root", "peerDependencies": { "peer": "*" } }"#)
+            .mkdir("src")
+            .package("node_modules/normal", "normal", &[])
+            .package("node_modules/peer", "peer", &[])
+            .package("node_modules/dev", "dev", &[]);
+        let edge = |to: &str, kind| super::DependencyEdge {
+            from: "root".to_string(),
+            to: to.to_string(),
+            kind,
+        };
+
+        let with_dev = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                include_dev: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let without_dev = super::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+
+        assert_eq!(
+            with_dev.edges,
+            vec![
+                edge("dev", super::DependencyKind::Dev),
+                edge("normal", super::DependencyKind::Normal),
+                edge("peer", super::DependencyKind::Peer),
+            ]
+        );
+        assert!(with_dev.packages.contains_key("dev"));
+        assert_eq!(
+            without_dev.edges,
+            vec![
+                edge("normal", super::DependencyKind::Normal),
+                edge("peer", super::DependencyKind::Peer),
+            ]
+        );
+        assert!(!without_dev.packages.contains_key("dev"));
+    }
+
+    #[test]
+    fn should_resolve_path_dependencies_relative_to_the_package() {
+        let fixture = Fixture::new("path-dependencies");
+        fixture
+            .package("app", "app", &["../shared"])
+            .package("shared", "shared", &[])
+            .write("shared/src/Shared.res", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path("app"), &None);
+
+        let shared = &packages["shared"];
+        assert_eq!(shared.name, "shared");
+        assert_eq!(shared.path, fixture.path("shared"));
+        assert!(shared.is_local);
+        assert_eq!(Vec::from_iter(shared.modules.to_owned().unwrap()), vec!["Shared"]);
+        assert_eq!(
+            packages["app"].bsconfig.bs_dependencies,
+            Some(vec!["shared".to_string()])
+        );
+    }
+
+    #[test]
+    fn should_resolve_the_same_relative_path_to_another_package_for_another_parent() {
+        let fixture = Fixture::new("path-dependencies-per-parent");
+        fixture
+            .package("", "root", &["./app", "./lib/a"])
+            .package("app", "app", &["../shared"])
+            .package("shared", "shared", &[])
+            .package("lib/a", "a", &["../shared"])
+            .package("lib/shared", "lib-shared", &[]);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(packages["shared"].path, fixture.path("shared"));
+        assert_eq!(packages["lib-shared"].path, fixture.path("lib/shared"));
+        assert_eq!(
+            packages["a"].bsconfig.bs_dependencies,
+            Some(vec!["lib-shared".to_string()])
+        );
+        assert_eq!(
+            packages["root"].bsconfig.bs_dependencies,
+            Some(vec!["app".to_string(), "a".to_string()])
+        );
+    }
+
+    #[test]
+    fn should_find_source_files_without_a_counterpart() {
+        let fixture = Fixture::new("unpaired-source-files");
+        fixture
+            .package("", "root", &[])
+            .write("src/Paired.res", "let a = 1")
+            .write("src/Paired.resi", "let a: int")
+            .write("src/NoInterface.res", "let a = 1")
+            .write("src/Orphan.resi", "let a: int");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::modules_without_interface(&packages),
+            vec![("root".to_string(), "src/NoInterface.res".to_string())]
+        );
+        assert_eq!(
+            super::interfaces_without_implementation(&packages),
+            vec![("root".to_string(), "src/Orphan.resi".to_string())]
+        );
+    }
+
+    #[test]
+    fn should_only_keep_the_changed_source_files() {
+        let fixture = Fixture::new("changed-paths");
+        fixture
+            .package("", "root", &["dep"])
+            .write("src/Changed.res", "let a = 1")
+            .write("src/Unchanged.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                changed_paths: Some(AHashSet::from_iter([
+                    std::path::PathBuf::from("src/Changed.res"),
+                    std::path::PathBuf::from("src/Deleted.res"),
+                ])),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            Vec::from_iter(tree.packages["root"].modules.to_owned().unwrap()),
+            vec!["Changed"]
+        );
+        assert!(tree.packages["dep"].modules.to_owned().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_keep_the_files_with_an_invalid_module_name() {
+        let fixture = Fixture::new("invalid-module-name");
+        fixture
+            .package("", "root", &[])
+            .write("src/Valid.res", "let a = 1")
+            .write("src/1invalid.res", "let b = 1");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        let mut modules = tree.packages["root"]
+            .modules
+            .to_owned()
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        modules.sort();
+        assert_eq!(modules, vec!["1invalid", "Valid"]);
+        let invalid = tree
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::InvalidModuleName)
+            .collect::<Vec<_>>();
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn should_trace_resolved_dependencies() {
+        let fixture = Fixture::new("trace");
+        fixture
+            .package("", "root", &["a"])
+            .package("node_modules/a", "a", &["b"])
+            .write("node_modules/a/src/A.res", "let a = 1")
+            .package("node_modules/b", "b", &[]);
+        let make = |trace| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    trace,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .diagnostics
+        };
+
+        let traces = make(true)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Trace)
+            .map(|d| d.message)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            traces,
+            vec![
+                format!(
+                    "Resolved \"a\" from {}, found 1 sources, 1 dependencies",
+                    fixture.path("node_modules/a")
+                ),
+                format!(
+                    "Resolved \"b\" from {}, found 0 sources, 0 dependencies",
+                    fixture.path("node_modules/b")
+                ),
+            ]
+        );
+        assert!(make(false).iter().all(|d| d.severity != Severity::Trace));
+    }
+
+    #[test]
+    fn should_point_out_a_misspelled_dependency_field() {
+        let fixture = Fixture::new("misspelled-field");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bsDependencies": ["dep"], "dependencies": { "bs": ["dep"] } }"#,
+            )
+            .mkdir("src");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+
+        assert_eq!(
+            tree.diagnostics
+                .iter()
+                .filter(|d| d.kind == DiagnosticKind::MisspelledField)
+                .map(|d| (d.severity, d.message.as_str()))
+                .collect::<Vec<(Severity, &str)>>(),
+            vec![(
+                Severity::Info,
+                "The package root has an unknown field \"bsDependencies\", did you mean \"bs-dependencies\"?"
+            )]
+        );
+        assert_eq!(tree.packages.len(), 1);
+    }
+
+    #[test]
+    fn should_build_the_module_graph() {
+        let fixture = Fixture::new("module-graph");
+        fixture
+            .package("", "root", &["dep"])
+            .write(
+                "src/Main.res",
+                "open Utils\nlet a = Log.info(Dep.value)\nmodule U = Utils",
+            )
+            .write("src/Utils.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let value = Js.log(1)");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let graph = super::build_module_graph(&packages);
+
+        assert_eq!(
+            graph["Main"],
+            AHashSet::from_iter(["Utils".to_string(), "Dep".to_string()])
+        );
+        assert!(graph["Utils"].is_empty());
+        assert!(graph["Dep"].is_empty());
+    }
+
+    #[test]
+    fn should_skip_dev_dependencies_in_production_mode() {
+        let fixture = Fixture::new("production-only");
+        fixture
+            .package("", "root", &["shipped", "tooling"])
+            .write(
+                "package.json",
+                r#"{ "name": "root", "dependencies": { "shipped": "1.0.0" }, "devDependencies": { "tooling": "1.0.0" } }"#,
+            )
+            .package("node_modules/shipped", "shipped", &[])
+            .package("node_modules/tooling", "tooling", &[]);
+        let make = |production_only, include_dev| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    production_only,
+                    include_dev,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let production = make(true, false);
+        let mut package_names = Vec::from_iter(production.packages.keys().cloned());
+        package_names.sort();
+
+        assert_eq!(package_names, vec!["root", "shipped"]);
+        assert_eq!(
+            production.packages["root"].bsconfig.bs_dependencies,
+            Some(vec!["shipped".to_string()])
+        );
+        assert!(production
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::SkippedDevDependency));
+        assert!(make(true, true).packages.contains_key("tooling"));
+        assert!(make(false, false).packages.contains_key("tooling"));
+    }
+
+    #[test]
+    fn should_group_source_files_by_extension() {
+        let fixture = Fixture::new("source-files-by-extension");
+        fixture
+            .package("", "root", &[])
+            .write("src/A.res", "let a = 1")
+            .write("src/A.resi", "let a: int")
+            .write("src/B.res", "let b = 1")
+            .write("src/C.ml", "let c = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let mut by_extension = Vec::from_iter(packages["root"].source_files_by_extension());
+        by_extension.sort();
+
+        assert_eq!(
+            by_extension,
+            vec![
+                ("ml".to_string(), vec!["src/C.ml".to_string()]),
+                (
+                    "res".to_string(),
+                    vec!["src/A.res".to_string(), "src/B.res".to_string()]
+                ),
+                ("resi".to_string(), vec!["src/A.resi".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_prefix_the_namespace_of_local_packages() {
+        let fixture = Fixture::new("namespace-prefix");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "my-app", "sources": "src", "namespace": true, "bs-dependencies": ["shared", "third-party"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write(
+                "packages/shared/bsconfig.json",
+                r#"{ "name": "shared", "sources": "src", "namespace": true }"#,
+            )
+            .mkdir("packages/shared/src")
+            .symlink("packages/shared", "node_modules/shared")
+            .write(
+                "node_modules/third-party/bsconfig.json",
+                r#"{ "name": "third-party", "sources": "src", "namespace": true }"#,
+            )
+            .mkdir("node_modules/third-party/src");
+        let make = |prefix: &str| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    force_namespace_prefix: Some(prefix.to_string()),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let namespace = |tree: &super::PackageTree, name: &str| tree.packages[name].namespace.to_suffix();
+
+        let tree = make("Org");
+        let invalid = make("org-");
+
+        assert_eq!(namespace(&tree, "my-app"), Some("Org_MyApp".to_string()));
+        assert_eq!(namespace(&tree, "shared"), Some("Org_Shared".to_string()));
+        assert_eq!(namespace(&tree, "third-party"), Some("ThirdParty".to_string()));
+        assert!(tree.packages["my-app"]
+            .modules
+            .as_ref()
+            .unwrap()
+            .contains("Main-Org_MyApp"));
+        let args = tree.packages["my-app"]
+            .compile_args(
+                "Main-Org_MyApp",
+                &tree.packages["my-app"],
+                &tree.packages,
+                "11.0.0",
+                &None,
+            )
+            .unwrap();
+        assert!(args
+            .implementation
+            .windows(2)
+            .any(|arg| arg == ["-bs-ns", "Org_MyApp"]));
+        assert_eq!(namespace(&invalid, "my-app"), Some("MyApp".to_string()));
+        assert!(invalid
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::InvalidNamespace && d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn should_resolve_the_output_dir() {
+        let fixture = Fixture::new("output-dir");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "package-specs": { "module": "es6", "output-dir": "dist/js" }, "bs-dependencies": ["default", "in-source"] }"#,
+            )
+            .mkdir("src")
+            .package("node_modules/default", "default", &[])
+            .write(
+                "node_modules/in-source/bsconfig.json",
+                r#"{ "name": "in-source", "sources": "src", "package-specs": { "module": "commonjs", "in-source": true } }"#,
+            )
+            .mkdir("node_modules/in-source/src");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(packages["root"].output_dir(), fixture.path("dist/js"));
+        assert_eq!(
+            packages["default"].output_dir(),
+            fixture.path("node_modules/default/lib/js")
+        );
+        assert_eq!(
+            packages["in-source"].output_dir(),
+            fixture.path("node_modules/in-source")
+        );
+    }
+
+    #[test]
+    fn should_prefer_a_non_dev_source_for_the_same_dir() {
+        let fixture = Fixture::new("overlapping-sources");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [{ "dir": "src", "type": "dev" }, "src", { "dir": "test", "type": "dev" }] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write("test/Test.res", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let root = &packages["root"];
+        let mut source_folders = Vec::from_iter(
+            root.source_folders
+                .iter()
+                .map(|source| (source.dir.as_str(), source.type_.as_deref())),
+        );
+        source_folders.sort();
+
+        assert_eq!(source_folders, vec![("src", None), ("test", Some("dev"))]);
+        assert_eq!(Vec::from_iter(root.modules.to_owned().unwrap()), vec!["Main"]);
+    }
+
+    #[test]
+    fn should_report_unreferenced_packages() {
+        let mut packages: AHashMap<String, Package> = AHashMap::new();
+        let package = |name: &str, bs_deps: Vec<&str>, dev_deps: Vec<&str>, is_root: bool| Package {
+            is_root,
+            ..create_package(
+                name.to_string(),
+                bs_deps.into_iter().map(String::from).collect(),
+                vec![],
+                dev_deps.into_iter().map(String::from).collect(),
+                None,
+            )
+        };
+        packages.insert("root".to_string(), package("root", vec!["a"], vec!["dev"], true));
+        packages.insert("a".to_string(), package("a", vec!["b"], vec![], false));
+        packages.insert("b".to_string(), package("b", vec![], vec![], false));
+        packages.insert("dev".to_string(), package("dev", vec![], vec![], false));
+        packages.insert("stray".to_string(), package("stray", vec!["b"], vec![], false));
+
+        assert_eq!(super::unreferenced_packages(&packages, "root"), vec!["stray"]);
+    }
+
+    #[test]
+    fn should_scan_dev_sources_per_the_policy() {
+        let fixture = Fixture::new("dev-sources");
+        let bsconfig = |name: &str, deps: &str| {
+            format!(
+                r#"{{ "name": "{}", "sources": ["src", {{ "dir": "test", "type": "dev" }}], "bs-dependencies": [{}] }}"#,
+                name, deps
+            )
+        };
+        fixture
+            .write("bsconfig.json", &bsconfig("root", r#""dep""#))
+            .write("src/Root.res", "let a = 1")
+            .write("test/RootTest.res", "let a = 1")
+            .write("node_modules/dep/bsconfig.json", &bsconfig("dep", ""))
+            .write("node_modules/dep/src/Dep.res", "let a = 1")
+            .write("node_modules/dep/test/DepTest.res", "let a = 1");
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                dev_sources: super::DevSourcePolicy {
+                    root: true,
+                    deps: false,
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let modules = |name: &str| {
+            let mut modules = Vec::from_iter(tree.packages[name].modules.to_owned().unwrap());
+            modules.sort();
+            modules
+        };
+
+        assert_eq!(modules("root"), vec!["Root", "RootTest"]);
+        assert_eq!(modules("dep"), vec!["Dep"]);
+    }
+
+    #[test]
+    fn should_recompute_the_modules_for_a_new_namespace() {
+        let fixture = Fixture::new("recompute-modules");
+        fixture
+            .package("", "root", &[])
+            .write("src/Main.res", "let a = 1");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let mut root = packages["root"].to_owned();
+        let modules = |package: &Package| {
+            let mut modules = Vec::from_iter(package.modules.to_owned().unwrap());
+            modules.sort();
+            modules
+        };
+        assert_eq!(modules(&root), vec!["Main"]);
+
+        root.namespace = Namespace::Namespace("App".to_string());
+        let diagnostics = root.recompute_modules();
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(modules(&root), vec!["App", "Main-App"]);
+    }
+
+    #[test]
+    fn should_report_and_prune_a_package_nested_in_a_source_folder() {
+        let fixture = Fixture::new("nested-package");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": { "dir": "src", "subdirs": true }, "bs-dependencies": ["child"] }"#,
+            )
+            .write("src/Root.res", "let a = 1")
+            .package("src/child", "child", &[])
+            .write("src/child/src/Child.res", "let a = 1")
+            .symlink("src/child", "node_modules/child");
+        let make = |prune_nested_packages| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    prune_nested_packages,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let modules = |tree: &super::PackageTree| {
+            let mut modules = Vec::from_iter(tree.packages["root"].modules.to_owned().unwrap());
+            modules.sort();
+            modules
+        };
+
+        let tree = make(false);
+        let pruned = make(true);
+
+        assert_eq!(
+            tree.diagnostics
+                .iter()
+                .filter(|d| d.kind == DiagnosticKind::NestedPackageSource)
+                .map(|d| d.path.to_owned())
+                .collect::<Vec<String>>(),
+            vec![tree.packages["root"].path.to_owned()]
+        );
+        assert_eq!(modules(&tree), vec!["Child", "Root"]);
+        assert_eq!(modules(&pruned), vec!["Root"]);
+        assert_eq!(
+            Vec::from_iter(pruned.packages["child"].modules.to_owned().unwrap()),
+            vec!["Child"]
+        );
+    }
+
+    #[test]
+    fn should_run_custom_validators() {
+        let fixture = Fixture::new("custom-validators");
+        fixture
+            .package("", "root", &["dep"])
+            .write("src/Root.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[]);
+        let require_namespace = super::Validator(std::sync::Arc::new(|packages| {
+            packages
+                .values()
+                .filter(|package| package.namespace.to_suffix().is_none())
+                .filter(|package| {
+                    package
+                        .modules
+                        .as_ref()
+                        .is_some_and(|modules| !modules.is_empty())
+                })
+                .map(|package| {
+                    super::Diagnostic::warning(
+                        DiagnosticKind::InvalidNamespace,
+                        &package.path,
+                        format!("The package {} must be namespaced", package.name),
+                    )
+                })
+                .collect()
+        }));
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                validators: vec![require_namespace],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            tree.diagnostics
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["The package root must be namespaced"]
+        );
+    }
+
+    #[test]
+    fn should_include_the_runtime_when_asked() {
+        let fixture = Fixture::new("runtime");
+        fixture
+            .package("", "root", &[])
+            .write("node_modules/rescript/package.json", r#"{ "name": "rescript" }"#)
+            .write("node_modules/rescript/lib/ocaml/belt.res", "")
+            .write("node_modules/rescript/lib/ocaml/belt_Array.resi", "")
+            .write("node_modules/rescript/lib/ocaml/js.ml", "");
+        let make = |include_runtime| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    include_runtime,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let tree = make(true);
+        let runtime = &tree.packages["rescript"];
+        let mut modules = Vec::from_iter(runtime.modules.to_owned().unwrap());
+        modules.sort();
+
+        assert!(runtime.is_runtime);
+        assert!(!tree.packages["root"].is_runtime);
+        assert_eq!(modules, vec!["Belt", "Belt_Array", "Js"]);
+        assert!(!std::path::Path::new(&fixture.path("node_modules/rescript/lib/bs")).exists());
+        assert!(!make(false).packages.contains_key("rescript"));
+    }
+
+    #[test]
+    fn should_print_the_tree_as_an_outline() {
+        let fixture = Fixture::new("print-tree");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "namespace": true, "bs-dependencies": ["a", "b"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .package("node_modules/a", "a", &["b"])
+            .write("node_modules/a/src/A.res", "let a = 1")
+            .package("node_modules/b", "b", &["a"]);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::print_tree(&packages, "root"),
+            [
+                "root (Root, 2 modules)",
+                "  a (no namespace, 1 modules)",
+                "    b (no namespace, 0 modules)",
+                "      a deduped",
+                "  b deduped",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn should_only_warn_about_an_unresolved_peer_dependency() {
+        let fixture = Fixture::new("unresolved-peer");
+        fixture
+            .package("", "root", &["lib", "missing"])
+            .package("node_modules/lib", "lib", &["peer"])
+            .write(
+                "node_modules/lib/package.json",
+                r#"{ "name": "lib", "peerDependencies": { "peer": "*" } }"#,
+            );
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+        let unresolved = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::UnresolvedDependency)
+            .map(|d| (d.path.to_owned(), d.severity))
+            .collect::<Vec<(String, Severity)>>();
+
+        assert_eq!(
+            unresolved,
+            vec![
+                (
+                    fixture.path("node_modules/lib/node_modules/peer"),
+                    Severity::Warning
+                ),
+                (fixture.path("node_modules/missing"), Severity::Error),
+            ]
+        );
+        // the config is kept as it was read, only the edges leave out what isn't in the tree
+        assert_eq!(
+            tree.packages["lib"].bsconfig.bs_dependencies,
+            Some(vec!["peer".to_string()])
+        );
+        assert!(!tree.packages.contains_key("peer"));
+        assert!(!tree
+            .edges
+            .iter()
+            .any(|edge| edge.to == "peer" || edge.to == "missing"));
+    }
+
+    #[test]
+    fn should_find_the_same_modules_scanning_sequentially_or_in_parallel() {
+        let fixture = Fixture::new("parallel-scan");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": ["a", "b", "c"], "bs-dependencies": ["dep"] }"#,
+            )
+            .write("a/A.res", "let a = 1")
+            .write("b/B.res", "let b = 1")
+            .write("c/C.res", "let c = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let modules = |parallel_scan_threshold| {
+            let tree = super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    parallel_scan_threshold: Some(parallel_scan_threshold),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let mut modules = tree
+                .packages
+                .values()
+                .flat_map(|package| package.modules.to_owned().unwrap())
+                .collect::<Vec<super::ModuleName>>();
+            modules.sort();
+            modules
+        };
+
+        assert_eq!(modules(0), vec!["A", "B", "C", "Dep"]);
+        assert_eq!(modules(usize::MAX), modules(0));
+    }
+
+    #[test]
+    fn should_scan_every_package_of_a_batch() {
+        let fixture = Fixture::new("batched-scan");
+        // more tiny packages than fit in a batch
+        let names = (0..40).map(|i| format!("tiny-{}", i)).collect::<Vec<String>>();
+        let dependencies = names.iter().map(|name| name.as_str()).collect::<Vec<&str>>();
+        fixture.package("", "root", &dependencies);
+        names.iter().enumerate().for_each(|(i, name)| {
+            fixture
+                .package(&format!("node_modules/{}", name), name, &[])
+                .write(&format!("node_modules/{}/src/Tiny{}.res", name, i), "let a = 1");
+        });
+        let modules = |parallel_scan_threshold| {
+            let tree = super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    parallel_scan_threshold,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let mut modules = tree
+                .packages
+                .values()
+                .flat_map(|package| package.modules.to_owned().unwrap())
+                .collect::<Vec<super::ModuleName>>();
+            modules.sort();
+            modules
+        };
+
+        assert_eq!(modules(None).len(), 40);
+        assert_eq!(modules(None), modules(Some(0)));
+    }
+
+    #[test]
+    fn should_resolve_the_config_of_a_dependency() {
+        let fixture = Fixture::new("resolved-config");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{
+                    "name": "root",
+                    "sources": "src",
+                    "bs-dependencies": ["dep"],
+                    "package-specs": { "module": "es6" },
+                    "suffix": ".mjs",
+                    "jsx": { "version": 4, "mode": "automatic" },
+                    "uncurried": false
+                }"#,
+            )
+            .mkdir("src")
+            .write(
+                "node_modules/dep/base.json",
+                r#"{ "bsc-flags": ["-open Belt"], "warnings": { "number": "-44" } }"#,
+            )
+            .write(
+                "node_modules/dep/bsconfig.json",
+                r#"{ "name": "dep", "sources": "src", "extends": "./base.json", "jsx": { "version": 3 } }"#,
+            )
+            .mkdir("node_modules/dep/src");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let config = super::resolved_config(&packages, &fixture.path("node_modules/dep")).unwrap();
+
+        assert_eq!(config.name, "dep");
+        assert_eq!(
+            crate::bsconfig::flatten_flags(&config.bsc_flags),
+            vec!["-open", "Belt"]
+        );
+        assert_eq!(config.warnings.as_ref().unwrap().number, Some("-44".to_string()));
+        assert_eq!(config.get_module(), "es6");
+        assert_eq!(config.get_suffix(), ".mjs");
+        assert_eq!(config.get_jsx_args(), vec!["-bs-jsx", "4"]);
+        assert_eq!(config.get_jsx_mode_args(), vec!["-bs-jsx-mode", "automatic"]);
+        assert_eq!(config.uncurried, Some(false));
+        assert_eq!(config.bs_dependencies, Some(vec![]));
+        assert!(super::resolved_config(&packages, &fixture.path("node_modules/missing")).is_none());
+    }
+
+    #[test]
+    fn should_not_mangle_absolute_source_dirs() {
+        let fixture = Fixture::new("absolute-source-dir");
+        fixture
+            .write(
+                "app/bsconfig.json",
+                &format!(
+                    r#"{{ "name": "app", "sources": [{:?}, {:?}] }}"#,
+                    fixture.path("app/src"),
+                    fixture.path("shared")
+                ),
+            )
+            .write("app/src/App.res", "let a = 1")
+            .write("shared/Shared.res", "let a = 1");
+
+        let root = fixture.path("app");
+        let packages = super::make(&None, &root, &None);
+        let mut source_files = packages["app"]
+            .source_files
+            .as_ref()
+            .unwrap()
+            .keys()
+            .map(|path| {
+                std::path::Path::new(&root)
+                    .join(path)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+        source_files.sort();
+
+        assert_eq!(
+            source_files,
+            vec![fixture.path("app/src/App.res"), fixture.path("shared/Shared.res")]
+        );
+        assert!(packages["app"]
+            .source_files
+            .as_ref()
+            .unwrap()
+            .contains_key("src/App.res"));
+    }
+
+    #[test]
+    fn should_group_packages_sharing_a_namespace() {
+        let fixture = Fixture::new("shared-namespace");
+        fixture
+            .package("", "root", &["a", "b", "c"])
+            .write(
+                "node_modules/a/bsconfig.json",
+                r#"{ "name": "a", "sources": "src", "namespace": "Shared" }"#,
+            )
+            .write(
+                "node_modules/b/bsconfig.json",
+                r#"{ "name": "b", "sources": "src", "namespace": "Shared", "namespace-entry": "B" }"#,
+            )
+            .write(
+                "node_modules/c/bsconfig.json",
+                r#"{ "name": "c", "sources": "src", "namespace": true }"#,
+            );
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let groups = super::packages_by_namespace(&packages);
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups["Shared"], vec!["a", "b"]);
+        assert_eq!(groups["C"], vec!["c"]);
+        assert_eq!(groups[""], vec!["root"]);
+    }
+
+    #[test]
+    fn should_report_source_files_outside_the_source_folders() {
+        let fixture = Fixture::new("unreferenced-sources");
+        fixture
+            .package("", "root", &["dep"])
+            .write("src/Main.res", "let a = 1")
+            .write("Stray.res", "let a = 1")
+            .write("scripts/Tool.resi", "let a: int")
+            .write("lib/bs/src/Main.res", "let a = 1")
+            .write("README.md", "")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/Other.res", "let a = 1");
+        let make = |report_unreferenced_sources| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    report_unreferenced_sources,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .diagnostics
+            .into_iter()
+            .filter(|d| d.kind == DiagnosticKind::UnreferencedSourceFile)
+            .map(|d| d.path)
+            .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            make(true),
+            vec![fixture.path("Stray.res"), fixture.path("scripts/Tool.resi")]
+        );
+        assert!(make(false).is_empty());
+    }
+
+    #[test]
+    fn should_list_the_include_paths_of_a_package() {
+        let fixture = Fixture::new("include-paths");
+        fixture
+            .package("", "root", &["a", "b"])
+            .package("node_modules/a", "a", &["c"])
+            .package("node_modules/b", "b", &["c"])
+            .package("node_modules/c", "c", &["a"]);
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            packages["root"].include_paths(&packages),
+            vec![
+                fixture.path("lib/bs"),
+                fixture.path("node_modules/a/lib/bs"),
+                fixture.path("node_modules/b/lib/bs"),
+                fixture.path("node_modules/c/lib/bs"),
+            ]
+        );
+        assert_eq!(
+            packages["c"].include_paths(&packages),
+            vec![
+                fixture.path("node_modules/c/lib/bs"),
+                fixture.path("node_modules/a/lib/bs")
+            ]
+        );
+    }
+
+    #[test]
+    fn should_round_trip_the_tree_through_the_cache() {
+        let fixture = Fixture::new("tree-cache");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": { "dir": "src", "subdirs": true }, "namespace": "App", "bs-dependencies": ["dep"], "custom-field": 1 }"#,
+            )
+            .write("src/nested/Main.res", "let a = 1")
+            // not a valid module name, it is still in the tree (and in the cache)
+            .write("src/1bad.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.resi", "let a: int");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let cache_path = fixture.path("tree.json");
+        assert!(packages["root"].modules.as_ref().unwrap().contains("1bad-App"));
+
+        super::save_tree(&packages, &cache_path).unwrap();
+        let loaded = super::load_tree(&cache_path).unwrap();
+
+        assert_eq!(loaded.len(), packages.len());
+        packages.iter().for_each(|(name, package)| {
+            let cached = &loaded[name];
+            assert_eq!(cached.bsconfig, package.bsconfig);
+            assert_eq!(cached.source_folders, package.source_folders);
+            assert_eq!(cached.modules, package.modules);
+            assert_eq!(cached.dirs, package.dirs);
+            assert_eq!(cached.path, package.path);
+            assert_eq!(cached.namespace.to_suffix(), package.namespace.to_suffix());
+            assert_eq!(
+                (cached.is_root, cached.is_local, cached.is_pinned_dep),
+                (package.is_root, package.is_local, package.is_pinned_dep)
+            );
+            let source_files = package.source_files.as_ref().unwrap();
+            let cached_source_files = cached.source_files.as_ref().unwrap();
+            assert_eq!(cached_source_files.len(), source_files.len());
+            source_files.iter().for_each(|(path, meta)| {
+                assert_eq!(cached_source_files[path].modified, meta.modified);
+                assert_eq!(cached_source_files[path].len, meta.len);
+            });
+        });
+        assert_eq!(
+            super::tree_fingerprint(&loaded),
+            super::tree_fingerprint(&packages)
+        );
+    }
+
+    #[test]
+    fn should_treat_an_incompatible_cache_as_absent() {
+        let fixture = Fixture::new("tree-cache-version");
+        fixture
+            .write(
+                "old.json",
+                r#"{ "version": 0, "rewatch_version": "1.0.0", "packages": {} }"#,
+            )
+            .write("broken.json", "{");
+
+        assert!(super::load_tree(&fixture.path("old.json")).is_none());
+        assert!(super::load_tree(&fixture.path("broken.json")).is_none());
+        assert!(super::load_tree(&fixture.path("missing.json")).is_none());
+    }
+
+    #[test]
+    fn should_report_a_source_that_is_a_file() {
+        let fixture = Fixture::new("source-is-file");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": ["src/index.res", "other"] }"#,
+            )
+            .write("src/index.res", "let a = 1");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+        let diagnostics = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::SourceIsFile)
+            .collect::<Vec<&super::Diagnostic>>();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, fixture.path("src/index.res"));
+        assert_eq!(
+            diagnostics[0].message,
+            "The source \"src/index.res\" is a file instead of a folder, did you mean \"src\"?"
+        );
+    }
+
+    #[test]
+    fn should_list_the_modules_affected_by_a_namespace_change() {
+        let fixture = Fixture::new("namespace-change");
+        fixture
+            .package("", "root", &[])
+            .write("src/Main.res", "let a = 1")
+            .write("src/Utils.resi", "let a: int");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let root = &packages["root"];
+
+        let mut affected = Vec::from_iter(super::modules_affected_by_namespace_change(
+            root,
+            &Namespace::Namespace("App".to_string()),
+        ));
+        affected.sort();
+
+        assert_eq!(affected, vec!["App", "Main", "Main-App", "Utils", "Utils-App"]);
+        assert!(super::modules_affected_by_namespace_change(root, &Namespace::NoNamespace).is_empty());
+    }
+
+    fn dependents_fixture(fixture: &Fixture) -> AHashMap<String, Package> {
+        fixture
+            .package("", "root", &["app", "other"])
+            .package("node_modules/app", "app", &["lib"])
+            .package("node_modules/other", "other", &[])
+            .package("node_modules/lib", "lib", &[])
+            .write("node_modules/lib/src/Lib.res", "let a = 1")
+            .write("node_modules/lib/src/Lib.resi", "let a: int");
+        super::make(&None, &fixture.path(""), &None)
+    }
+
+    #[test]
+    fn should_rebuild_the_dependents_after_an_interface_change() {
+        let fixture = Fixture::new("interface-change");
+        let packages = dependents_fixture(&fixture);
+
+        let mut rebuild = Vec::from_iter(super::dependents_to_rebuild(&packages, "lib", "Lib", true));
+        rebuild.sort();
+
+        assert_eq!(rebuild, vec!["app", "lib", "root"]);
+    }
+
+    #[test]
+    fn should_only_rebuild_the_package_after_an_implementation_change() {
+        let fixture = Fixture::new("implementation-change");
+        let packages = dependents_fixture(&fixture);
+
+        assert_eq!(
+            super::dependents_to_rebuild(&packages, "lib", "Lib", false),
+            AHashSet::from_iter(["lib".to_string()])
+        );
+        assert!(super::dependents_to_rebuild(&packages, "lib", "Missing", true).is_empty());
+        assert!(super::dependents_to_rebuild(&packages, "other", "Lib", true).is_empty());
+    }
+
+    #[test]
+    fn should_rebuild_the_dependents_after_a_change_to_an_implementation_without_an_interface() {
+        let fixture = Fixture::new("implementation-without-interface-change");
+        let packages = {
+            dependents_fixture(&fixture);
+            fixture.write("node_modules/lib/src/Public.res", "let a = 1");
+            super::make(&None, &fixture.path(""), &None)
+        };
+
+        let mut rebuild = Vec::from_iter(super::dependents_to_rebuild(&packages, "lib", "Public", false));
+        rebuild.sort();
+
+        assert_eq!(rebuild, vec!["app", "lib", "root"]);
+    }
+
+    #[test]
+    fn should_only_rebuild_the_package_of_the_namespaced_module_that_changed() {
+        let fixture = Fixture::new("namespaced-module-change");
+        fixture
+            .package("", "root", &["a", "b"])
+            .write(
+                "node_modules/a/bsconfig.json",
+                r#"{ "name": "a", "sources": "src", "namespace": "NsA" }"#,
+            )
+            .write("node_modules/a/src/Foo.res", "let a = 1")
+            .write("node_modules/a/src/Foo.resi", "let a: int")
+            .write(
+                "node_modules/b/bsconfig.json",
+                r#"{ "name": "b", "sources": "src", "namespace": "NsB" }"#,
+            )
+            .write("node_modules/b/src/Foo.res", "let a = 1")
+            .write("node_modules/b/src/Foo.resi", "let a: int");
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            super::dependents_to_rebuild(&packages, "a", "Foo", false),
+            AHashSet::from_iter(["a".to_string()])
+        );
+        assert_eq!(
+            super::dependents_to_rebuild(&packages, "b", "Foo-NsB", false),
+            AHashSet::from_iter(["b".to_string()])
+        );
+    }
+
+    #[test]
+    fn should_stop_scanning_a_source_folder_with_too_many_files() {
+        let fixture = Fixture::new("source-too-large");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [{ "dir": "huge", "subdirs": true }, "src"] }"#,
+            )
+            .write("src/Main.res", "let a = 1");
+        (0..10).for_each(|i| {
+            fixture.write(&format!("huge/nested/File{}.res", i), "let a = 1");
+        });
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                max_source_entries: Some(5),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let too_large = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::SourceTooLarge)
+            .map(|d| d.path.to_owned())
+            .collect::<Vec<String>>();
+        assert_eq!(too_large, vec![fixture.path("huge")]);
+        assert_eq!(
+            Vec::from_iter(tree.packages["root"].source_files.as_ref().unwrap().keys()),
+            vec!["src/Main.res"]
+        );
+    }
+
+    #[test]
+    fn should_generate_the_namespace_file_contents() {
+        let fixture = Fixture::new("namespace-file");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "my-app", "sources": "src", "namespace": true, "bs-dependencies": ["plain"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write("src/utils.res", "let a = 1")
+            .write("src/utils.resi", "let a: int")
+            .package("node_modules/plain", "plain", &[])
+            .write("node_modules/plain/src/Plain.res", "let a = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert_eq!(
+            packages["my-app"].namespace_file_contents(),
+            Some("module Main = Main-MyApp\nmodule Utils = Utils-MyApp\n".to_string())
+        );
+        assert_eq!(packages["plain"].namespace_file_contents(), None);
+    }
+
+    #[test]
+    fn should_compute_the_production_and_full_closures() {
+        let fixture = Fixture::new("closures");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["lib"], "bs-dev-dependencies": ["test-lib"] }"#,
+            )
+            .package("node_modules/lib", "lib", &[])
+            .package("node_modules/test-lib", "test-lib", &["test-helper"])
+            .package("node_modules/test-helper", "test-helper", &[]);
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                include_dev: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let root = tree.packages["root"].path.to_owned();
+
+        assert_eq!(
+            tree.closures.production,
+            AHashSet::from_iter([root.to_owned(), fixture.path("node_modules/lib")])
+        );
+        assert_eq!(
+            tree.closures.full,
+            AHashSet::from_iter([
+                root.to_owned(),
+                fixture.path("node_modules/lib"),
+                fixture.path("node_modules/test-lib"),
+                fixture.path("node_modules/test-helper"),
+            ])
+        );
+    }
+
+    #[test]
+    fn should_not_report_a_package_with_explicitly_empty_sources() {
+        let fixture = Fixture::new("empty-packages");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["meta", "missing-folder"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write(
+                "node_modules/meta/bsconfig.json",
+                r#"{ "name": "meta", "sources": [] }"#,
+            )
+            .write(
+                "node_modules/missing-folder/bsconfig.json",
+                r#"{ "name": "missing-folder", "sources": "source" }"#,
+            );
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        assert!(packages["meta"].bsconfig.has_empty_sources());
+        assert_eq!(super::empty_packages(&packages), vec!["missing-folder"]);
+    }
+
+    #[test]
+    fn should_order_the_packages_leaf_first() {
+        let fixture = Fixture::new("reverse-topological");
+        fixture
+            .package("", "root", &["a", "b"])
+            .package("node_modules/a", "a", &["c"])
+            .package("node_modules/b", "b", &["c"])
+            .package("node_modules/c", "c", &[]);
+        let mut packages = super::make(&None, &fixture.path(""), &None);
+
+        let order = super::reverse_topological_order(&packages).unwrap();
+
+        assert_eq!(order.first().unwrap(), "root");
+        assert_eq!(order.last().unwrap(), "c");
+        assert_eq!(order.len(), 4);
+
+        packages.get_mut("c").unwrap().bsconfig.bs_dependencies = Some(vec!["a".to_string()]);
+        assert_eq!(
+            super::reverse_topological_order(&packages),
+            Err(super::CircularDependency {
+                cycle: vec!["a".to_string(), "c".to_string()]
+            })
+        );
+    }
+
+    #[test]
+    fn should_report_a_deeply_nested_recursive_source() {
+        let fixture = Fixture::new("deep-source-tree");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [{ "dir": "src", "subdirs": true }] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .mkdir("src/a/b/c/d/e");
+        let options = |max_source_depth| super::MakeOptions {
+            max_source_depth,
+            ..Default::default()
+        };
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &options(Some(3))).unwrap();
+        let deep = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::DeepSourceTree)
+            .collect::<Vec<_>>();
+        assert_eq!(deep.len(), 1);
+        assert_eq!(deep[0].path, fixture.path("src"));
+        assert!(deep[0].message.contains("src/a/b/c/d/e"));
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &options(None)).unwrap();
+        assert!(!tree
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::DeepSourceTree));
+    }
+
+    #[test]
+    fn should_predict_the_output_files_of_an_in_source_package() {
+        let fixture = Fixture::new("expected-output-files");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "my-app", "sources": { "dir": "src", "subdirs": true }, "namespace": true, "package-specs": { "module": "es6", "in-source": true }, "suffix": ".bs.js" }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write("src/nested/utils.res", "let a = 1")
+            .write("src/nested/utils.resi", "let a: int")
+            .write("src/OnlyInterface.resi", "let a: int");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let package = &packages["my-app"];
+        let root = std::path::Path::new(&package.path);
+
+        assert_eq!(
+            package.expected_output_files(package),
+            vec![
+                root.join("MyApp.bs.js").to_string_lossy().to_string(),
+                root.join("src/Main.bs.js").to_string_lossy().to_string(),
+                root.join("src/nested/utils.bs.js").to_string_lossy().to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_read_a_project_without_dependencies_like_any_other() {
+        let fixture = Fixture::new("single-package");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": [], "namespace": true }"#,
+            )
+            .write("src/Main.res", "let a = 1");
+        let root_folder = fixture.path("");
+        let options = super::MakeOptions::default();
+        let diagnostics = super::Diagnostics::new();
+
+        let root_bsconfig = super::read_bsconfig(&root_folder);
+        assert!(!super::has_dependencies(&root_bsconfig, true));
+        let fast = super::read_packages(&root_folder, None, &options, &diagnostics).unwrap();
+        let mut general = AHashMap::from_iter([(
+            "root".to_string(),
+            super::make_package(root_bsconfig.to_owned(), &root_folder, false, true),
+        )]);
+        super::read_dependency_packages(
+            &mut general,
+            &root_bsconfig,
+            &root_folder,
+            &None,
+            &options,
+            &diagnostics,
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&fast).unwrap(),
+            serde_json::to_value(&general).unwrap()
+        );
+        assert!(diagnostics.into_sorted().is_empty());
+    }
+
+    #[test]
+    fn should_report_a_deleted_source_file_when_reconciling_the_cache() {
+        let fixture = Fixture::new("reconcile-cache");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["dep"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .write("src/Removed.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let cache_path = fixture.path("tree.json");
+        super::save_tree(&packages, &cache_path).unwrap();
+
+        std::fs::remove_file(fixture.path("src/Removed.res")).unwrap();
+        let cached = super::load_tree(&cache_path).unwrap();
+        let (reconciled, diff, _diagnostics) =
+            super::reconcile_cache(cached, &fixture.path(""), &None, &super::MakeOptions::default());
+
+        assert_eq!(
+            diff,
+            super::TreeDiff {
+                removed_files: vec![("root".to_string(), "src/Removed.res".to_string())],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Vec::from_iter(
+                reconciled["root"]
+                    .modules
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .map(|m| m.as_str())
+            ),
+            vec!["Main"]
+        );
+
+        std::fs::remove_dir_all(fixture.path("node_modules/dep")).unwrap();
+        let (reconciled, diff, _diagnostics) = super::reconcile_cache(
+            reconciled,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions::default(),
+        );
+        assert_eq!(diff.removed_packages, vec!["dep"]);
+        assert!(!reconciled.contains_key("dep"));
+        assert!(super::reconcile_cache(
+            reconciled,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions::default()
+        )
+        .1
+        .is_empty());
+    }
+
+    #[test]
+    fn should_report_a_file_of_another_size_with_the_same_modification_time_as_modified() {
+        let fixture = Fixture::new("reconcile-cache-size");
+        fixture
+            .package("", "root", &[])
+            .write("src/Main.res", "let a = 1");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let cache_path = fixture.path("tree.json");
+        super::save_tree(&packages, &cache_path).unwrap();
+        let modified = packages["root"].source_files.as_ref().unwrap()["src/Main.res"].modified;
+
+        fixture.write("src/Main.res", "let a = 1\nlet b = 2");
+        std::fs::File::options()
+            .write(true)
+            .open(fixture.path("src/Main.res"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (_reconciled, diff, _diagnostics) = super::reconcile_cache(
+            super::load_tree(&cache_path).unwrap(),
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions::default(),
+        );
+
+        assert_eq!(
+            diff.modified_files,
+            vec![("root".to_string(), "src/Main.res".to_string())]
+        );
+    }
+
+    #[test]
+    fn should_only_scan_the_packages_that_changed_when_reconciling_the_cache() {
+        use super::source_reader::SourceReader;
+
+        #[derive(Default)]
+        struct RecordingReader(std::sync::Mutex<AHashSet<std::path::PathBuf>>);
+        impl SourceReader for RecordingReader {
+            fn read_dir(
+                &self,
+                package_dir: &std::path::Path,
+                path: &std::path::Path,
+            ) -> std::io::Result<Vec<super::source_reader::SourceEntry>> {
+                self.0.lock().unwrap().insert(package_dir.to_path_buf());
+                super::DiskReader.read_dir(package_dir, path)
+            }
+        }
+
+        let fixture = Fixture::new("reconcile-cache-rescan");
+        fixture
+            .package("", "root", &["dep"])
+            .write("src/Main.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let cache_path = fixture.path("tree.json");
+        super::save_tree(&packages, &cache_path).unwrap();
+        let reader = std::sync::Arc::new(RecordingReader::default());
+        let options = super::MakeOptions {
+            source_reader: Some(super::SharedReader(reader.clone())),
+            ..Default::default()
+        };
+
+        fixture.write("src/Added.res", "let a = 1");
+        let (reconciled, diff, _diagnostics) = super::reconcile_cache(
+            super::load_tree(&cache_path).unwrap(),
+            &fixture.path(""),
+            &None,
+            &options,
+        );
+
+        assert_eq!(
+            diff.added_files,
+            vec![("root".to_string(), "src/Added.res".to_string())]
+        );
+        assert_eq!(
+            Vec::from_iter(reader.0.lock().unwrap().drain()),
+            vec![std::path::PathBuf::from(fixture.path(""))]
+        );
+        let (_reconciled, diff, _diagnostics) =
+            super::reconcile_cache(reconciled, &fixture.path(""), &None, &options);
+        assert!(diff.is_empty());
+        assert!(reader.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_reconcile_the_cache_like_make_would() {
+        use super::source_reader::SourceReader;
+
+        // the deadline has passed by the time the dependency is read
+        struct SlowReader;
+        impl SourceReader for SlowReader {
+            fn read_dir(
+                &self,
+                package_dir: &std::path::Path,
+                path: &std::path::Path,
+            ) -> std::io::Result<Vec<super::source_reader::SourceEntry>> {
+                let deadline = match package_dir.ends_with("dep") {
+                    true => Some(std::time::Instant::now()),
+                    false => None,
+                };
+                super::DeadlineReader::new(Box::new(super::DiskReader), deadline).read_dir(package_dir, path)
+            }
+        }
+
+        let fixture = Fixture::new("reconcile-cache-make");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "namespace": "App", "bs-dependencies": ["dep"] }"#,
+            )
+            .write("src/Main.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let filter = Some(regex::Regex::new("Main").unwrap());
+        let options = super::MakeOptions {
+            force_namespace_prefix: Some("Org".to_string()),
+            ..Default::default()
+        };
+        let packages = super::make_with(&filter, &fixture.path(""), &None, &options)
+            .unwrap()
+            .packages;
+        let cache_path = fixture.path("tree.json");
+        super::save_tree(&packages, &cache_path).unwrap();
+
+        // the root is read and scanned again, and a folder of the dependency changed
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": ["src", "lib"], "namespace": "my-app", "bs-dependencies": ["dep"] }"#,
+            )
+            .write("lib/MainLib.res", "let a = 1")
+            .write("lib/Skipped.res", "let a = 1")
+            .write("node_modules/dep/src/Added.res", "let a = 1");
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        ["bsconfig.json", "node_modules/dep/src"].iter().for_each(|path| {
+            std::fs::File::open(fixture.path(path))
+                .unwrap()
+                .set_modified(later)
+                .unwrap()
+        });
+        let (reconciled, diff, diagnostics) = super::reconcile_cache(
+            super::load_tree(&cache_path).unwrap(),
+            &fixture.path(""),
+            &filter,
+            &super::MakeOptions {
+                source_reader: Some(super::SharedReader(std::sync::Arc::new(SlowReader))),
+                ..options
+            },
+        );
+
+        assert_eq!(
+            diff.added_files,
+            vec![("root".to_string(), "lib/MainLib.res".to_string())]
+        );
+        assert_eq!(diff.removed_packages, vec!["dep"]);
+        assert!(!reconciled.contains_key("dep"));
+        assert_eq!(
+            reconciled["root"].namespace.to_suffix(),
+            Some("Org_MyApp".to_string())
+        );
+        assert!(diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::InvalidNamespace));
+    }
+
+    #[test]
+    fn should_compare_the_configs_like_they_are_in_the_tree_when_reconciling_the_cache() {
+        let fixture = Fixture::new("reconcile-cache-config");
+        fixture
+            .package("", "root", &["dep", "missing", "./shared"])
+            .package("node_modules/dep", "dep", &[])
+            .package("node_modules/other", "other", &[])
+            .package("shared", "shared", &[]);
+        let packages = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default())
+            .unwrap()
+            .packages;
+        let cache_path = fixture.path("tree.json");
+        super::save_tree(&packages, &cache_path).unwrap();
+        let rewrite_config = |dependencies: &str| {
+            fixture.write(
+                "bsconfig.json",
+                &format!(
+                    r#"{{
+                        "name": "root",
+                        "sources": "src",
+                        "bs-dependencies": [{}]
+                    }}"#,
+                    dependencies
+                ),
+            );
+            let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+            std::fs::File::options()
+                .write(true)
+                .open(fixture.path("bsconfig.json"))
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        };
+        let reconcile = || {
+            super::reconcile_cache(
+                super::load_tree(&cache_path).unwrap(),
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions::default(),
+            )
+        };
+
+        rewrite_config(r#""dep", "missing", "./shared""#);
+        let (reconciled, diff, _diagnostics) = reconcile();
+        rewrite_config(r#""dep", "missing", "./shared", "other""#);
+        let (_reconciled, restructured, _diagnostics) = reconcile();
+
+        assert!(diff.is_empty());
+        assert_eq!(
+            reconciled["root"].bsconfig.bs_dependencies,
+            Some(vec![
+                "dep".to_string(),
+                "missing".to_string(),
+                "shared".to_string()
+            ])
+        );
+        assert_eq!(
+            restructured.changed_configs,
+            vec![("root".to_string(), crate::bsconfig::ConfigChangeKind::Restructure)]
+        );
+    }
+
+    #[test]
+    fn should_report_a_trivial_config() {
+        let fixture = Fixture::new("trivial-config");
+        fixture.write("bsconfig.json", "{}");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        let trivial = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::TrivialConfig)
+            .map(|d| d.path.to_owned())
+            .collect::<Vec<String>>();
+        assert_eq!(trivial, vec![tree.packages[""].path.to_owned()]);
+        assert!(matches!(
+            tree.packages[""].namespace,
+            super::Namespace::NoNamespace
+        ));
+    }
+
+    #[test]
+    fn should_key_path_dependencies_without_a_name_by_their_path() {
+        let fixture = Fixture::new("trivial-path-dependencies");
+        fixture
+            .package("", "root", &["./a", "./b"])
+            .write("a/bsconfig.json", "{}")
+            .write("b/bsconfig.json", "{}");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        assert_eq!(tree.packages["./a"].path, fixture.path("a"));
+        assert_eq!(tree.packages["./b"].path, fixture.path("b"));
+        assert_eq!(
+            tree.diagnostics
+                .iter()
+                .filter(|d| d.kind == DiagnosticKind::TrivialConfig)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn should_expand_a_glob_in_a_source_dir() {
+        let fixture = Fixture::new("source-glob");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [{ "dir": "src/*", "subdirs": false }] }"#,
+            )
+            .write("src/a/A.res", "let a = 1")
+            .write("src/b/B.res", "let b = 1")
+            .write("src/b/nested/Nested.res", "let n = 1")
+            .write("src/Top.res", "let t = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+
+        let mut dirs = packages["root"]
+            .source_folders
+            .iter()
+            .map(|source| (source.dir.as_str(), source.subdirs.to_owned()))
+            .collect::<Vec<_>>();
+        dirs.sort_by(|a, b| a.0.cmp(b.0));
+        let subdirs = Some(crate::bsconfig::Subdirs::Recurse(false));
+        assert_eq!(
+            dirs,
+            vec![("src/a", subdirs.to_owned()), ("src/b", subdirs.to_owned())]
+        );
+        let mut files = Vec::from_iter(packages["root"].source_files.as_ref().unwrap().keys());
+        files.sort();
+        assert_eq!(files, vec!["src/a/A.res", "src/b/B.res"]);
+    }
+
+    #[test]
+    fn should_report_all_the_problems_of_an_unhealthy_tree() {
+        let fixture = Fixture::new("health-check");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "bs-dependencies": ["a", "b", "empty"] }"#,
+            )
+            .write("src/FooBar.res", "let a = 1")
+            .package("node_modules/a", "a", &[])
+            .write("node_modules/a/src/Shared.res", "let a = 1")
+            // a dependency that isn't there
+            .package("node_modules/b", "b", &["missing"])
+            .write("node_modules/b/src/Shared.res", "let a = 1")
+            .write("node_modules/b/src/Foobar.res", "let a = 1")
+            .package("node_modules/empty", "empty", &[])
+            .package("node_modules/unused", "unused", &[])
+            .write("node_modules/unused/src/Unused.res", "let a = 1");
+        let mut packages = super::make_with(&None, &fixture.path(""), &None, &Default::default())
+            .unwrap()
+            .packages;
+        let unused = super::make(&None, &fixture.path("node_modules/unused"), &None);
+        packages.insert("unused".to_string(), unused["unused"].to_owned());
+        packages.get_mut("unused").unwrap().is_root = false;
+        // a cycle
+        packages.get_mut("a").unwrap().bsconfig.bs_dependencies = Some(vec!["b".to_string()]);
+        packages
+            .get_mut("b")
+            .unwrap()
+            .bsconfig
+            .bs_dependencies
+            .as_mut()
+            .unwrap()
+            .push("a".to_string());
+        ["a", "b"].iter().for_each(|name| {
+            packages.get_mut(*name).unwrap().namespace = super::Namespace::Namespace("Lib".to_string())
+        });
+
+        let diagnostics = super::health_check(&packages, "root", &super::HealthChecks::default());
+
+        let mut kinds = diagnostics
+            .iter()
+            .map(|d| d.kind)
+            .collect::<Vec<DiagnosticKind>>();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::UnresolvedDependency,
+                DiagnosticKind::CircularDependency,
+                DiagnosticKind::DuplicateModule,
+                DiagnosticKind::CaseCollision,
+                DiagnosticKind::EmptyPackage,
+                DiagnosticKind::NamespaceCollision,
+                DiagnosticKind::UnreferencedPackage,
+            ]
+        );
+        assert_eq!(
+            crate::build::diagnostics::severity_counts(&diagnostics),
+            vec![(Severity::Info, 1), (Severity::Warning, 2), (Severity::Error, 4)]
+        );
+        let without_cycles = super::health_check(
+            &packages,
+            "root",
+            &super::HealthChecks {
+                cycles: false,
+                ..Default::default()
+            },
+        );
+        assert!(!without_cycles
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::CircularDependency));
+    }
+
+    #[test]
+    fn should_leave_out_the_packages_that_were_not_scanned_before_the_deadline() {
+        use super::source_reader::SourceReader;
+
+        // the deadline has passed by the time the slow package is read
+        struct SlowReader;
+        impl SourceReader for SlowReader {
+            fn read_dir(
+                &self,
+                package_dir: &std::path::Path,
+                path: &std::path::Path,
+            ) -> std::io::Result<Vec<super::source_reader::SourceEntry>> {
+                let deadline = match package_dir.ends_with("slow") {
+                    true => Some(std::time::Instant::now()),
+                    false => None,
+                };
+                super::DeadlineReader::new(Box::new(super::DiskReader), deadline).read_dir(package_dir, path)
+            }
+        }
+
+        let fixture = Fixture::new("scan-deadline");
+        fixture
+            .package("", "root", &["fast", "slow"])
+            .write("src/Main.res", "let a = 1")
+            .package("node_modules/fast", "fast", &[])
+            .write("node_modules/fast/src/Fast.res", "let a = 1")
+            .package("node_modules/slow", "slow", &[])
+            .write("node_modules/slow/src/Slow.res", "let a = 1");
+
+        let tree = super::make_with(
+            &None,
+            &fixture.path(""),
+            &None,
+            &super::MakeOptions {
+                source_reader: Some(super::SharedReader(std::sync::Arc::new(SlowReader))),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut names = Vec::from_iter(tree.packages.keys().map(|name| name.as_str()));
+        names.sort();
+        assert_eq!(names, vec!["fast", "root"]);
+        assert!(tree.packages["fast"].modules.as_ref().unwrap().contains("Fast"));
+        let timeouts = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::Timeout)
+            .collect::<Vec<_>>();
+        assert_eq!(timeouts.len(), 1);
+        assert!(timeouts[0].message.ends_with(": slow"));
+    }
+
+    #[test]
+    fn should_count_the_source_files_by_extension() {
+        let fixture = Fixture::new("tree-stats");
+        fixture
+            .package("", "root", &["reason"])
+            .write("src/A.res", "let a = 1")
+            .write("src/A.resi", "let a: int")
+            .write("src/B.res", "let b = 1")
+            .write("src/C.ml", "let c = 1")
+            .write("src/C.mli", "let c: int")
+            .package("node_modules/reason", "reason", &[])
+            .write("node_modules/reason/src/R.re", "let r = 1;")
+            .write("node_modules/reason/src/R.rei", "let r: int;")
+            .write("node_modules/reason/src/S.re", "let s = 1;")
+            .write("node_modules/reason/src/T.res", "let t = 1");
+
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let stats = super::tree_stats(&packages);
+
+        let mut by_extension = Vec::from_iter(stats.source_files_by_extension.iter());
+        by_extension.sort();
+        assert_eq!(
+            by_extension,
+            vec![
+                (&"ml".to_string(), &1),
+                (&"mli".to_string(), &1),
+                (&"re".to_string(), &2),
+                (&"rei".to_string(), &1),
+                (&"res".to_string(), &3),
+                (&"resi".to_string(), &1),
+            ]
+        );
+        assert_eq!(
+            (
+                stats.packages,
+                stats.local_packages,
+                stats.modules,
+                stats.source_files
+            ),
+            (2, 1, 6, 9)
+        );
+        assert_eq!(packages["reason"].source_file_counts()["re"], 2);
+        assert!(!packages["reason"].source_file_counts().contains_key("ml"));
+    }
+
+    #[test]
+    fn should_resolve_the_dependencies_of_a_symlinked_workspace_from_where_it_is() {
+        let fixture = Fixture::new("symlinked-workspace");
+        fixture
+            .package("app", "app", &["@myorg/ui"])
+            .mkdir("app/src")
+            // the app has a theme of its own, which is not the one the ui package uses
+            .package("app/node_modules/theme", "theme", &[])
+            .write("other/bsconfig.json", r#"{ "name": "other", "sources": [] }"#)
+            .package("other/packages/ui", "@myorg/ui", &["theme"])
+            .write("other/packages/ui/src/Button.res", "let a = 1")
+            .package("other/node_modules/theme", "theme", &[])
+            .write("other/node_modules/theme/src/Theme.res", "let a = 1")
+            .symlink("other/packages/ui", "app/node_modules/@myorg/ui");
+
+        let packages = super::make(&None, &fixture.path("app"), &None);
 
-    pub fn get_jsx_mode_args(&self) -> Vec<String> {
-        self.bsconfig.get_jsx_mode_args()
+        assert_eq!(
+            packages["@myorg/ui"].path,
+            std::path::Path::new(&fixture.path("other/packages/ui"))
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+        );
+        assert_eq!(
+            packages["theme"].path,
+            std::path::Path::new(&fixture.path("other/node_modules/theme"))
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+        );
     }
 
-    pub fn get_jsx_module_args(&self) -> Vec<String> {
-        self.bsconfig.get_jsx_module_args()
-    }
+    #[test]
+    fn should_list_the_namespaced_packages() {
+        let fixture = Fixture::new("namespaced-packages");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "namespace": true, "bs-dependencies": ["local", "third-party", "plain"] }"#,
+            )
+            .mkdir("src")
+            .write(
+                "packages/local/bsconfig.json",
+                r#"{ "name": "local", "sources": "src", "namespace": "Local" }"#,
+            )
+            .mkdir("packages/local/src")
+            .symlink("packages/local", "node_modules/local")
+            .write(
+                "node_modules/third-party/bsconfig.json",
+                r#"{ "name": "third-party", "sources": "src", "namespace": true }"#,
+            )
+            .mkdir("node_modules/third-party/src")
+            .package("node_modules/plain", "plain", &[]);
 
-    pub fn get_uncurried_args(&self, version: &str, root_package: &packages::Package) -> Vec<String> {
-        root_package.bsconfig.get_uncurried_args(version)
-    }
-}
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let names = |only_local| {
+            super::namespaced_packages(&packages, only_local)
+                .iter()
+                .map(|package| package.name.to_owned())
+                .collect::<Vec<String>>()
+        };
 
-fn get_unallowed_dependents(
-    packages: &AHashMap<String, Package>,
-    package_name: &String,
-    dependencies: &Vec<String>,
-) -> Option<String> {
-    for deps_package_name in dependencies {
-        if let Some(deps_package) = packages.get(deps_package_name) {
-            let deps_allowed_dependents = deps_package.bsconfig.allowed_dependents.to_owned();
-            if let Some(allowed_dependents) = deps_allowed_dependents {
-                if !allowed_dependents.contains(package_name) {
-                    return Some(deps_package_name.to_string());
-                }
-            }
-        }
+        assert_eq!(names(true), vec!["local", "root"]);
+        assert_eq!(names(false), vec!["local", "root", "third-party"]);
     }
-    None
-}
-#[derive(Debug, Clone)]
-struct UnallowedDependency {
-    bs_deps: Vec<String>,
-    pinned_deps: Vec<String>,
-    bs_dev_deps: Vec<String>,
-}
 
-pub fn validate_packages_dependencies(packages: &AHashMap<String, Package>) -> bool {
-    let mut detected_unallowed_dependencies: AHashMap<String, UnallowedDependency> = AHashMap::new();
-
-    for (package_name, package) in packages {
-        let bs_dependencies = &package.bsconfig.bs_dependencies.to_owned().unwrap_or(vec![]);
-        let pinned_dependencies = &package.bsconfig.pinned_dependencies.to_owned().unwrap_or(vec![]);
-        let dev_dependencies = &package.bsconfig.bs_dev_dependencies.to_owned().unwrap_or(vec![]);
+    #[test]
+    fn should_compress_overlapping_watch_paths() {
+        let fixture = Fixture::new("compressed-watch-paths");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": [
+                    { "dir": "src", "subdirs": ["a", "b"] },
+                    { "dir": "lib", "subdirs": true },
+                    "lib/nested",
+                    { "dir": "partial", "subdirs": ["included"] }
+                ] }"#,
+            )
+            .mkdir("src/a")
+            .mkdir("src/b")
+            .mkdir("lib/nested/deep")
+            .mkdir("partial/included")
+            .mkdir("partial/excluded");
 
-        vec![
-            ("bs-dependencies", bs_dependencies),
-            ("pinned-dependencies", pinned_dependencies),
-            ("bs-dev-dependencies", dev_dependencies),
-        ]
-        .iter()
-        .for_each(|(dependency_type, dependencies)| {
-            if let Some(unallowed_dependency_name) =
-                get_unallowed_dependents(packages, package_name, dependencies)
-            {
-                let empty_unallowed_deps = UnallowedDependency {
-                    bs_deps: vec![],
-                    pinned_deps: vec![],
-                    bs_dev_deps: vec![],
-                };
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let watch_path = |path: &str, recursive| super::WatchPath {
+            path: fixture.path(path),
+            recursive,
+        };
 
-                let unallowed_dependency = detected_unallowed_dependencies.entry(String::from(package_name));
-                let value = unallowed_dependency.or_insert_with(|| empty_unallowed_deps);
-                match *dependency_type {
-                    "bs-dependencies" => value.bs_deps.push(unallowed_dependency_name),
-                    "pinned-dependencies" => value.pinned_deps.push(unallowed_dependency_name),
-                    "bs-dev-dependencies" => value.bs_dev_deps.push(unallowed_dependency_name),
-                    _ => (),
-                }
-            }
-        });
-    }
-    for (package_name, unallowed_deps) in detected_unallowed_dependencies.iter() {
-        println!(
-            "\n{}: {} has the following unallowed dependencies:",
-            console::style("Error").red(),
-            console::style(package_name).bold()
+        assert_eq!(
+            super::compressed_watch_paths(&packages),
+            vec![
+                watch_path("bsconfig.json", false),
+                watch_path("lib", true),
+                watch_path("partial", false),
+                watch_path("partial/included", true),
+                watch_path("src", true),
+            ]
         );
-
-        vec![
-            ("bs-dependencies", unallowed_deps.bs_deps.to_owned()),
-            ("pinned-dependencies", unallowed_deps.pinned_deps.to_owned()),
-            ("bs-dev-dependencies", unallowed_deps.bs_dev_deps.to_owned()),
-        ]
-        .iter()
-        .for_each(|(deps_type, map)| {
-            if !map.is_empty() {
-                println!(
-                    "{} dependencies: {}",
-                    console::style(deps_type).bold().dim(),
-                    console::style(map.join(" \n -")).bold().dim()
-                );
-            }
-        });
     }
-    let has_any_unallowed_dependent = detected_unallowed_dependencies.len() > 0;
 
-    if has_any_unallowed_dependent {
-        println!(
-            "\nUpdate the {} value in the {} of the unallowed dependencies to solve the issue!",
-            console::style("unallowed_dependents").bold().dim(),
-            console::style("bsconfig.json").bold().dim()
-        )
+    #[test]
+    fn should_report_an_interface_with_its_implementation_in_another_namespace() {
+        let fixture = Fixture::new("namespace-mismatch");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": "src", "namespace": "App", "bs-dependencies": ["lib"] }"#,
+            )
+            .write("src/Foo.res", "let a = 1")
+            .write(
+                "src/lib/bsconfig.json",
+                r#"{ "name": "lib", "sources": ".", "namespace": "Lib" }"#,
+            )
+            .write("src/lib/Foo.resi", "let a: int")
+            .symlink("src/lib", "node_modules/lib");
+
+        let tree = super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default()).unwrap();
+
+        let mismatches = tree
+            .diagnostics
+            .iter()
+            .filter(|d| d.kind == DiagnosticKind::NamespaceMismatch)
+            .collect::<Vec<_>>();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].path,
+            std::path::Path::new(&tree.packages["lib"].path)
+                .join("Foo.resi")
+                .to_string_lossy()
+        );
+        assert!(mismatches[0].message.contains("(Lib)"));
+        assert!(mismatches[0]
+            .message
+            .contains("src/Foo.res of package root (App)"));
     }
-    !has_any_unallowed_dependent
-}
 
-#[cfg(test)]
-mod test {
-    use crate::bsconfig::Source;
-    use ahash::{AHashMap, AHashSet};
+    #[test]
+    fn should_fail_on_a_missing_dependency_with_strict_dependencies() {
+        let fixture = Fixture::new("strict-dependencies");
+        fixture
+            .package("", "root", &["a"])
+            .package("node_modules/a", "a", &["missing"]);
+        let make = |strict_dependencies| {
+            super::make_with(
+                &None,
+                &fixture.path(""),
+                &None,
+                &super::MakeOptions {
+                    strict_dependencies,
+                    ..Default::default()
+                },
+            )
+        };
 
-    use super::{Namespace, Package};
+        match make(true) {
+            Err(super::MakeError::MissingDependency { chain, message }) => {
+                assert_eq!(chain, vec!["root", "a", "missing"]);
+                assert!(message.contains("\"missing\" is not found"));
+            }
+            other => panic!("Expected a missing dependency, got {:?}", other),
+        }
 
-    fn create_package(
-        name: String,
-        bs_deps: Vec<String>,
-        pinned_deps: Vec<String>,
-        dev_deps: Vec<String>,
-        allowed_dependents: Option<Vec<String>>,
-    ) -> Package {
-        return Package {
-            name: name.clone(),
-            bsconfig: crate::bsconfig::Config {
-                name: name.clone(),
-                sources: crate::bsconfig::OneOrMore::Single(Source::Shorthand(String::from("Source"))),
-                package_specs: None,
-                warnings: None,
-                suffix: None,
-                pinned_dependencies: Some(pinned_deps),
-                bs_dependencies: Some(bs_deps),
-                bs_dev_dependencies: Some(dev_deps),
-                ppx_flags: None,
-                bsc_flags: None,
-                reason: None,
-                namespace: None,
-                jsx: None,
-                uncurried: None,
-                namespace_entry: None,
-                allowed_dependents,
-            },
-            source_folders: AHashSet::new(),
-            source_files: None,
-            namespace: Namespace::Namespace(String::from("Package1")),
-            modules: None,
-            path: String::from("./something"),
-            dirs: None,
-            is_pinned_dep: false,
-            is_root: false,
+        let tree = make(false).unwrap();
+        assert!(tree.packages.contains_key("a"));
+        assert!(!tree.packages.contains_key("missing"));
+        assert!(tree
+            .diagnostics
+            .iter()
+            .any(|d| d.kind == DiagnosticKind::UnresolvedDependency && d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn should_propagate_staleness_through_the_module_graph() {
+        let fixture = Fixture::new("stale-modules-transitive");
+        fixture
+            .package("", "root", &[])
+            .write("src/Main.res", "let a = Feature.a")
+            .write("src/Feature.res", "let a = Utils.a + Other.a")
+            .write("src/Utils.res", "let a = Log.a")
+            // the ones in a cycle are stale together
+            .write("src/Log.res", "let a = 1\nlet b = Cyclic.b")
+            .write("src/Cyclic.res", "let b = 1\nlet c = Log.a")
+            .write("src/Other.res", "let a = 1")
+            .write("src/Unrelated.res", "let a = Other.a");
+        let packages = super::make(&None, &fixture.path(""), &None);
+        let stale = |changed: &[&str]| {
+            let mut stale = Vec::from_iter(super::stale_modules_transitive(
+                &packages,
+                &AHashSet::from_iter(changed.iter().map(|module| module.to_string())),
+            ));
+            stale.sort();
+            stale
         };
+
+        assert_eq!(stale(&["Utils"]), vec!["Feature", "Main", "Utils"]);
+        assert_eq!(
+            stale(&["Cyclic"]),
+            vec!["Cyclic", "Feature", "Log", "Main", "Utils"]
+        );
+        assert_eq!(stale(&["Main"]), vec!["Main"]);
     }
+
     #[test]
-    fn should_return_false_with_invalid_parents_as_bs_dependencies() {
-        let mut packages: AHashMap<String, Package> = AHashMap::new();
-        packages.insert(
-            String::from("Package1"),
-            create_package(
-                String::from("Package1"),
-                vec![String::from("Package2")],
-                vec![],
-                vec![],
-                None,
-            ),
+    fn should_warn_about_a_config_field_that_is_too_new_for_the_compiler() {
+        let fixture = Fixture::new("compiler-compatibility");
+        fixture.write(
+            "bsconfig.json",
+            r#"{ "name": "root", "sources": "src", "uncurried": true }"#,
         );
-        packages.insert(
-            String::from("Package2"),
-            create_package(
-                String::from("Package2"),
-                vec![],
-                vec![],
-                vec![],
-                Some(vec![String::from("Package3")]),
-            ),
+        fixture.mkdir("src");
+        fixture.write(
+            "node_modules/rescript/package.json",
+            r#"{ "name": "rescript", "version": "10.1.4" }"#,
         );
+        let options = super::MakeOptions {
+            check_compiler_compatibility: true,
+            ..Default::default()
+        };
+        let tree = super::make_with(&None, &fixture.path(""), &None, &options).unwrap();
 
-        let is_valid = super::validate_packages_dependencies(&packages);
-        assert_eq!(is_valid, false)
+        assert_eq!(tree.compiler_version.as_deref(), Some("10.1.4"));
+        let incompatible = tree
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::IncompatibleConfig)
+            .collect::<Vec<_>>();
+        assert_eq!(incompatible.len(), 1);
+        assert_eq!(incompatible[0].severity, Severity::Warning);
+        assert!(incompatible[0].message.contains("\"uncurried\""));
     }
 
     #[test]
-    fn should_return_false_with_invalid_parents_as_pinned_dependencies() {
-        let mut packages: AHashMap<String, Package> = AHashMap::new();
-        packages.insert(
-            String::from("Package1"),
-            create_package(
-                String::from("Package1"),
-                vec![],
-                vec![String::from("Package2")],
-                vec![],
-                None,
-            ),
-        );
-        packages.insert(
-            String::from("Package2"),
-            create_package(
-                String::from("Package2"),
-                vec![],
-                vec![],
-                vec![],
-                Some(vec![String::from("Package3")]),
-            ),
-        );
+    fn should_only_keep_the_test_files_when_the_dev_sources_are_scanned() {
+        let fixture = Fixture::new("test-file-patterns");
+        fixture.package("", "root", &[]);
+        fixture.write("src/Foo.res", "");
+        fixture.write("src/Foo_test.res", "");
+        let tree = |root_dev_sources| {
+            let options = super::MakeOptions {
+                test_file_patterns: vec!["*_test.res".to_string()],
+                dev_sources: super::DevSourcePolicy {
+                    root: root_dev_sources,
+                    deps: false,
+                },
+                ..Default::default()
+            };
+            super::make_with(&None, &fixture.path(""), &None, &options).unwrap()
+        };
+        let modules = |tree: &super::PackageTree| {
+            let mut modules = tree.packages["root"]
+                .modules
+                .to_owned()
+                .unwrap()
+                .iter()
+                .map(|module| module.as_str().to_string())
+                .collect::<Vec<String>>();
+            modules.sort();
+            modules
+        };
 
-        let is_valid = super::validate_packages_dependencies(&packages);
-        assert_eq!(is_valid, false)
+        let production = tree(false);
+        assert_eq!(modules(&production), vec!["Foo"]);
+
+        let test = tree(true);
+        assert_eq!(modules(&test), vec!["Foo", "Foo_test"]);
+        let source_files = test.packages["root"].source_files.to_owned().unwrap();
+        assert!(source_files["src/Foo_test.res"].is_test);
+        assert!(!source_files["src/Foo.res"].is_test);
     }
 
     #[test]
-    fn should_return_false_with_invalid_parents_as_dev_dependencies() {
-        let mut packages: AHashMap<String, Package> = AHashMap::new();
-        packages.insert(
-            String::from("Package1"),
-            create_package(
-                String::from("Package1"),
-                vec![],
-                vec![],
-                vec![String::from("Package2")],
-                None,
-            ),
-        );
-        packages.insert(
-            String::from("Package2"),
-            create_package(
-                String::from("Package2"),
-                vec![],
-                vec![],
-                vec![],
-                Some(vec![String::from("Package3")]),
-            ),
+    fn should_snapshot_a_package_the_same_way_every_build() {
+        let fixture = Fixture::new("package-snapshot");
+        fixture.write(
+            "bsconfig.json",
+            r#"{
+                "name": "root",
+                "namespace": true,
+                "sources": [{ "dir": "src", "subdirs": true }, { "dir": "test", "type": "dev" }],
+                "bs-dependencies": ["dep"]
+            }"#,
         );
+        fixture.write("src/Foo.res", "");
+        fixture.write("src/nested/Bar.res", "");
+        fixture.mkdir("test");
+        fixture.package("node_modules/dep", "dep", &[]);
+        let snapshot = || {
+            super::make_with(&None, &fixture.path(""), &None, &super::MakeOptions::default())
+                .unwrap()
+                .packages["root"]
+                .snapshot()
+        };
 
-        let is_valid = super::validate_packages_dependencies(&packages);
-        assert_eq!(is_valid, false)
+        let first = snapshot();
+        assert_eq!(first, snapshot());
+        assert_eq!(
+            first,
+            "name: root
+namespace: Root
+source folders:
+  src (recursive)
+  test (dev)
+dependencies:
+  dep
+dev dependencies:
+modules:
+  Bar-Root
+  Foo-Root
+  Root
+"
+        );
     }
 
     #[test]
-    fn should_return_true_with_no_invalid_parent() {
-        let mut packages: AHashMap<String, Package> = AHashMap::new();
-        packages.insert(
-            String::from("Package1"),
-            create_package(
-                String::from("Package1"),
-                vec![String::from("Package2")],
-                vec![],
-                vec![],
-                None,
+    fn should_make_a_path_relative() {
+        assert_eq!(
+            super::relative_path(
+                std::path::Path::new("/repo/shared/src"),
+                std::path::Path::new("/repo/packages/app")
             ),
+            std::path::Path::new("../../shared/src")
         );
-        packages.insert(
-            String::from("Package2"),
-            create_package(
-                String::from("Package2"),
-                vec![],
-                vec![],
-                vec![],
-                Some(vec![String::from("Package1")]),
+        assert_eq!(
+            super::relative_path(
+                std::path::Path::new("/repo/app/src"),
+                std::path::Path::new("/repo/app")
             ),
+            std::path::Path::new("src")
         );
+    }
 
-        let is_valid = super::validate_packages_dependencies(&packages);
-        assert_eq!(is_valid, true)
+    #[test]
+    fn should_stop_following_a_chain_of_symlinked_folders() {
+        let fixture = Fixture::new("symlink-depth");
+        fixture
+            .write(
+                "bsconfig.json",
+                r#"{ "name": "root", "sources": { "dir": "src", "subdirs": true } }"#,
+            )
+            .write("src/Root.res", "")
+            .write("chain/one/One.res", "")
+            .write("chain/two/Two.res", "")
+            .write("chain/three/Three.res", "")
+            .symlink("chain/one", "src/one")
+            .symlink("chain/two", "chain/one/two")
+            .symlink("chain/three", "chain/two/three");
+        let options = super::MakeOptions {
+            max_symlink_depth: Some(2),
+            ..Default::default()
+        };
+        let tree = super::make_with(&None, &fixture.path(""), &None, &options).unwrap();
+
+        let mut source_files =
+            Vec::from_iter(tree.packages["root"].source_files.to_owned().unwrap().into_keys());
+        source_files.sort();
+        assert_eq!(
+            source_files,
+            vec!["src/Root.res", "src/one/One.res", "src/one/two/Two.res"]
+        );
+        let exceeded = tree
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::SymlinkDepthExceeded)
+            .collect::<Vec<_>>();
+        assert_eq!(exceeded.len(), 1);
+        assert!(exceeded[0].path.ends_with("src/one/two/three"));
     }
 }
@@ -0,0 +1,174 @@
+use super::{PackageTree, TreeDiff};
+use crate::build::diagnostics::{self, Diagnostic};
+use std::sync::{Arc, RwLock};
+
+/// The package tree shared between threads, for instance an LSP server that answers queries
+/// while the watcher updates the tree. Queries take the read lock, updates the write lock, so a
+/// query never sees a half updated tree. Cloning shares the same tree.
+#[derive(Debug, Clone)]
+pub struct SharedTree {
+    tree: Arc<RwLock<PackageTree>>,
+}
+
+impl SharedTree {
+    pub fn new(tree: PackageTree) -> Self {
+        Self {
+            tree: Arc::new(RwLock::new(tree)),
+        }
+    }
+
+    /// Runs a query on the tree, under the read lock
+    pub fn read<T>(&self, query: impl FnOnce(&PackageTree) -> T) -> T {
+        query(&self.tree.read().expect("The package tree lock is poisoned"))
+    }
+
+    /// Runs an update of the tree, under the write lock
+    pub fn update<T>(&self, update: impl FnOnce(&mut PackageTree) -> T) -> T {
+        update(&mut self.tree.write().expect("The package tree lock is poisoned"))
+    }
+
+    /// Swaps in a freshly built tree
+    pub fn replace(&self, tree: PackageTree) {
+        self.update(|current| *current = tree)
+    }
+
+    /// The name of the package that has `module_name`
+    pub fn package_of_module(&self, module_name: &str) -> Option<String> {
+        self.read(|tree| {
+            tree.packages
+                .iter()
+                .find(|(_name, package)| {
+                    package
+                        .modules
+                        .as_ref()
+                        .is_some_and(|modules| modules.contains(module_name))
+                })
+                .map(|(name, _package)| name.to_owned())
+        })
+    }
+
+    /// See `packages::add_dependency`
+    pub fn add_dependency(&self, parent_path: &str, dependency_name: &str) -> Result<Vec<String>, String> {
+        self.update(|tree| super::add_dependency(tree, parent_path, dependency_name))
+    }
+
+    /// See `packages::remove_dependency`
+    pub fn remove_dependency(&self, parent_path: &str, dependency_name: &str) -> Vec<String> {
+        self.update(|tree| {
+            let removed = super::remove_dependency(&mut tree.packages, parent_path, dependency_name);
+            tree.edges = super::dependency_edges(&tree.packages, tree.options.include_dev);
+            tree.closures = super::closures(&tree.packages, &tree.edges);
+            removed
+        })
+    }
+
+    /// See `packages::reconcile_cache`, with the filter and the options the tree was built with.
+    /// The diagnostics that are new are added to the ones of the tree.
+    pub fn reconcile_cache(&self, root_folder: &str) -> TreeDiff {
+        self.update(|tree| {
+            let (packages, diff, new_diagnostics) = super::reconcile_cache(
+                std::mem::take(&mut tree.packages),
+                root_folder,
+                &tree.filter,
+                &tree.options,
+            );
+            tree.packages = packages;
+            new_diagnostics.into_iter().for_each(|diagnostic| {
+                if !tree.diagnostics.contains(&diagnostic) {
+                    tree.diagnostics.push(diagnostic)
+                }
+            });
+            diagnostics::sort(&mut tree.diagnostics);
+            tree.edges = super::dependency_edges(&tree.packages, tree.options.include_dev);
+            tree.closures = super::closures(&tree.packages, &tree.edges);
+            diff
+        })
+    }
+
+    /// See `Package::recompute_modules`, for the package `package_name` (if it is in the tree)
+    pub fn recompute_modules(&self, package_name: &str) -> Vec<Diagnostic> {
+        self.update(|tree| {
+            tree.packages
+                .get_mut(package_name)
+                .map(|package| package.recompute_modules())
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedTree;
+    use crate::build::packages;
+    use crate::testing::Fixture;
+    use std::thread;
+
+    #[test]
+    fn should_answer_queries_while_the_tree_is_updated() {
+        let fixture = Fixture::new("shared-tree");
+        fixture
+            .package("", "root", &["dep"])
+            .write("src/Root.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let tree = packages::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+        let root_path = tree.packages["root"].path.to_owned();
+        let tree = SharedTree::new(tree);
+
+        let readers = (0..4)
+            .map(|_| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    (0..100).for_each(|_| {
+                        assert_eq!(tree.package_of_module("Root"), Some("root".to_string()));
+                        // the dependency is either fully there, or fully removed
+                        assert!(tree.read(|tree| {
+                            let has_edge = tree.packages["root"]
+                                .bsconfig
+                                .bs_dependencies
+                                .as_ref()
+                                .is_some_and(|deps| deps.contains(&"dep".to_string()));
+                            has_edge == tree.packages.contains_key("dep")
+                                && has_edge == tree.edges.iter().any(|edge| edge.to == "dep")
+                        }));
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let removed = tree.remove_dependency(&root_path, "dep");
+        readers.into_iter().for_each(|reader| reader.join().unwrap());
+
+        assert_eq!(removed, vec![fixture.path("node_modules/dep")]);
+        assert_eq!(tree.package_of_module("Dep"), None);
+        assert_eq!(tree.read(|tree| tree.packages.len()), 1);
+    }
+
+    #[test]
+    fn should_update_the_tree_incrementally_under_the_write_lock() {
+        let fixture = Fixture::new("shared-tree-updates");
+        fixture
+            .package("", "root", &[])
+            .write("src/Root.res", "let a = 1")
+            .package("node_modules/dep", "dep", &[])
+            .write("node_modules/dep/src/Dep.res", "let a = 1");
+        let tree = packages::make_with(&None, &fixture.path(""), &None, &Default::default()).unwrap();
+        let root_path = tree.packages["root"].path.to_owned();
+        let tree = SharedTree::new(tree);
+
+        assert_eq!(
+            tree.add_dependency(&root_path, "dep"),
+            Ok(vec![fixture.path("node_modules/dep")])
+        );
+        assert_eq!(tree.package_of_module("Dep"), Some("dep".to_string()));
+        assert!(tree.read(|tree| tree.edges.iter().any(|edge| edge.to == "dep")));
+
+        fixture.write("src/Added.res", "let a = 1");
+        let diff = tree.reconcile_cache(&fixture.path(""));
+        assert_eq!(
+            diff.added_files,
+            vec![("root".to_string(), "src/Added.res".to_string())]
+        );
+        assert_eq!(tree.package_of_module("Added"), Some("root".to_string()));
+        assert!(tree.recompute_modules("root").is_empty());
+    }
+}
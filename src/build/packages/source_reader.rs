@@ -0,0 +1,194 @@
+use ahash::AHashMap;
+use flate2::read::GzDecoder;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A file or folder in a source folder
+#[derive(Debug, Clone)]
+pub struct SourceEntry {
+    pub name: OsString,
+    pub is_dir: bool,
+    // a symlink (to a folder, `is_dir` follows the link)
+    pub is_symlink: bool,
+    pub modified: SystemTime,
+    // the size in bytes
+    pub len: u64,
+}
+
+/// Lists the contents of the source folders of a package. Normally these are read from disk, but
+/// a package can also ship its sources in an archive (see `TarReader`).
+pub trait SourceReader: Sync {
+    /// The entries in `path` (relative to `package_dir`)
+    fn read_dir(&self, package_dir: &Path, path: &Path) -> io::Result<Vec<SourceEntry>>;
+}
+
+pub struct DiskReader;
+
+impl SourceReader for DiskReader {
+    fn read_dir(&self, package_dir: &Path, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        fs::read_dir(package_dir.join(path))?
+            .map(|entry| {
+                let entry = entry?;
+                let metadata = fs::metadata(entry.path())?;
+                Ok(SourceEntry {
+                    name: entry.file_name(),
+                    is_dir: metadata.is_dir(),
+                    is_symlink: entry.file_type()?.is_symlink(),
+                    modified: metadata.modified()?,
+                    len: metadata.len(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the sources from a (read-only) tar archive, which is read into memory once. The paths in
+/// the archive are relative to the package root. Note that this only covers scanning: the
+/// compiler still needs the files on disk.
+pub struct TarReader {
+    // paths relative to the root of the archive
+    entries: Vec<(PathBuf, bool, SystemTime, u64)>,
+}
+
+impl TarReader {
+    pub fn new(archive: impl Read) -> io::Result<Self> {
+        let mut entries = vec![];
+        for entry in tar::Archive::new(archive).entries()? {
+            let entry = entry?;
+            let path = entry
+                .path()?
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect::<PathBuf>();
+            let is_dir = entry.header().entry_type().is_dir();
+            let modified = UNIX_EPOCH + Duration::from_secs(entry.header().mtime().unwrap_or(0));
+            let len = entry.header().size().unwrap_or(0);
+            entries.push((path, is_dir, modified, len));
+        }
+        Ok(Self { entries })
+    }
+
+    /// An archive without any files, for when the archive can't be read
+    pub fn empty() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// Opens a `.tar` archive, or a gzipped one (`.tar.gz` or `.tgz`)
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz" | "tgz") => Self::new(GzDecoder::new(file)),
+            _ => Self::new(file),
+        }
+    }
+}
+
+impl SourceReader for TarReader {
+    fn read_dir(&self, _package_dir: &Path, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        let path = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect::<PathBuf>();
+        let mut found = false;
+        let mut children: AHashMap<OsString, SourceEntry> = AHashMap::new();
+        for (entry_path, is_dir, modified, len) in &self.entries {
+            let mut rest = match entry_path.strip_prefix(&path) {
+                Ok(rest) => rest.components(),
+                Err(_) => continue,
+            };
+            found = true;
+            if let Some(name) = rest.next() {
+                // folders don't need to have their own entry in the archive
+                let is_dir = *is_dir || rest.next().is_some();
+                let name = name.as_os_str().to_owned();
+                children.entry(name.to_owned()).or_insert(SourceEntry {
+                    name,
+                    is_dir,
+                    is_symlink: false,
+                    modified: *modified,
+                    len: *len,
+                });
+            }
+        }
+        if !found {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in the archive", path.to_string_lossy()),
+            ));
+        }
+        Ok(children.into_values().collect())
+    }
+}
+
+/// A reader that is shared (by the options of `make_with`), for tools that keep the sources
+/// somewhere else than on disk, like the unsaved buffers of an editor
+#[derive(Clone)]
+pub struct SharedReader(pub Arc<dyn SourceReader + Send>);
+
+impl fmt::Debug for SharedReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedReader")
+    }
+}
+
+impl SourceReader for SharedReader {
+    fn read_dir(&self, package_dir: &Path, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        self.0.read_dir(package_dir, path)
+    }
+}
+
+/// Stops reading once the `deadline` has passed: every read after it fails with
+/// `io::ErrorKind::TimedOut`, so a scan that takes too long is cancelled between folders. It
+/// remembers whether a read timed out (also one of the wrapped reader), so a scan that was cut
+/// short can be told apart from a complete one.
+pub struct DeadlineReader {
+    reader: Box<dyn SourceReader>,
+    deadline: Option<Instant>,
+    timed_out: AtomicBool,
+}
+
+impl DeadlineReader {
+    pub fn new(reader: Box<dyn SourceReader>, deadline: Option<Instant>) -> DeadlineReader {
+        DeadlineReader {
+            reader,
+            deadline,
+            timed_out: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether any of the reads timed out
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+}
+
+impl SourceReader for DeadlineReader {
+    fn read_dir(&self, package_dir: &Path, path: &Path) -> io::Result<Vec<SourceEntry>> {
+        let result = match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "The deadline for scanning has passed",
+            )),
+            _ => self.reader.read_dir(package_dir, path),
+        };
+        if let Err(e) = &result {
+            if is_timeout(e) {
+                self.timed_out.store(true, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+/// Whether the error of a read is the one of a `DeadlineReader`
+pub fn is_timeout(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|error| error.kind() == io::ErrorKind::TimedOut)
+}
@@ -363,7 +363,7 @@ fn generate_ast(
 
 fn path_to_ast_extension(path: &Path) -> &str {
     let extension = path.extension().unwrap().to_str().unwrap();
-    if helpers::is_interface_ast_file(extension) {
+    if helpers::is_interface_file(extension) {
         ".iast"
     } else {
         ".ast"
@@ -54,6 +54,43 @@ pub fn package_path(root: &str, package_name: &str) -> String {
     format!("{}/node_modules/{}", root, package_name)
 }
 
+/// Whether a `bs-dependencies` entry is a path (like `"../shared"`) instead of a package name.
+/// Scoped package names (`@scope/name`) also contain a slash, but they aren't paths.
+pub fn is_path_dependency(dependency: &str) -> bool {
+    dependency.starts_with('.') || (dependency.contains('/') && !dependency.starts_with('@'))
+}
+
+/// Walks up the directory tree from `path` and returns the first `node_modules/<package_name>`
+/// that exists, the same way node resolves packages. This makes it possible to start from a
+/// package that is installed (flat) inside of a `node_modules` folder.
+pub fn find_package_in_ancestors(path: &Path, package_name: &str) -> Option<PathBuf> {
+    path.ancestors()
+        .map(|ancestor| ancestor.join("node_modules").join(package_name))
+        .find(|candidate| candidate.exists())
+}
+
+/// pnpm installs the real packages in a virtual store, as
+/// `node_modules/.pnpm/<name>@<version>/node_modules/<name>`, next to (symlinks to) their own
+/// dependencies. For a package in the store this returns that inner `node_modules` folder, which is
+/// where its dependencies are resolved from.
+pub fn get_pnpm_store_node_modules(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| {
+            ancestor.file_name().is_some_and(|name| name == "node_modules")
+                && ancestor
+                    .parent()
+                    .and_then(|entry| entry.parent())
+                    .and_then(|store| store.file_name())
+                    .is_some_and(|name| name == ".pnpm")
+        })
+        .map(|node_modules| node_modules.to_path_buf())
+}
+
+pub fn is_inside_node_modules(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str() == "node_modules")
+}
+
 pub fn get_abs_path(path: &str) -> String {
     let abs_path_buf = PathBuf::from(path);
 
@@ -85,11 +122,13 @@ pub fn change_extension(path: &str, new_extension: &str) -> String {
 }
 
 /// Capitalizes the first character in s.
+// like the compiler (`String.capitalize_ascii`), this only uppercases the first character, the
+// rest of the file stem is kept as is
 fn capitalize(s: &str) -> String {
     let mut c = s.chars();
     match c.next() {
         None => String::new(),
-        Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+        Some(f) => f.to_ascii_uppercase().to_string() + c.as_str(),
     }
 }
 
@@ -109,10 +148,41 @@ pub fn module_name_with_namespace(module_name: &str, namespace: &packages::Names
     capitalize(&add_suffix(module_name, namespace))
 }
 
+/// The language of a source file, which decides how the module name is derived from its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceLanguage {
+    OCaml,
+    ReScript,
+}
+
+impl SourceLanguage {
+    /// `.ml` and `.mli` files are OCaml, everything else (`.res`, `.resi` and Reason) follows
+    /// the ReScript rules
+    pub fn of_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("ml" | "mli") => SourceLanguage::OCaml,
+            _ => SourceLanguage::ReScript,
+        }
+    }
+
+    /// The (uncapitalized) module name of a file with this basename (without its extension)
+    pub fn module_basename(&self, basename: &str) -> String {
+        match self {
+            // like the OCaml compiler (`Compenv.module_of_filename`), everything from the first
+            // dot is left out, so `foo.test.ml` is the module `Foo`
+            SourceLanguage::OCaml => basename.split('.').next().unwrap_or(basename).to_string(),
+            SourceLanguage::ReScript => basename.to_string(),
+        }
+    }
+}
+
 // this doesn't capitalize the module name! if the rescript name of the file is "foo.res" the
 // compiler assets are foo-Namespace.cmt and foo-Namespace.cmj, but the module name is Foo
 pub fn file_path_to_compiler_asset_basename(path: &str, namespace: &packages::Namespace) -> String {
-    let base = get_basename(path);
+    let base = SourceLanguage::of_path(path).module_basename(&get_basename(path));
     add_suffix(&base, namespace)
 }
 
@@ -136,14 +206,59 @@ pub fn create_build_path(build_path: &str) {
         .unwrap();
 }
 
-pub fn get_bsc(root_path: &str, workspace_root: Option<String>) -> String {
-    let subfolder = match (std::env::consts::OS, std::env::consts::ARCH) {
+/// The folder in the rescript package that holds the binaries for this platform
+pub fn get_bin_subfolder() -> &'static str {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
         ("macos", "aarch64") => "darwinarm64",
         ("macos", _) => "darwin",
         ("linux", _) => "linux",
         ("windows", _) => "win32",
         _ => panic!("Unsupported architecture"),
-    };
+    }
+}
+
+/// Finds `bsc.exe` in the nearest `rescript` (or legacy `bs-platform`) package in the
+/// `node_modules` of `path` or one of its ancestors.
+pub fn find_bsc(path: &Path) -> Option<PathBuf> {
+    let subfolder = get_bin_subfolder();
+    path.ancestors()
+        .flat_map(|ancestor| {
+            ["rescript", "bs-platform"].map(|package_name| {
+                ancestor
+                    .join("node_modules")
+                    .join(package_name)
+                    .join(subfolder)
+                    .join("bsc.exe")
+            })
+        })
+        .find(|candidate| candidate.exists())
+        .and_then(|candidate| candidate.canonicalize().ok())
+}
+
+/// The version of the nearest `rescript` package in the `node_modules` of `path` or one of its
+/// ancestors, from its package.json. Unlike `get_rescript_version` this doesn't run the compiler,
+/// so it also works when the compiler is not installed for this platform.
+pub fn find_rescript_version(path: &Path) -> Option<String> {
+    path.ancestors()
+        .map(|ancestor| {
+            ancestor
+                .join("node_modules")
+                .join("rescript")
+                .join("package.json")
+        })
+        .find(|candidate| candidate.exists())
+        .and_then(|package_json| fs::read_to_string(package_json).ok())
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|package_json| {
+            package_json
+                .get("version")
+                .and_then(|version| version.as_str())
+                .map(|version| version.to_string())
+        })
+}
+
+pub fn get_bsc(root_path: &str, workspace_root: Option<String>) -> String {
+    let subfolder = get_bin_subfolder();
 
     match (
         PathBuf::from(format!(
@@ -331,3 +446,41 @@ pub fn read_file(path: &Path) -> Result<String, std::io::Error> {
     file.read_to_string(&mut contents)?;
     Ok(contents)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{file_path_to_module_name, SourceLanguage};
+    use crate::build::packages::Namespace;
+
+    #[test]
+    fn should_only_uppercase_the_first_character_of_the_module_name() {
+        let module_name = |path: &str| file_path_to_module_name(path, &Namespace::NoNamespace);
+
+        assert_eq!(module_name("src/my_module.res"), "My_module");
+        assert_eq!(module_name("src/fooBar.res"), "FooBar");
+        assert_eq!(module_name("src/FooBar.res"), "FooBar");
+        assert_eq!(module_name("src/école.res"), "école");
+        assert_eq!(
+            file_path_to_module_name("src/my_module.resi", &Namespace::Namespace("App".to_string())),
+            "My_module-App"
+        );
+    }
+
+    #[test]
+    fn should_derive_module_names_by_the_rules_of_the_source_language() {
+        let module_name = |path: &str| file_path_to_module_name(path, &Namespace::NoNamespace);
+
+        assert_eq!(module_name("src/my_module.ml"), module_name("src/my_module.res"));
+        assert_eq!(module_name("src/FooBar.mli"), module_name("src/FooBar.resi"));
+        assert_eq!(module_name("src/fooBar.ml"), "FooBar");
+        // OCaml stops at the first dot, ReScript only leaves out the extension
+        assert_eq!(module_name("src/foo.test.ml"), "Foo");
+        assert_eq!(module_name("src/foo.test.mli"), "Foo");
+        assert_eq!(module_name("src/foo.test.res"), "Foo.test");
+        assert_eq!(
+            SourceLanguage::of_path("src/foo.test.res"),
+            SourceLanguage::ReScript
+        );
+        assert_eq!(SourceLanguage::of_path("src/foo.re"), SourceLanguage::ReScript);
+    }
+}
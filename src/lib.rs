@@ -5,4 +5,6 @@ pub mod helpers;
 pub mod lock;
 pub mod queue;
 pub mod sourcedirs;
+#[cfg(test)]
+mod testing;
 pub mod watcher;
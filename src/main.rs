@@ -1,5 +1,6 @@
 use clap::{Parser, ValueEnum};
 use regex::Regex;
+use std::time::Duration;
 
 use rewatch::{build, cmd, lock, watcher};
 
@@ -57,6 +58,17 @@ struct Args {
     /// A custom path to bsc
     #[arg(long)]
     bsc_path: Option<String>,
+
+    /// The time (in milliseconds) the watcher waits for file system events to settle, before it
+    /// starts compiling. Events on the same file within this window are collapsed into one.
+    /// Defaults to 100
+    #[arg(long)]
+    watch_delay: Option<u64>,
+
+    /// Only watch the package with this name and its dependencies, instead of all packages. For
+    /// instance when working on one app in a big monorepo
+    #[arg(long)]
+    package: Option<String>,
 }
 
 fn main() {
@@ -108,12 +120,28 @@ fn main() {
                 };
             }
             Command::Watch => {
-                watcher::start(
-                    &filter,
-                    &folder,
-                    args.after_build,
-                    args.create_sourcedirs.unwrap_or(false),
-                );
+                let create_sourcedirs = args.create_sourcedirs.unwrap_or(false);
+                let coalesce_window = args
+                    .watch_delay
+                    .map(Duration::from_millis)
+                    .unwrap_or(watcher::DEFAULT_COALESCE_WINDOW);
+                match args.package {
+                    Some(package) => watcher::watch_package(
+                        &filter,
+                        &folder,
+                        &package,
+                        args.after_build,
+                        create_sourcedirs,
+                        coalesce_window,
+                    ),
+                    None => watcher::start(
+                        &filter,
+                        &folder,
+                        args.after_build,
+                        create_sourcedirs,
+                        coalesce_window,
+                    ),
+                }
             }
         },
     }
@@ -32,7 +32,11 @@ fn package_to_dirs<'a>(package: &'a Package, root_package_path: &String) -> AHas
         .as_ref()
         .unwrap_or(&AHashSet::new())
         .iter()
-        .filter_map(|path| path.to_str().map(|path| format!("{relative_path}/{path}")))
+        .filter_map(|path| match path.is_absolute() {
+            // a source dir outside of the package
+            true => path.to_str().map(|path| path.to_string()),
+            false => path.to_str().map(|path| format!("{relative_path}/{path}")),
+        })
         .collect::<AHashSet<String>>()
 }
 
@@ -0,0 +1,85 @@
+//! Helpers for tests that need a real package tree on disk. A `Fixture` is a scratch directory in
+//! the system temp folder that is removed again when it goes out of scope.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FIXTURE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "rewatch-{}-{}-{}",
+            name,
+            std::process::id(),
+            FIXTURE_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("Could not create fixture directory");
+        Self {
+            root: root
+                .canonicalize()
+                .expect("Could not canonicalize fixture directory"),
+        }
+    }
+
+    /// Absolute path (as a string) of a path relative to the fixture root
+    pub fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_string_lossy().to_string()
+    }
+
+    /// Writes a file relative to the fixture root, creating the parent folders
+    pub fn write(&self, relative: &str, contents: &str) -> &Self {
+        let path = self.root.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("Could not create fixture folder");
+        }
+        fs::write(path, contents).expect("Could not write fixture file");
+        self
+    }
+
+    pub fn mkdir(&self, relative: &str) -> &Self {
+        fs::create_dir_all(self.root.join(relative)).expect("Could not create fixture folder");
+        self
+    }
+
+    /// Creates a symlink at `link` pointing to `target`, both relative to the fixture root
+    #[cfg(unix)]
+    pub fn symlink(&self, target: &str, link: &str) -> &Self {
+        let link = self.root.join(link);
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent).expect("Could not create fixture folder");
+        }
+        std::os::unix::fs::symlink(self.root.join(target), link).expect("Could not create symlink");
+        self
+    }
+
+    /// Writes a `bsconfig.json` for a package in `dir` with the given name, `src` as its only source
+    /// folder, and the given `bs-dependencies`.
+    pub fn package(&self, dir: &str, name: &str, bs_dependencies: &[&str]) -> &Self {
+        let deps = bs_dependencies
+            .iter()
+            .map(|dep| format!("\"{}\"", dep))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.write(
+            &Path::new(dir).join("bsconfig.json").to_string_lossy(),
+            &format!(
+                r#"{{ "name": "{}", "sources": "src", "bs-dependencies": [{}] }}"#,
+                name, deps
+            ),
+        )
+        .mkdir(&Path::new(dir).join("src").to_string_lossy())
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
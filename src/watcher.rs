@@ -1,15 +1,17 @@
 use crate::build;
-use crate::build::build_types::SourceType;
+use crate::build::build_types::{BuildState, SourceType};
 use crate::build::clean;
+use crate::build::packages::{self, WatchPath};
 use crate::cmd;
 use crate::helpers;
 use crate::helpers::emojis::*;
 use crate::queue::FifoQueue;
 use crate::queue::*;
+use ahash::{AHashMap, AHashSet};
 use futures_timer::Delay;
 use notify::event::ModifyKind;
 use notify::{Config, Error, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
@@ -31,6 +33,13 @@ fn is_rescript_file(path_buf: &Path) -> bool {
     }
 }
 
+fn is_config_file(path_buf: &Path) -> bool {
+    path_buf
+        .file_name()
+        .map(|name| name == "bsconfig.json")
+        .unwrap_or(false)
+}
+
 fn is_in_build_path(path_buf: &Path) -> bool {
     path_buf
         .to_str()
@@ -46,14 +55,242 @@ fn matches_filter(path_buf: &Path, filter: &Option<regex::Regex>) -> bool {
     filter.as_ref().map(|re| !re.is_match(&name)).unwrap_or(true)
 }
 
+/// The default time the watcher waits for file system events to settle before it starts a build
+pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Collapses the events that arrived within one window into (at most) one event per path, so a
+/// flood of events (like a branch switch) doesn't trigger the same work over and over. The latest
+/// event for a path wins, except when the path was created within the window: then it stays a
+/// create, or, when it was also removed again, it is dropped altogether.
+fn coalesce_events(events: Vec<Event>) -> Vec<Event> {
+    let mut order: Vec<PathBuf> = vec![];
+    let mut by_path: AHashMap<PathBuf, (EventKind, EventKind)> = AHashMap::new();
+    for event in events {
+        for path in event.paths {
+            match by_path.get_mut(&path) {
+                Some((_first, last)) => *last = event.kind,
+                None => {
+                    order.push(path.to_owned());
+                    by_path.insert(path, (event.kind, event.kind));
+                }
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|path| {
+            let kind = match by_path[&path] {
+                (EventKind::Create(_), EventKind::Remove(_)) => return None,
+                (first @ EventKind::Create(_), _) => first,
+                (_, last) => last,
+            };
+            Some(Event::new(kind).add_path(path))
+        })
+        .collect()
+}
+
+/// The paths of `package_name` and its dependencies that need to be watched, when only that
+/// package is watched
+fn get_package_scope(
+    build_state: &build::build_types::BuildState,
+    package_name: &str,
+) -> AHashSet<WatchPath> {
+    let in_scope = packages::transitive_dependencies(&build_state.packages, package_name);
+    let packages = build_state
+        .packages
+        .iter()
+        .filter(|(name, _package)| in_scope.contains(*name))
+        .map(|(name, package)| (name.to_owned(), package.to_owned()))
+        .collect();
+    packages::watch_paths(&packages)
+}
+
+/// The paths that are registered with notify. After a full build these are brought in line with
+/// the (new) tree: the paths that are new are watched, and the ones that are gone are unwatched.
+#[derive(Debug, Default)]
+struct WatchSet {
+    paths: AHashSet<WatchPath>,
+}
+
+impl WatchSet {
+    fn update(&mut self, watcher: &mut dyn Watcher, paths: AHashSet<WatchPath>) {
+        self.paths.difference(&paths).for_each(|watch_path| {
+            // it may be gone already
+            let _ = watcher.unwatch(Path::new(&watch_path.path));
+        });
+        paths.difference(&self.paths).for_each(|watch_path| {
+            let recursive_mode = match watch_path.recursive {
+                true => RecursiveMode::Recursive,
+                false => RecursiveMode::NonRecursive,
+            };
+            // a path that doesn't exist (yet) is picked up by the next full build
+            let _ = watcher.watch(Path::new(&watch_path.path), recursive_mode);
+        });
+        self.paths = paths;
+    }
+}
+
+/// Whether a changed path is covered by the watch paths
+fn is_watched(path: &Path, watch_paths: &AHashSet<WatchPath>) -> bool {
+    watch_paths.iter().any(|watch_path| {
+        let watch_path_buf = Path::new(&watch_path.path);
+        path == watch_path_buf
+            || if watch_path.recursive {
+                path.starts_with(watch_path_buf)
+            } else {
+                path.parent() == Some(watch_path_buf)
+            }
+    })
+}
+
+/// The paths to register with notify: those of `package` and its dependencies, or when there is
+/// no package, those of all packages
+fn get_watch_paths(build_state: &BuildState, package: Option<&str>) -> AHashSet<WatchPath> {
+    match package {
+        Some(package) => get_package_scope(build_state, package),
+        None => packages::watch_paths(&build_state.packages),
+    }
+}
+
+/// Marks the source files of the (coalesced) events dirty, and returns the kind of compile that
+/// is needed after them. Only the events on the `watch_paths` count. The subfolders of a
+/// recursive source are watched one by one (see `packages::watch_paths`), so a folder that is
+/// created in a watched folder needs a full compile, which also starts watching it. A change to a
+/// config needs a full compile too.
+fn process_events(
+    events: Vec<Event>,
+    build_state: &mut BuildState,
+    filter: &Option<regex::Regex>,
+    watch_paths: &AHashSet<WatchPath>,
+    mut needs_compile_type: CompileType,
+) -> CompileType {
+    for event in coalesce_events(events) {
+        if matches!(event.kind, EventKind::Create(_))
+            && event
+                .paths
+                .iter()
+                .any(|path| path.is_dir() && is_watched(path, watch_paths))
+        {
+            needs_compile_type = CompileType::Full;
+        }
+        if !matches!(
+            event.kind,
+            EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_))
+        ) && event
+            .paths
+            .iter()
+            .any(|path| is_config_file(path) && is_watched(path, watch_paths))
+        {
+            needs_compile_type = CompileType::Full;
+        }
+        let paths = event
+            .paths
+            .iter()
+            .filter(|path| is_rescript_file(path))
+            .filter(|path| !is_in_build_path(path))
+            .filter(|path| matches_filter(path, filter))
+            .filter(|path| is_watched(path, watch_paths));
+        for path in paths {
+            let path_buf = path.to_path_buf();
+
+            match (needs_compile_type, event.kind) {
+                (
+                    CompileType::Incremental | CompileType::None,
+                    // when we have a name change, create or remove event we need to do a full compile
+                    EventKind::Remove(_)
+                    | EventKind::Any
+                    | EventKind::Create(_)
+                    | EventKind::Modify(ModifyKind::Name(_)),
+                ) => {
+                    // if we are going to do a full compile, we don't need to bother marking
+                    // files dirty because we do a full scan anyway
+                    needs_compile_type = CompileType::Full;
+                }
+
+                (
+                    CompileType::None | CompileType::Incremental,
+                    // when we have a data change event, we can do an incremental compile
+                    EventKind::Modify(ModifyKind::Data(_)),
+                ) => {
+                    // if we are going to compile incrementally, we need to mark the exact files
+                    // dirty
+                    if let Ok(canonicalized_path_buf) = path_buf.canonicalize() {
+                        for module in build_state.modules.values_mut() {
+                            match module.source_type {
+                                SourceType::SourceFile(ref mut source_file) => {
+                                    // mark the implementation file dirty
+                                    let package = build_state
+                                        .packages
+                                        .get(&module.package_name)
+                                        .expect("Package not found");
+                                    let canonicalized_implementation_file =
+                                        std::path::PathBuf::from(package.path.to_string())
+                                            .join(&source_file.implementation.path);
+                                    if canonicalized_path_buf == canonicalized_implementation_file {
+                                        if let Ok(modified) =
+                                            canonicalized_path_buf.metadata().and_then(|x| x.modified())
+                                        {
+                                            source_file.implementation.last_modified = modified;
+                                        };
+                                        source_file.implementation.parse_dirty = true;
+                                        break;
+                                    }
+
+                                    // mark the interface file dirty
+                                    if let Some(ref mut interface) = source_file.interface {
+                                        let canonicalized_interface_file =
+                                            std::path::PathBuf::from(package.path.to_string())
+                                                .join(&interface.path);
+                                        if canonicalized_path_buf == canonicalized_interface_file {
+                                            if let Ok(modified) =
+                                                canonicalized_path_buf.metadata().and_then(|x| x.modified())
+                                            {
+                                                interface.last_modified = modified;
+                                            }
+                                            interface.parse_dirty = true;
+                                            break;
+                                        }
+                                    }
+                                }
+                                SourceType::MlMap(_) => (),
+                            }
+                        }
+                        needs_compile_type = CompileType::Incremental;
+                    }
+                }
+
+                (
+                    CompileType::None | CompileType::Incremental,
+                    // these are not relevant events for compilation
+                    EventKind::Access(_)
+                    | EventKind::Other
+                    | EventKind::Modify(ModifyKind::Any)
+                    | EventKind::Modify(ModifyKind::Metadata(_))
+                    | EventKind::Modify(ModifyKind::Other),
+                ) => (),
+                // if we already need a full compile, we don't need to check for other events
+                (CompileType::Full, _) => (),
+            }
+        }
+    }
+    needs_compile_type
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn async_watch(
     q: Arc<FifoQueue<Result<Event, Error>>>,
     path: &str,
     filter: &Option<regex::Regex>,
     after_build: Option<String>,
     create_sourcedirs: bool,
+    coalesce_window: Duration,
+    watcher: &mut dyn Watcher,
+    package: Option<&str>,
 ) -> notify::Result<()> {
     let mut build_state = build::initialize_build(None, filter, path, None).expect("Can't initialize build");
+    let mut watch_set = WatchSet::default();
+    watch_set.update(watcher, get_watch_paths(&build_state, package));
     let mut needs_compile_type = CompileType::Incremental;
     // create a mutex to capture if ctrl-c was pressed
     let ctrlc_pressed = Arc::new(Mutex::new(false));
@@ -77,7 +314,7 @@ async fn async_watch(
         let mut events: Vec<Event> = vec![];
         if !q.is_empty() {
             // Wait for events to settle
-            Delay::new(Duration::from_millis(50)).await;
+            Delay::new(coalesce_window).await;
         }
         while !q.is_empty() {
             if let Ok(event) = q.pop() {
@@ -85,97 +322,13 @@ async fn async_watch(
             }
         }
 
-        for event in events {
-            let paths = event
-                .paths
-                .iter()
-                .filter(|path| is_rescript_file(path))
-                .filter(|path| !is_in_build_path(path))
-                .filter(|path| matches_filter(path, filter));
-            for path in paths {
-                let path_buf = path.to_path_buf();
-
-                match (needs_compile_type, event.kind) {
-                    (
-                        CompileType::Incremental | CompileType::None,
-                        // when we have a name change, create or remove event we need to do a full compile
-                        EventKind::Remove(_)
-                        | EventKind::Any
-                        | EventKind::Create(_)
-                        | EventKind::Modify(ModifyKind::Name(_)),
-                    ) => {
-                        // if we are going to do a full compile, we don't need to bother marking
-                        // files dirty because we do a full scan anyway
-                        needs_compile_type = CompileType::Full;
-                    }
-
-                    (
-                        CompileType::None | CompileType::Incremental,
-                        // when we have a data change event, we can do an incremental compile
-                        EventKind::Modify(ModifyKind::Data(_)),
-                    ) => {
-                        // if we are going to compile incrementally, we need to mark the exact files
-                        // dirty
-                        if let Ok(canonicalized_path_buf) = path_buf.canonicalize() {
-                            for module in build_state.modules.values_mut() {
-                                match module.source_type {
-                                    SourceType::SourceFile(ref mut source_file) => {
-                                        // mark the implementation file dirty
-                                        let package = build_state
-                                            .packages
-                                            .get(&module.package_name)
-                                            .expect("Package not found");
-                                        let canonicalized_implementation_file =
-                                            std::path::PathBuf::from(package.path.to_string())
-                                                .join(&source_file.implementation.path);
-                                        if canonicalized_path_buf == canonicalized_implementation_file {
-                                            if let Ok(modified) =
-                                                canonicalized_path_buf.metadata().and_then(|x| x.modified())
-                                            {
-                                                source_file.implementation.last_modified = modified;
-                                            };
-                                            source_file.implementation.parse_dirty = true;
-                                            break;
-                                        }
-
-                                        // mark the interface file dirty
-                                        if let Some(ref mut interface) = source_file.interface {
-                                            let canonicalized_interface_file =
-                                                std::path::PathBuf::from(package.path.to_string())
-                                                    .join(&interface.path);
-                                            if canonicalized_path_buf == canonicalized_interface_file {
-                                                if let Ok(modified) = canonicalized_path_buf
-                                                    .metadata()
-                                                    .and_then(|x| x.modified())
-                                                {
-                                                    interface.last_modified = modified;
-                                                }
-                                                interface.parse_dirty = true;
-                                                break;
-                                            }
-                                        }
-                                    }
-                                    SourceType::MlMap(_) => (),
-                                }
-                            }
-                            needs_compile_type = CompileType::Incremental;
-                        }
-                    }
-
-                    (
-                        CompileType::None | CompileType::Incremental,
-                        // these are not relevant events for compilation
-                        EventKind::Access(_)
-                        | EventKind::Other
-                        | EventKind::Modify(ModifyKind::Any)
-                        | EventKind::Modify(ModifyKind::Metadata(_))
-                        | EventKind::Modify(ModifyKind::Other),
-                    ) => (),
-                    // if we already need a full compile, we don't need to check for other events
-                    (CompileType::Full, _) => (),
-                }
-            }
-        }
+        needs_compile_type = process_events(
+            events,
+            &mut build_state,
+            filter,
+            &watch_set.paths,
+            needs_compile_type,
+        );
         match needs_compile_type {
             CompileType::Incremental => {
                 let timing_total = Instant::now();
@@ -207,6 +360,7 @@ async fn async_watch(
                 let timing_total = Instant::now();
                 build_state =
                     build::initialize_build(None, filter, path, None).expect("Can't initialize build");
+                watch_set.update(watcher, get_watch_paths(&build_state, package));
                 let _ =
                     build::incremental_build(&mut build_state, None, initial_build, false, create_sourcedirs);
                 if let Some(a) = after_build.clone() {
@@ -239,20 +393,280 @@ pub fn start(
     folder: &str,
     after_build: Option<String>,
     create_sourcedirs: bool,
+    coalesce_window: Duration,
+) {
+    watch(
+        filter,
+        folder,
+        after_build,
+        create_sourcedirs,
+        coalesce_window,
+        None,
+    )
+}
+
+/// Like `start`, but only the source folders (and configs) of `package_name` and the packages it
+/// (transitively) depends on are watched, for working on one app in a big repository. Changes to
+/// the other packages don't trigger a build.
+pub fn watch_package(
+    filter: &Option<regex::Regex>,
+    folder: &str,
+    package_name: &str,
+    after_build: Option<String>,
+    create_sourcedirs: bool,
+    coalesce_window: Duration,
+) {
+    watch(
+        filter,
+        folder,
+        after_build,
+        create_sourcedirs,
+        coalesce_window,
+        Some(package_name),
+    )
+}
+
+fn watch(
+    filter: &Option<regex::Regex>,
+    folder: &str,
+    after_build: Option<String>,
+    create_sourcedirs: bool,
+    coalesce_window: Duration,
+    package: Option<&str>,
 ) {
     futures::executor::block_on(async {
         let queue = Arc::new(FifoQueue::<Result<Event, Error>>::new());
         let producer = queue.clone();
         let consumer = queue.clone();
 
+        // the paths are registered once the tree is built
         let mut watcher = RecommendedWatcher::new(move |res| producer.push(res), Config::default())
             .expect("Could not create watcher");
-        watcher
-            .watch(folder.as_ref(), RecursiveMode::Recursive)
-            .expect("Could not start watcher");
 
-        if let Err(e) = async_watch(consumer, folder, filter, after_build, create_sourcedirs).await {
+        if let Err(e) = async_watch(
+            consumer,
+            folder,
+            filter,
+            after_build,
+            create_sourcedirs,
+            coalesce_window,
+            &mut watcher,
+            package,
+        )
+        .await
+        {
             println!("error: {:?}", e)
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use crate::build::build_types::BuildState;
+    use crate::build::packages;
+    use crate::testing::Fixture;
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
+    use notify::{Event, EventKind, RecursiveMode};
+    use std::path::{Path, PathBuf};
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn should_drop_files_that_were_created_and_removed_within_the_window() {
+        let events = vec![
+            event(EventKind::Create(CreateKind::File), "/src/Temp.res"),
+            event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                "/src/Temp.res",
+            ),
+            event(EventKind::Remove(RemoveKind::File), "/src/Temp.res"),
+        ];
+
+        assert!(super::coalesce_events(events).is_empty());
+    }
+
+    #[test]
+    fn should_collapse_events_on_the_same_path() {
+        let events = vec![
+            event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), "/src/A.res"),
+            event(EventKind::Create(CreateKind::File), "/src/B.res"),
+            event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), "/src/B.res"),
+            event(EventKind::Remove(RemoveKind::File), "/src/A.res"),
+        ];
+
+        let coalesced = super::coalesce_events(events);
+
+        assert_eq!(
+            coalesced,
+            vec![
+                event(EventKind::Remove(RemoveKind::File), "/src/A.res"),
+                event(EventKind::Create(CreateKind::File), "/src/B.res"),
+            ]
+        );
+    }
+
+    // records the paths it is asked to watch, instead of watching them
+    #[derive(Default)]
+    struct RecordingWatcher {
+        watched: Vec<(PathBuf, RecursiveMode)>,
+    }
+
+    impl notify::Watcher for RecordingWatcher {
+        fn new<F: notify::EventHandler>(_event_handler: F, _config: notify::Config) -> notify::Result<Self> {
+            Ok(Self::default())
+        }
+
+        fn watch(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+            self.watched.push((path.to_path_buf(), recursive_mode));
+            Ok(())
+        }
+
+        fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+            self.watched.retain(|(watched, _recursive_mode)| watched != path);
+            Ok(())
+        }
+
+        fn kind() -> notify::WatcherKind {
+            notify::WatcherKind::NullWatcher
+        }
+    }
+
+    fn build_state(fixture: &Fixture) -> BuildState {
+        fixture
+            .package("", "root", &["app", "other"])
+            .package("node_modules/app", "app", &["shared"])
+            .package("node_modules/shared", "shared", &[])
+            .package("node_modules/other", "other", &[])
+            .write("node_modules/app/src/App.res", "let app = 1")
+            .write("node_modules/shared/src/Shared.res", "let shared = 1")
+            .write("node_modules/other/src/Other.res", "let other = 1");
+        let packages = packages::make(&None, &fixture.path(""), &None);
+        let mut build_state = BuildState::new(
+            fixture.path(""),
+            "root".to_string(),
+            packages,
+            None,
+            "11.0.0".to_string(),
+            "bsc".to_string(),
+        );
+        packages::parse_packages(&mut build_state).unwrap();
+        build_state
+    }
+
+    #[test]
+    fn should_only_register_the_package_and_its_dependencies() {
+        let fixture = Fixture::new("watch-package-paths");
+        let build_state = build_state(&fixture);
+        let mut watcher = RecordingWatcher::default();
+        let mut watch_set = super::WatchSet::default();
+
+        watch_set.update(&mut watcher, super::get_package_scope(&build_state, "app"));
+
+        let mut watched = watcher
+            .watched
+            .iter()
+            .map(|(path, recursive_mode)| {
+                assert_eq!(*recursive_mode, RecursiveMode::NonRecursive);
+                path.strip_prefix(fixture.path("")).unwrap().to_path_buf()
+            })
+            .collect::<Vec<PathBuf>>();
+        watched.sort();
+        assert_eq!(
+            watched,
+            vec![
+                PathBuf::from("node_modules/app/bsconfig.json"),
+                PathBuf::from("node_modules/app/src"),
+                PathBuf::from("node_modules/shared/bsconfig.json"),
+                PathBuf::from("node_modules/shared/src"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_update_for_an_event_outside_of_the_package() {
+        let fixture = Fixture::new("watch-package-events");
+        let mut build_state = build_state(&fixture);
+        let scope = super::get_package_scope(&build_state, "app");
+        let modify = |path: &str| {
+            vec![event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                &fixture.path(path),
+            )]
+        };
+
+        let compile_type = super::process_events(
+            modify("node_modules/other/src/Other.res"),
+            &mut build_state,
+            &None,
+            &scope,
+            super::CompileType::None,
+        );
+        assert_eq!(compile_type, super::CompileType::None);
+
+        let compile_type = super::process_events(
+            modify("node_modules/app/src/App.res"),
+            &mut build_state,
+            &None,
+            &scope,
+            super::CompileType::None,
+        );
+        assert_eq!(compile_type, super::CompileType::Incremental);
+    }
+
+    #[test]
+    fn should_only_cover_the_direct_children_of_a_non_recursive_path() {
+        let watch_paths = [super::WatchPath {
+            path: "/app/src".to_string(),
+            recursive: false,
+        }]
+        .into_iter()
+        .collect();
+
+        assert!(super::is_watched(Path::new("/app/src/App.res"), &watch_paths));
+        assert!(!super::is_watched(
+            Path::new("/app/src/nested/Nested.res"),
+            &watch_paths
+        ));
+    }
+
+    #[test]
+    fn should_compile_everything_when_a_folder_is_created_in_a_watched_folder() {
+        let fixture = Fixture::new("watch-new-folder");
+        let mut build_state = build_state(&fixture);
+        let watch_paths = packages::watch_paths(&build_state.packages);
+        fixture.mkdir("node_modules/app/src/new");
+
+        let compile_type = super::process_events(
+            vec![event(
+                EventKind::Create(CreateKind::Folder),
+                &fixture.path("node_modules/app/src/new"),
+            )],
+            &mut build_state,
+            &None,
+            &watch_paths,
+            super::CompileType::None,
+        );
+        assert_eq!(compile_type, super::CompileType::Full);
+    }
+
+    #[test]
+    fn should_compile_everything_when_a_config_changes() {
+        let fixture = Fixture::new("watch-config-change");
+        let mut build_state = build_state(&fixture);
+        let watch_paths = packages::watch_paths(&build_state.packages);
+
+        let compile_type = super::process_events(
+            vec![event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+                &fixture.path("node_modules/app/bsconfig.json"),
+            )],
+            &mut build_state,
+            &None,
+            &watch_paths,
+            super::CompileType::None,
+        );
+        assert_eq!(compile_type, super::CompileType::Full);
+    }
+}